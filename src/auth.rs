@@ -1,3 +1,5 @@
+use std::{collections::HashSet, convert::Infallible, fmt, str::FromStr};
+
 pub trait User {
     fn name(&self) -> String;
     fn password(&self) -> String;
@@ -14,6 +16,14 @@ pub trait User {
         self.privileges().iter().any(|p| p == privilege)
     }
 
+    /// The user's privileges, with Luanti's built-in ones recognized
+    fn privilege_set(&self) -> Vec<Privilege> {
+        self.privileges()
+            .iter()
+            .map(|privilege| Privilege::from_str(privilege).unwrap())
+            .collect()
+    }
+
     fn check_password(&self, password: &str) -> bool;
 }
 
@@ -27,3 +37,191 @@ pub trait AuthBackend<U: User> {
         self.users_mut().iter_mut().find(|user| user.name() == id)
     }
 }
+
+/// The differences between two auth databases, see `auth_diff`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuthChangeset {
+    /// Names of the users only the first database has
+    pub only_a: Vec<String>,
+    /// Names of the users only the second database has
+    pub only_b: Vec<String>,
+    /// Users both databases have, but with a different password or privileges
+    pub changed: Vec<UserChange>,
+}
+
+/// How a user differs between two auth databases
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserChange {
+    pub name: String,
+    /// True if the password hashes differ
+    pub password_changed: bool,
+    /// Privileges the user has only in the second database
+    pub privileges_added: Vec<Privilege>,
+    /// Privileges the user has only in the first database
+    pub privileges_removed: Vec<Privilege>,
+}
+
+/// Compares the users of two auth databases
+///
+/// Users are matched by name. Privileges are compared as sets, so their order doesn't matter.
+///
+/// # Arguments
+/// - `a` - The database to compare from, such as the older copy
+/// - `b` - The database to compare to
+///
+/// # Returns
+/// - The users only in `a`, only in `b`, and those that changed, each sorted by name
+pub fn auth_diff<U: User>(a: &impl AuthBackend<U>, b: &impl AuthBackend<U>) -> AuthChangeset {
+    let mut changeset = AuthChangeset::default();
+    for user in a.users() {
+        let Some(other) = b.get_user(user.name()) else {
+            changeset.only_a.push(user.name());
+            continue;
+        };
+        let before = user.privilege_set();
+        let after = other.privilege_set();
+        let missing_from = |privileges: &[Privilege], from: &[Privilege]| {
+            let from: HashSet<&Privilege> = from.iter().collect();
+            let mut missing: Vec<Privilege> = Vec::new();
+            for privilege in privileges {
+                if !from.contains(privilege) && !missing.contains(privilege) {
+                    missing.push(privilege.clone());
+                }
+            }
+            missing
+        };
+        let change = UserChange {
+            name: user.name(),
+            password_changed: user.password() != other.password(),
+            privileges_added: missing_from(&after, &before),
+            privileges_removed: missing_from(&before, &after),
+        };
+        if change.password_changed
+            || !change.privileges_added.is_empty()
+            || !change.privileges_removed.is_empty()
+        {
+            changeset.changed.push(change);
+        }
+    }
+    for user in b.users() {
+        if a.get_user(user.name()).is_none() {
+            changeset.only_b.push(user.name());
+        }
+    }
+
+    changeset.only_a.sort();
+    changeset.only_b.sort();
+    changeset.changed.sort_by(|x, y| x.name.cmp(&y.name));
+    changeset
+}
+
+/// A privilege, as granted to users with `/grant`
+///
+/// Covers the privileges built into Luanti; any other, such as those registered by mods, is kept by name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Privilege {
+    Interact,
+    Shout,
+    BasicPrivs,
+    Privs,
+    Teleport,
+    Bring,
+    Settime,
+    Server,
+    ProtectionBypass,
+    Ban,
+    Kick,
+    Give,
+    Password,
+    Fly,
+    Fast,
+    Noclip,
+    Rollback,
+    Debug,
+    BasicDebug,
+    /// A privilege not built into Luanti
+    Other(String),
+}
+
+impl Privilege {
+    /// The name the privilege is granted by
+    pub fn as_str(&self) -> &str {
+        match self {
+            Privilege::Interact => "interact",
+            Privilege::Shout => "shout",
+            Privilege::BasicPrivs => "basic_privs",
+            Privilege::Privs => "privs",
+            Privilege::Teleport => "teleport",
+            Privilege::Bring => "bring",
+            Privilege::Settime => "settime",
+            Privilege::Server => "server",
+            Privilege::ProtectionBypass => "protection_bypass",
+            Privilege::Ban => "ban",
+            Privilege::Kick => "kick",
+            Privilege::Give => "give",
+            Privilege::Password => "password",
+            Privilege::Fly => "fly",
+            Privilege::Fast => "fast",
+            Privilege::Noclip => "noclip",
+            Privilege::Rollback => "rollback",
+            Privilege::Debug => "debug",
+            Privilege::BasicDebug => "basic_debug",
+            Privilege::Other(name) => name,
+        }
+    }
+}
+
+impl FromStr for Privilege {
+    /// Every name is a privilege - unknown ones become `Other`
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "interact" => Privilege::Interact,
+            "shout" => Privilege::Shout,
+            "basic_privs" => Privilege::BasicPrivs,
+            "privs" => Privilege::Privs,
+            "teleport" => Privilege::Teleport,
+            "bring" => Privilege::Bring,
+            "settime" => Privilege::Settime,
+            "server" => Privilege::Server,
+            "protection_bypass" => Privilege::ProtectionBypass,
+            "ban" => Privilege::Ban,
+            "kick" => Privilege::Kick,
+            "give" => Privilege::Give,
+            "password" => Privilege::Password,
+            "fly" => Privilege::Fly,
+            "fast" => Privilege::Fast,
+            "noclip" => Privilege::Noclip,
+            "rollback" => Privilege::Rollback,
+            "debug" => Privilege::Debug,
+            "basic_debug" => Privilege::BasicDebug,
+            name => Privilege::Other(name.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod privilege_tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("interact".parse(), Ok(Privilege::Interact));
+        assert_eq!("basic_privs".parse(), Ok(Privilege::BasicPrivs));
+        assert_eq!(
+            "customprivilege".parse(),
+            Ok(Privilege::Other("customprivilege".to_string()))
+        );
+
+        for name in ["interact", "customprivilege", "protection_bypass", "fly"] {
+            assert_eq!(name.parse::<Privilege>().unwrap().to_string(), name);
+        }
+    }
+}
@@ -1,3 +1,3 @@
 pub mod java;
 pub mod luanti;
-//pub mod memory;
+pub mod memory;
@@ -1,6 +1,10 @@
-mod auth;
+pub mod analysis;
+pub mod auth;
+pub mod edit;
 pub mod file_format;
 pub mod mods;
-mod map;
+pub mod map;
+pub mod map_high;
+pub mod node_def;
 pub mod world;
-mod block_serialization;
\ No newline at end of file
+pub mod block_serialization;
\ No newline at end of file
@@ -0,0 +1,939 @@
+// World-wide scans over a Luanti map
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::BitOr,
+};
+
+use crate::{Area, Coordinate, SpatialCoordinate, WorldError};
+
+use super::{
+    block_serialization::{
+        decode_block_header_at, node_order, read_block, v29::MapBlock29, BlockDeserializeError,
+        NODE_COUNT,
+    },
+    map::{HashedCoordinate, MapReader},
+};
+
+/// Counts how many of each node there are across the whole map
+///
+/// # Arguments
+/// - `reader` - The map to scan
+/// - `skip_all_ignore` - Leave out all-`ignore` placeholder blocks, which aren't really generated
+///
+/// # Returns
+/// - Node name -> count. Content ids missing from a block's mapping are counted as `unknown`.
+pub fn node_histogram<R: MapReader>(
+    reader: &R,
+    skip_all_ignore: bool,
+) -> Result<HashMap<String, u64>, WorldError> {
+    let mut histogram = HashMap::new();
+    for coord in reader.blocks()? {
+        let block = read_block(reader, coord)?;
+        if skip_all_ignore && block.is_all_ignore() {
+            continue;
+        }
+        count_nodes(&block, &mut histogram);
+    }
+    Ok(histogram)
+}
+
+/// Counts how many of each node there are across the whole map, decoding blocks on all cores
+///
+/// Block data is still read on the calling thread, as map connections can't be shared between
+/// threads, so this pays off when decoding rather than reading dominates.
+///
+/// # Arguments
+/// - `reader` - The map to scan
+///
+/// # Returns
+/// - The same as `node_histogram` with `skip_all_ignore` off
+#[cfg(feature = "rayon")]
+pub fn par_node_histogram<R: MapReader>(reader: &R) -> Result<HashMap<String, u64>, WorldError> {
+    use super::block_serialization::decode_block_at;
+    use rayon::prelude::*;
+
+    // Blocks read ahead of decoding - bounds memory use on large maps
+    const BATCH_SIZE: usize = 1024;
+
+    type Histogram = HashMap<String, u64>;
+    let merge = |mut a: Histogram, b: Histogram| -> Result<Histogram, WorldError> {
+        for (name, count) in b {
+            *a.entry(name).or_default() += count;
+        }
+        Ok(a)
+    };
+
+    let mut histogram = HashMap::new();
+    for batch in reader.blocks()?.chunks(BATCH_SIZE) {
+        let data = batch
+            .iter()
+            .map(|coord| Ok((*coord, reader.get_block(*coord)?)))
+            .collect::<Result<Vec<_>, WorldError>>()?;
+        let counts = data
+            .par_iter()
+            .map(|(coord, data)| {
+                let mut counts = HashMap::new();
+                count_nodes(&decode_block_at(*coord, data)?, &mut counts);
+                Ok(counts)
+            })
+            .try_reduce(HashMap::new, merge)?;
+        histogram = merge(histogram, counts)?;
+    }
+    Ok(histogram)
+}
+
+/// Adds the nodes of a block to a histogram of node name -> count
+fn count_nodes(block: &MapBlock29, histogram: &mut HashMap<String, u64>) {
+    let mut counts: HashMap<u16, u64> = HashMap::new();
+    for id in block.content_ids() {
+        *counts.entry(*id).or_default() += 1;
+    }
+    for (id, count) in counts {
+        let name = block.content_name(id).unwrap_or("unknown");
+        *histogram.entry(name.to_string()).or_default() += count;
+    }
+}
+
+/// Counts the nodes in the map that aren't defined, such as those left behind by uninstalled mods
+///
+/// `air` and `ignore` are built into the engine, so they are never reported, whether or not `known`
+/// holds them - a game never registers them itself.
+///
+/// # Arguments
+/// - `reader` - The map to scan
+/// - `known` - Names of the nodes the game defines, as found by
+///   [`scan_node_names`](super::node_def::scan_node_names)
+///
+/// # Returns
+/// - Node name -> count, for each node name not in `known`. Content ids missing from a block's
+///   mapping are counted as `unknown`.
+pub fn unknown_nodes<R: MapReader>(
+    reader: &R,
+    known: &HashSet<String>,
+) -> Result<HashMap<String, u64>, WorldError> {
+    let mut histogram = node_histogram(reader, false)?;
+    histogram.retain(|name, _| !matches!(name.as_str(), "air" | "ignore") && !known.contains(name));
+    Ok(histogram)
+}
+
+/// Estimates how much space the generated blocks of the map take up once decompressed
+///
+/// This is an estimate: a block whose zstd frame records its decompressed size counts with that size,
+/// which is exact. Luanti doesn't record it, so other blocks count with the size of their node data
+/// alone - 4096 nodes of 2 bytes of content id and 1 byte each of param1 and param2 - which makes up
+/// nearly all of a block, but leaves out its mapping, metadata, objects and timers.
+///
+/// # Arguments
+/// - `reader` - The map to scan
+///
+/// # Returns
+/// - The estimated size in bytes
+pub fn estimated_uncompressed_size<R: MapReader>(reader: &R) -> Result<u64, WorldError> {
+    // content_width + params_width
+    const NODE_DATA_SIZE: u64 = NODE_COUNT as u64 * (2 + 2);
+
+    let mut size = 0;
+    for coord in reader.blocks_iter()? {
+        let coord = coord?;
+        let data = reader.get_block(coord)?;
+        if !decode_block_header_at(coord, &data)?.was_generated() {
+            continue;
+        }
+        size += match data.split_first() {
+            Some((29, frame)) => zstd::zstd_safe::get_frame_content_size(frame)
+                .ok()
+                .flatten()
+                .unwrap_or(NODE_DATA_SIZE),
+            _ => NODE_DATA_SIZE,
+        };
+    }
+    Ok(size)
+}
+
+/// Finds the blocks stored under a packed position that no block position packs to
+///
+/// Luanti only writes positions with each component in `-2048..=2047`, which pack to a limited range
+/// of values. Anything else - such as a position written by a tool that packed it wrongly - unpacks
+/// to some in-range position that doesn't pack back to the same value, so Luanti would never find
+/// the block there.
+///
+/// # Arguments
+/// - `reader` - The map to scan
+///
+/// # Returns
+/// - The packed values that are out of range, in the order the map lists them
+pub fn validate_positions<R: MapReader>(reader: &R) -> Result<Vec<i64>, WorldError> {
+    let mut invalid = Vec::new();
+    for coord in reader.blocks_iter()? {
+        let coord = coord?;
+        let (x, y, z) = coord.position();
+        if HashedCoordinate::at(x, y, z) != Ok(coord) {
+            invalid.push(coord.value);
+        }
+    }
+    Ok(invalid)
+}
+
+/// Finds the node-space area covered by the map's blocks
+///
+/// # Arguments
+/// - `reader` - The map to scan
+/// - `skip_all_ignore` - Leave out all-`ignore` placeholder blocks, which aren't really generated
+///
+/// # Returns
+/// - The area spanning every (counted) block, or None if there are none
+pub fn world_bounds<R: MapReader>(
+    reader: &R,
+    skip_all_ignore: bool,
+) -> Result<Option<Area>, WorldError> {
+    let mut bounds: Option<Area> = None;
+    for coord in reader.blocks()? {
+        if skip_all_ignore && read_block(reader, coord)?.is_all_ignore() {
+            continue;
+        }
+        let block = <SpatialCoordinate as Coordinate>::from(coord)
+            .map_err(|_| WorldError::CorruptData(format!("Invalid block position {}", coord)))?;
+        let from = SpatialCoordinate {
+            x: block.x * 16,
+            y: block.y * 16,
+            z: block.z * 16,
+        };
+        let to = from
+            + SpatialCoordinate {
+                x: 15,
+                y: 15,
+                z: 15,
+            };
+        bounds = Some(match bounds {
+            None => Area { from, to },
+            Some(area) => Area {
+                from: SpatialCoordinate {
+                    x: area.from.x.min(from.x),
+                    y: area.from.y.min(from.y),
+                    z: area.from.z.min(from.z),
+                },
+                to: SpatialCoordinate {
+                    x: area.to.x.max(to.x),
+                    y: area.to.y.max(to.y),
+                    z: area.to.z.max(to.z),
+                },
+            },
+        });
+    }
+    Ok(bounds)
+}
+
+/// Finds the existing block closest to a node position
+///
+/// Searches outwards in cubic shells of block positions around the block containing `from`, so the
+/// first shell holding any block wins. Within a shell, the block with the smallest straight-line
+/// distance is picked.
+///
+/// # Arguments
+/// - `reader` - The map to search
+/// - `from` - The node position to search from
+/// - `max_radius` - How many blocks away from `from`'s block to search
+///
+/// # Returns
+/// - The nearest block, or None if there is none within `max_radius`
+pub fn nearest_block<R: MapReader>(
+    reader: &R,
+    from: SpatialCoordinate,
+    max_radius: i16,
+) -> Result<Option<HashedCoordinate>, WorldError> {
+    let center = (
+        i64::from(from.x.div_euclid(16)),
+        i64::from(from.y.div_euclid(16)),
+        i64::from(from.z.div_euclid(16)),
+    );
+    for radius in 0..=i64::from(max_radius.max(0)) {
+        let mut shell = Vec::new();
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                for dz in -radius..=radius {
+                    if dx.abs().max(dy.abs()).max(dz.abs()) != radius {
+                        continue;
+                    }
+                    let position = (
+                        i16::try_from(center.0 + dx),
+                        i16::try_from(center.1 + dy),
+                        i16::try_from(center.2 + dz),
+                    );
+                    // Positions beyond the map limits can't hold a block
+                    if let (Ok(x), Ok(y), Ok(z)) = position {
+                        if let Ok(coord) = HashedCoordinate::at(x, y, z) {
+                            shell.push((dx * dx + dy * dy + dz * dz, coord));
+                        }
+                    }
+                }
+            }
+        }
+
+        let coords: Vec<HashedCoordinate> = shell.iter().map(|(_, coord)| *coord).collect();
+        let existing = reader.blocks_exist(&coords)?;
+        let nearest = shell
+            .into_iter()
+            .filter(|(_, coord)| existing.contains(coord))
+            .min_by_key(|(distance, _)| *distance);
+        if let Some((_, coord)) = nearest {
+            return Ok(Some(coord));
+        }
+    }
+    Ok(None)
+}
+
+/// Loads the existing blocks around a node position, such as those a viewer there could see
+///
+/// # Arguments
+/// - `reader` - The map to read
+/// - `center` - The node position to load around
+/// - `radius_blocks` - How many blocks away from `center`'s block to load, along each axis
+///
+/// # Returns
+/// - The position and raw data of each existing block within the cube, sorted by z, then y, then x
+pub fn blocks_near<R: MapReader>(
+    reader: &R,
+    center: SpatialCoordinate,
+    radius_blocks: i16,
+) -> Result<Vec<(HashedCoordinate, Vec<u8>)>, WorldError> {
+    let center = (
+        i64::from(center.x.div_euclid(16)),
+        i64::from(center.y.div_euclid(16)),
+        i64::from(center.z.div_euclid(16)),
+    );
+    let radius = i64::from(radius_blocks.max(0));
+    let mut coords = Vec::new();
+    for dz in -radius..=radius {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let position = (
+                    i16::try_from(center.0 + dx),
+                    i16::try_from(center.1 + dy),
+                    i16::try_from(center.2 + dz),
+                );
+                // Positions beyond the map limits can't hold a block
+                if let (Ok(x), Ok(y), Ok(z)) = position {
+                    if let Ok(coord) = HashedCoordinate::at(x, y, z) {
+                        coords.push(coord);
+                    }
+                }
+            }
+        }
+    }
+
+    let existing = reader.blocks_exist(&coords)?;
+    coords
+        .into_iter()
+        .filter(|coord| existing.contains(coord))
+        .map(|coord| Ok((coord, reader.get_block(coord)?)))
+        .collect()
+}
+
+/// How a block's node data differs from what a block holds, see `find_node_data_mismatches`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeDataMismatch {
+    /// The node data holds this many nodes, rather than 4096. Too many shift the rest of the block
+    /// along, so they are found by where the rest of it decodes from.
+    NodeCount(usize),
+    /// The block is followed by this many bytes, which Luanti ignores
+    TrailingBytes(usize),
+    /// The node data is stored with these widths, rather than 2 and 2
+    Widths { content_width: u8, params_width: u8 },
+}
+
+/// Finds the blocks whose node data does not hold exactly one block's worth of nodes, or that is
+/// stored in a layout Luanti doesn't read
+///
+/// Blocks of other versions are not checked, nor are blocks that are corrupt in other ways, such as
+/// before their node data.
+///
+/// # Returns
+/// - The position of each such block, with how its node data differs
+pub fn find_node_data_mismatches<R: MapReader>(
+    reader: &R,
+) -> Result<Vec<(HashedCoordinate, NodeDataMismatch)>, WorldError> {
+    let mut mismatched = Vec::new();
+    for coord in reader.blocks()? {
+        let data = reader.get_block(coord)?;
+        if data.first() != Some(&29) {
+            continue;
+        }
+        let mismatch = match MapBlock29::node_data_layout(&data[1..]) {
+            Ok((NODE_COUNT, 0)) => continue,
+            Ok((NODE_COUNT, trailing)) => NodeDataMismatch::TrailingBytes(trailing),
+            Ok((nodes, _)) => NodeDataMismatch::NodeCount(nodes),
+            Err(BlockDeserializeError::NodeWidthMismatch {
+                content_width,
+                params_width,
+            }) => NodeDataMismatch::Widths {
+                content_width,
+                params_width,
+            },
+            Err(_) => continue,
+        };
+        mismatched.push((coord, mismatch));
+    }
+    Ok(mismatched)
+}
+
+/// A set of the six faces of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaceMask(u8);
+
+impl FaceMask {
+    pub const NONE: FaceMask = FaceMask(0);
+    pub const NEGATIVE_X: FaceMask = FaceMask(0x01);
+    pub const POSITIVE_X: FaceMask = FaceMask(0x02);
+    pub const NEGATIVE_Y: FaceMask = FaceMask(0x04);
+    pub const POSITIVE_Y: FaceMask = FaceMask(0x08);
+    pub const NEGATIVE_Z: FaceMask = FaceMask(0x10);
+    pub const POSITIVE_Z: FaceMask = FaceMask(0x20);
+    pub const ALL: FaceMask = FaceMask(0x3f);
+
+    /// True if every face in `faces` is also in this set
+    pub fn contains(&self, faces: FaceMask) -> bool {
+        self.0 & faces.0 == faces.0
+    }
+}
+
+impl BitOr for FaceMask {
+    type Output = FaceMask;
+
+    fn bitor(self, other: FaceMask) -> FaceMask {
+        FaceMask(self.0 | other.0)
+    }
+}
+
+/// Finds which faces of a block border open space in the neighboring blocks
+///
+/// A face is exposed if the neighboring block is missing, or if any node of the neighbor's layer
+/// touching the face is `air` or `ignore`.
+///
+/// # Arguments
+/// - `reader` - The map to check
+/// - `block` - The block whose faces to check. It does not need to exist itself.
+pub fn exposed_faces<R: MapReader>(
+    reader: &R,
+    block: HashedCoordinate,
+) -> Result<FaceMask, WorldError> {
+    // Each face, the neighbor across it, and the index of a node in the neighbor's touching layer
+    // given the two other (local) coordinates
+    type LayerIndex = fn(u8, u8) -> usize;
+    let faces: [(FaceMask, HashedCoordinate, LayerIndex); 6] = [
+        (FaceMask::NEGATIVE_X, HashedCoordinate::left(), |a, b| {
+            node_order(15, a, b)
+        }),
+        (FaceMask::POSITIVE_X, HashedCoordinate::right(), |a, b| {
+            node_order(0, a, b)
+        }),
+        (FaceMask::NEGATIVE_Y, HashedCoordinate::down(), |a, b| {
+            node_order(a, 15, b)
+        }),
+        (FaceMask::POSITIVE_Y, HashedCoordinate::up(), |a, b| {
+            node_order(a, 0, b)
+        }),
+        (FaceMask::NEGATIVE_Z, HashedCoordinate::back(), |a, b| {
+            node_order(a, b, 15)
+        }),
+        (FaceMask::POSITIVE_Z, HashedCoordinate::forward(), |a, b| {
+            node_order(a, b, 0)
+        }),
+    ];
+
+    let mut exposed = FaceMask::NONE;
+    for (face, offset, layer_index) in faces {
+        let neighbor = block + offset;
+        if !reader.block_exists(neighbor)? {
+            exposed = exposed | face;
+            continue;
+        }
+        let neighbor = read_block(reader, neighbor)?;
+        let open = (0..16).any(|a| {
+            (0..16).any(|b| {
+                let id = neighbor.content_ids()[layer_index(a, b)];
+                matches!(neighbor.content_name(id), Some("air") | Some("ignore"))
+            })
+        });
+        if open {
+            exposed = exposed | face;
+        }
+    }
+    Ok(exposed)
+}
+
+/// Finds the surface of a column of nodes
+///
+/// # Arguments
+/// - `reader` - The map to scan
+/// - `x`, `z` - Node position of the column
+/// - `top`, `bottom` - The span of Y to scan, inclusive
+///
+/// # Returns
+/// - The Y of the highest node that is neither `air` nor `ignore`, or None if there is none.
+///   Blocks missing from the map are treated as empty.
+pub fn column_surface<R: MapReader>(
+    reader: &R,
+    x: i32,
+    z: i32,
+    top: i32,
+    bottom: i32,
+) -> Result<Option<i32>, WorldError> {
+    let (Ok(block_x), Ok(block_z)) = (
+        i16::try_from(x.div_euclid(16)),
+        i16::try_from(z.div_euclid(16)),
+    ) else {
+        return Ok(None);
+    };
+    let (column_x, column_z) = (x.rem_euclid(16) as u8, z.rem_euclid(16) as u8);
+
+    for block_y in (bottom.div_euclid(16)..=top.div_euclid(16)).rev() {
+        let Some(coord) = i16::try_from(block_y)
+            .ok()
+            .and_then(|block_y| HashedCoordinate::at(block_x, block_y, block_z).ok())
+        else {
+            continue;
+        };
+        if !reader.block_exists(coord)? {
+            continue;
+        }
+
+        let block = read_block(reader, coord)?;
+        for y in (bottom.max(block_y * 16)..=top.min(block_y * 16 + 15)).rev() {
+            let name = block.node_name_at(column_x, y.rem_euclid(16) as u8, column_z);
+            if !matches!(name, Some("air") | Some("ignore")) {
+                return Ok(Some(y));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod luanti_analysis_tests {
+    use super::*;
+    use crate::backend::luanti::{
+        block_serialization::{decode_block, MapBlockData},
+        map::{MapWriter, MemoryMapReader, SQLite3MapReader},
+        node_def::scan_node_names,
+    };
+    use std::fs;
+
+    /// Reads the raw data of a mixed terrain block and an all-`ignore` placeholder from the sample world
+    fn sample_blocks() -> (Vec<u8>, Vec<u8>) {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let terrain = sample
+            .get_block(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        let placeholder = sample
+            .get_block(HashedCoordinate { value: 218136553 })
+            .unwrap();
+        (terrain, placeholder)
+    }
+
+    #[test]
+    fn node_histogram() {
+        let (terrain, placeholder) = sample_blocks();
+        let map = MemoryMapReader::new();
+        map.set_block(HashedCoordinate::at(0, 0, 0).unwrap(), &terrain)
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 1).unwrap(), &terrain)
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 2).unwrap(), &placeholder)
+            .unwrap();
+
+        let all = super::node_histogram(&map, false).unwrap();
+        assert_eq!(all.values().sum::<u64>(), 3 * 4096);
+        assert_eq!(all["ignore"], 4096);
+
+        let generated = super::node_histogram(&map, true).unwrap();
+        assert_eq!(generated.values().sum::<u64>(), 2 * 4096);
+        assert!(!generated.contains_key("ignore"));
+        assert_eq!(all["air"], generated["air"]);
+    }
+
+    #[test]
+    fn estimated_uncompressed_size() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let terrain = sample
+            .get_block(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        // Holds a chest, so has metadata as well
+        let chest = sample
+            .get_block(HashedCoordinate { value: 335548405 })
+            .unwrap();
+        let map = MemoryMapReader::new();
+        assert_eq!(super::estimated_uncompressed_size(&map).unwrap(), 0);
+
+        // Blocks whose frames record their size count exactly
+        let mut lengths = 0;
+        for (x, data) in [(0, &terrain), (1, &chest)] {
+            let decompressed = zstd::stream::decode_all(&data[1..]).unwrap();
+            let mut block = vec![29];
+            block.extend(zstd::bulk::compress(&decompressed, 0).unwrap());
+            assert!(decode_block(&block).unwrap().was_generated());
+            map.set_block(HashedCoordinate::at(x, 0, 0).unwrap(), &block)
+                .unwrap();
+            lengths += decompressed.len() as u64;
+        }
+        assert_eq!(super::estimated_uncompressed_size(&map).unwrap(), lengths);
+
+        // Luanti's own frames don't, so they count as their node data
+        map.set_block(HashedCoordinate::at(2, 0, 0).unwrap(), &terrain)
+            .unwrap();
+        assert_eq!(
+            super::estimated_uncompressed_size(&map).unwrap(),
+            lengths + 4096 * 4
+        );
+
+        // Ungenerated blocks don't count at all
+        let ungenerated = sample
+            .get_block(HashedCoordinate { value: 117424115 })
+            .unwrap();
+        assert!(!decode_block(&ungenerated).unwrap().was_generated());
+        map.set_block(HashedCoordinate::at(3, 0, 0).unwrap(), &ungenerated)
+            .unwrap();
+        assert_eq!(
+            super::estimated_uncompressed_size(&map).unwrap(),
+            lengths + 4096 * 4
+        );
+    }
+
+    #[test]
+    fn validate_positions() {
+        let map = SQLite3MapReader::open_memory().unwrap();
+        for (x, y, z) in [(0, 0, 0), (-2048, 2047, -2048), (2047, -2048, 2047)] {
+            map.set_block(HashedCoordinate::at(x, y, z).unwrap(), &vec![29])
+                .unwrap();
+        }
+        assert!(super::validate_positions(&map).unwrap().is_empty());
+
+        // z = 2048, and values far beyond any position
+        let invalid = [2048 * 16777216, 1 << 40, -(1 << 40), i64::MAX];
+        for value in invalid {
+            map.set_block(HashedCoordinate { value }, &vec![29])
+                .unwrap();
+        }
+        let mut reported = super::validate_positions(&map).unwrap();
+        reported.sort();
+        let mut expected = invalid.to_vec();
+        expected.sort();
+        assert_eq!(reported, expected);
+    }
+
+    #[test]
+    fn unknown_nodes() {
+        let (terrain, placeholder) = sample_blocks();
+        // A game registering every node of the terrain block, except the built-in air and ignore
+        let game = tempfile::tempdir().unwrap();
+        let init: String = decode_block(&terrain)
+            .unwrap()
+            .name_id_mapping()
+            .values()
+            .filter(|name| !matches!(name.as_str(), "air" | "ignore"))
+            .map(|name| format!("minetest.register_node(\"{}\", {{}})\n", name))
+            .collect();
+        fs::write(game.path().join("init.lua"), init).unwrap();
+        let known = scan_node_names(game.path()).unwrap();
+        assert!(!known.contains("air"));
+
+        let mut leftover = decode_block(&terrain).unwrap();
+        for x in 0..3 {
+            leftover.set_node_at(x, 15, 15, "oldmod:gizmo", 0);
+        }
+        let map = MemoryMapReader::new();
+        map.set_block(HashedCoordinate::at(0, 0, 0).unwrap(), &terrain)
+            .unwrap();
+        map.set_block(
+            HashedCoordinate::at(0, 0, 1).unwrap(),
+            &leftover.serialize(),
+        )
+        .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 2).unwrap(), &placeholder)
+            .unwrap();
+
+        assert_eq!(
+            super::unknown_nodes(&map, &known).unwrap(),
+            HashMap::from([("oldmod:gizmo".to_string(), 3)])
+        );
+        assert!(super::unknown_nodes(&MemoryMapReader::new(), &known)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_node_histogram() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let map = MemoryMapReader::new();
+        for coord in sample.blocks().unwrap().into_iter().take(300) {
+            map.set_block(coord, &sample.get_block(coord).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(
+            super::par_node_histogram(&map).unwrap(),
+            super::node_histogram(&map, false).unwrap()
+        );
+        assert!(super::par_node_histogram(&MemoryMapReader::new())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn world_bounds() {
+        let (terrain, placeholder) = sample_blocks();
+        let map = MemoryMapReader::new();
+        map.set_block(HashedCoordinate::at(0, 0, -3).unwrap(), &terrain)
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 2).unwrap(), &terrain)
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 5).unwrap(), &placeholder)
+            .unwrap();
+
+        let bounds = super::world_bounds(&map, false).unwrap().unwrap();
+        assert_eq!(bounds.from, SpatialCoordinate { x: 0, y: 0, z: -48 });
+        assert_eq!(
+            bounds.to,
+            SpatialCoordinate {
+                x: 15,
+                y: 15,
+                z: 95
+            }
+        );
+
+        let bounds = super::world_bounds(&map, true).unwrap().unwrap();
+        assert_eq!(bounds.from, SpatialCoordinate { x: 0, y: 0, z: -48 });
+        assert_eq!(
+            bounds.to,
+            SpatialCoordinate {
+                x: 15,
+                y: 15,
+                z: 47
+            }
+        );
+
+        assert!(super::world_bounds(&MemoryMapReader::new(), false)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn nearest_block() {
+        let (terrain, _) = sample_blocks();
+        let map = MemoryMapReader::new();
+        let near = HashedCoordinate::at(2, -1, 0).unwrap();
+        let far = HashedCoordinate::at(-4, 0, 3).unwrap();
+        map.set_block(near, &terrain).unwrap();
+        map.set_block(far, &terrain).unwrap();
+
+        // Node (8,8,8) is in block (0,0,0)
+        let from = SpatialCoordinate { x: 8, y: 8, z: 8 };
+        assert_eq!(super::nearest_block(&map, from, 8).unwrap(), Some(near));
+        assert_eq!(super::nearest_block(&map, from, 1).unwrap(), None);
+
+        // Node (-60,0,40) is in block (-4,0,2)
+        let from = SpatialCoordinate {
+            x: -60,
+            y: 0,
+            z: 40,
+        };
+        assert_eq!(super::nearest_block(&map, from, 8).unwrap(), Some(far));
+        assert_eq!(
+            super::nearest_block(&MemoryMapReader::new(), from, 4).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn blocks_near() {
+        let map = MemoryMapReader::new();
+        for (index, (x, y, z)) in [(0, 0, 0), (1, 1, 1), (3, 0, 0), (-1, 0, 0), (0, -2, 0)]
+            .into_iter()
+            .enumerate()
+        {
+            map.set_block(
+                HashedCoordinate::at(x, y, z).unwrap(),
+                &vec![29, index as u8],
+            )
+            .unwrap();
+        }
+
+        let center = SpatialCoordinate { x: 5, y: 5, z: 5 };
+        let near = super::blocks_near(&map, center, 1).unwrap();
+        assert_eq!(
+            near,
+            vec![
+                (HashedCoordinate::at(-1, 0, 0).unwrap(), vec![29, 3]),
+                (HashedCoordinate::at(0, 0, 0).unwrap(), vec![29, 0]),
+                (HashedCoordinate::at(1, 1, 1).unwrap(), vec![29, 1]),
+            ]
+        );
+
+        assert_eq!(super::blocks_near(&map, center, 0).unwrap().len(), 1);
+        assert!(
+            super::blocks_near(&map, SpatialCoordinate { x: 0, y: 200, z: 0 }, 2)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn column_surface() {
+        let (terrain, _) = sample_blocks();
+        let mut air = decode_block(&terrain).unwrap();
+        for index in 0..4096 {
+            air.set_node(index, "air", 0);
+        }
+        let mut ground = decode_block(&air.serialize()).unwrap();
+        // Stone at node (3, 21, 7), with a cave below it
+        ground.set_node_at(3, 5, 7, "default:stone", 0);
+        ground.set_node_at(3, 2, 7, "default:stone", 0);
+
+        let map = MemoryMapReader::new();
+        map.set_block(HashedCoordinate::at(0, 2, 0).unwrap(), &air.serialize())
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 1, 0).unwrap(), &ground.serialize())
+            .unwrap();
+
+        assert_eq!(super::column_surface(&map, 3, 7, 47, 0).unwrap(), Some(21));
+        assert_eq!(super::column_surface(&map, 3, 7, 20, 0).unwrap(), Some(18));
+        assert_eq!(super::column_surface(&map, 3, 7, 17, 0).unwrap(), None);
+        assert_eq!(super::column_surface(&map, 4, 7, 47, -64).unwrap(), None);
+    }
+
+    #[test]
+    fn find_node_data_mismatches() {
+        let (terrain, _) = sample_blocks();
+        let map = MemoryMapReader::new();
+        map.set_block(HashedCoordinate::at(0, 0, 0).unwrap(), &terrain)
+            .unwrap();
+
+        // Everything of an empty block up to its node data, then the rest of it
+        let node_data = |content_width: u8, nodes: usize, rest: &[u8]| {
+            let mut data = vec![0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, content_width, 2];
+            data.extend(vec![0; nodes * 4]);
+            data.extend_from_slice(rest);
+            let mut block = vec![29];
+            block.extend(zstd::stream::encode_all(data.as_slice(), 0).unwrap());
+            block
+        };
+        // No metadata, static objects or timers
+        let rest = [0, 0, 0, 0, 10, 0, 0];
+        let mut trailing = rest.to_vec();
+        trailing.extend_from_slice(&[0xaa; 5]);
+        let blocks = [
+            (node_data(2, 10, &[]), NodeDataMismatch::NodeCount(10)),
+            (node_data(2, 4000, &rest), NodeDataMismatch::NodeCount(4000)),
+            (node_data(2, 4097, &rest), NodeDataMismatch::NodeCount(4097)),
+            (
+                node_data(2, 4096, &trailing),
+                NodeDataMismatch::TrailingBytes(5),
+            ),
+            (
+                node_data(1, 4096, &rest),
+                NodeDataMismatch::Widths {
+                    content_width: 1,
+                    params_width: 2,
+                },
+            ),
+        ];
+        for (z, (block, _)) in blocks.iter().enumerate() {
+            map.set_block(HashedCoordinate::at(0, 0, z as i16 + 1).unwrap(), block)
+                .unwrap();
+        }
+        // A well-formed block of just the same kind isn't reported
+        map.set_block(
+            HashedCoordinate::at(0, 1, 0).unwrap(),
+            &node_data(2, 4096, &rest),
+        )
+        .unwrap();
+
+        let mut found = super::find_node_data_mismatches(&map).unwrap();
+        found.sort_by_key(|(coord, _)| coord.value);
+        let expected: Vec<_> = blocks
+            .iter()
+            .enumerate()
+            .map(|(z, (_, mismatch))| {
+                (HashedCoordinate::at(0, 0, z as i16 + 1).unwrap(), *mismatch)
+            })
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn exposed_faces() {
+        let (terrain, _) = sample_blocks();
+        let mut stone = decode_block(&terrain).unwrap();
+        for index in 0..4096 {
+            stone.set_node(index, "default:stone", 0);
+        }
+        let stone = stone.serialize();
+
+        let map = MemoryMapReader::new();
+        let center = HashedCoordinate::at(0, 0, 0).unwrap();
+        map.set_block(center, &stone).unwrap();
+        assert_eq!(super::exposed_faces(&map, center).unwrap(), FaceMask::ALL);
+
+        for (x, y, z) in [
+            (-1, 0, 0),
+            (1, 0, 0),
+            (0, -1, 0),
+            (0, 1, 0),
+            (0, 0, -1),
+            (0, 0, 1),
+        ] {
+            map.set_block(HashedCoordinate::at(x, y, z).unwrap(), &stone)
+                .unwrap();
+        }
+        assert_eq!(super::exposed_faces(&map, center).unwrap(), FaceMask::NONE);
+
+        // A single air node on the touching layer of the block above opens the top face
+        let mut above = decode_block(&stone).unwrap();
+        above.set_node_at(4, 0, 9, "air", 0);
+        map.set_block(center + HashedCoordinate::up(), &above.serialize())
+            .unwrap();
+        assert_eq!(
+            super::exposed_faces(&map, center).unwrap(),
+            FaceMask::POSITIVE_Y
+        );
+
+        // But not if it is anywhere else in that block
+        let mut above = decode_block(&stone).unwrap();
+        above.set_node_at(4, 1, 9, "air", 0);
+        map.set_block(center + HashedCoordinate::up(), &above.serialize())
+            .unwrap();
+        assert_eq!(super::exposed_faces(&map, center).unwrap(), FaceMask::NONE);
+
+        map.remove_block(center + HashedCoordinate::back()).unwrap();
+        let exposed = super::exposed_faces(&map, center).unwrap();
+        assert!(exposed.contains(FaceMask::NEGATIVE_Z));
+        assert!(!exposed.contains(FaceMask::NEGATIVE_Z | FaceMask::POSITIVE_Z));
+    }
+
+    #[test]
+    fn corrupt_block_is_located() {
+        let (terrain, _) = sample_blocks();
+        let mut corrupt = terrain.clone();
+        corrupt.truncate(corrupt.len() / 2);
+
+        let map = MemoryMapReader::new();
+        map.set_block(HashedCoordinate::at(0, 0, 1).unwrap(), &terrain)
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 2).unwrap(), &corrupt)
+            .unwrap();
+        map.set_block(HashedCoordinate::at(0, 0, 3).unwrap(), &terrain)
+            .unwrap();
+
+        match super::node_histogram(&map, false) {
+            Err(WorldError::CorruptPartition(position, _)) => {
+                assert_eq!(position, SpatialCoordinate { x: 0, y: 0, z: 2 })
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(matches!(
+            super::world_bounds(&map, true),
+            Err(WorldError::CorruptPartition(..))
+        ));
+    }
+}
@@ -1,14 +1,69 @@
-use std::{cell::Ref, collections::HashMap, rc::Weak, sync::Arc};
-
+use std::{
+    cell::Ref,
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    rc::Weak,
+    sync::Arc,
+};
+
+use flate2::read::GzDecoder;
 use rusqlite::{params, Connection};
 
-use crate::auth::{AuthBackend, User};
+use crate::{
+    auth::{AuthBackend, User},
+    WorldError,
+};
+
+use super::world::BackendType;
+
+/// Names of every user in a world's auth database
+///
+/// # Arguments
+/// - `backend` - The world's auth backend: `Files` (`auth.txt`) or `SQLite3` (`auth.sqlite`)
+/// - `dir` - The world directory
+///
+/// # Returns
+/// - The names, or none if the world has no auth database yet
+///
+/// # Errors
+/// - `WorldError::UnknownError` - If the backend is not supported
+/// - `WorldError::CorruptData` - If `auth.txt` could not be read
+/// - `WorldError::DatabaseError` - If `auth.sqlite` could not be read
+pub(super) fn auth_user_names(backend: BackendType, dir: &Path) -> Result<Vec<String>, WorldError> {
+    match backend {
+        BackendType::Files => {
+            let path = dir.join("auth.txt");
+            if !path.is_file() {
+                return Ok(Vec::new());
+            }
+            let backend = AuthTxtBackend::from_path(&path)?;
+            Ok(backend.users().iter().map(User::name).collect())
+        }
+        BackendType::SQLite3 => {
+            let path = dir.join("auth.sqlite");
+            if !path.is_file() {
+                return Ok(Vec::new());
+            }
+            let backend = AuthSqlBackend::try_open_file(&path.to_string_lossy())?;
+            Ok(backend.users().iter().map(User::name).collect())
+        }
+        BackendType::Dummy => Ok(Vec::new()),
+        backend => Err(WorldError::UnknownError(format!(
+            "Unsupported auth backend {:?}",
+            backend
+        ))),
+    }
+}
 
-struct AuthTxtBackend {
+/// A world's `auth.txt`, as used by the `files` auth backend
+pub struct AuthTxtBackend {
     users: Vec<AuthTxtBackendUser>,
 }
 
-struct AuthTxtBackendUser {
+/// A user of an `auth.txt`
+pub struct AuthTxtBackendUser {
     name: String,
     password: String,
     privileges: Vec<String>,
@@ -88,6 +143,44 @@ impl AuthTxtBackend {
 
         AuthTxtBackend { users: data }
     }
+
+    /// Parses `auth.txt` data from a reader
+    ///
+    /// # Errors
+    /// - `WorldError::CorruptData` - If the data could not be read, or is not UTF-8
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<AuthTxtBackend, WorldError> {
+        let mut serialized = String::new();
+        reader
+            .read_to_string(&mut serialized)
+            .map_err(|e| WorldError::CorruptData(format!("Failed to read auth.txt: {}", e)))?;
+        Ok(Self::from(&serialized))
+    }
+
+    /// Reads an `auth.txt` file, which may be gzip-compressed (as backups often are)
+    ///
+    /// Compressed files are recognized by a `.gz` extension or the gzip magic bytes.
+    ///
+    /// # Errors
+    /// - `WorldError::FileNotFound` - If the file could not be opened
+    /// - `WorldError::CorruptData` - If the file could not be read or decompressed
+    pub fn from_path(path: &Path) -> Result<AuthTxtBackend, WorldError> {
+        const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+        let file = File::open(path)
+            .map_err(|_| WorldError::FileNotFound(format!("Failed to open {}", path.display())))?;
+        let mut reader = BufReader::new(file);
+        // Peek at the start of the file without consuming it
+        let gzip_magic = reader
+            .fill_buf()
+            .map_err(|e| WorldError::CorruptData(format!("Failed to read auth.txt: {}", e)))?
+            .starts_with(&GZIP_MAGIC);
+
+        if path.extension().is_some_and(|extension| extension == "gz") || gzip_magic {
+            Self::from_reader(GzDecoder::new(reader))
+        } else {
+            Self::from_reader(reader)
+        }
+    }
 }
 
 impl AuthBackend<AuthTxtBackendUser> for AuthTxtBackend {
@@ -103,6 +196,7 @@ impl AuthBackend<AuthTxtBackendUser> for AuthTxtBackend {
 #[cfg(test)]
 mod auth_txt_backend_tests {
     use super::*;
+    use crate::auth::{auth_diff, Privilege, UserChange};
 
     #[test]
     fn from() {
@@ -111,9 +205,78 @@ mod auth_txt_backend_tests {
         assert_eq!(backend.users[0].name(), "celeron55");
         assert_eq!(backend.users[0].privileges(), vec!["interact", "shout"]);
     }
+
+    #[test]
+    fn diff() {
+        let a = AuthTxtBackend::from(
+            "celeron55:hash:interact,shout\nsfan5:hash:interact,fly\nsingleplayer::interact",
+        );
+        let b = AuthTxtBackend::from(
+            "sfan5:hash:fly,interact\ncareful:hash:interact\nceleron55:hash:interact,server",
+        );
+        let changeset = auth_diff(&a, &b);
+        assert_eq!(changeset.only_a, vec!["singleplayer"]);
+        assert_eq!(changeset.only_b, vec!["careful"]);
+        // sfan5 only has their privileges in another order
+        assert_eq!(
+            changeset.changed,
+            vec![UserChange {
+                name: "celeron55".to_string(),
+                password_changed: false,
+                privileges_added: vec![Privilege::Server],
+                privileges_removed: vec![Privilege::Shout],
+            }]
+        );
+
+        let c = AuthTxtBackend::from("celeron55:newhash:interact,shout");
+        let changeset = auth_diff(&a, &c);
+        assert!(changeset.changed[0].password_changed);
+        assert!(changeset.changed[0].privileges_added.is_empty());
+        assert!(auth_diff(&a, &a).changed.is_empty());
+    }
+
+    #[test]
+    fn from_path_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let auth_txt = "celeron55::interact,shout\nsfan5::interact,fly,fast\n";
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("auth.txt");
+        std::fs::write(&plain, auth_txt).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(auth_txt.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let gzipped = dir.path().join("auth.txt.gz");
+        std::fs::write(&gzipped, &compressed).unwrap();
+        // No telling extension - Detected by the magic bytes instead
+        let renamed = dir.path().join("auth.bak");
+        std::fs::write(&renamed, &compressed).unwrap();
+
+        let expected = AuthTxtBackend::from_path(&plain).unwrap();
+        assert_eq!(expected.users.len(), 2);
+        for path in [&gzipped, &renamed] {
+            let backend = AuthTxtBackend::from_path(path).unwrap();
+            assert_eq!(backend.users.len(), expected.users.len());
+            for (user, expected) in backend.users.iter().zip(expected.users.iter()) {
+                assert_eq!(user.name(), expected.name());
+                assert_eq!(user.privileges(), expected.privileges());
+            }
+        }
+        assert_eq!(
+            AuthTxtBackend::from_path(&gzipped).unwrap().users[1].privileges(),
+            vec!["interact", "fly", "fast"]
+        );
+
+        assert!(matches!(
+            AuthTxtBackend::from_path(&dir.path().join("missing.txt")),
+            Err(WorldError::FileNotFound(_))
+        ));
+    }
 }
 
-/* Schema for SQLite3:
+/// Schema of Luanti's `auth.sqlite`
+const AUTH_SQL_SCHEMA: &str = "
 CREATE TABLE `auth` (
     `id` INTEGER PRIMARY KEY AUTOINCREMENT,
     `name` VARCHAR(32) UNIQUE,
@@ -126,17 +289,19 @@ CREATE TABLE `user_privileges` (
     PRIMARY KEY (id, privilege),
     CONSTRAINT fk_id FOREIGN KEY (id) REFERENCES auth (id) ON DELETE CASCADE
 );
-*/
+";
 
+/// A user of an `auth.sqlite` database
 #[derive(Clone, Debug)]
-struct AuthSqlBackendUser {
+pub struct AuthSqlBackendUser {
     name: String,
     password: String,
     last_login: i32,
     privileges: Vec<String>,
 }
 
-struct AuthSqlBackend {
+/// A world's `auth.sqlite` database, with its users loaded
+pub struct AuthSqlBackend {
     conn: Connection,
     users: Vec<AuthSqlBackendUser>,
 }
@@ -184,10 +349,32 @@ impl User for AuthSqlBackendUser {
 }
 
 impl AuthSqlBackend {
-    fn open_memory() -> AuthSqlBackend {
+    /// Creates the `auth` and `user_privileges` tables, as Luanti would for a new world
+    ///
+    /// # Arguments
+    /// - `conn` - A connection to an empty database
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the tables could not be created (e.g. they already exist)
+    pub fn create_schema(conn: &Connection) -> Result<(), WorldError> {
+        conn.execute_batch(AUTH_SQL_SCHEMA)
+            .map_err(|_| WorldError::DatabaseError("Failed to create auth tables".to_string()))
+    }
+
+    /// Enables foreign keys on a connection, which SQLite leaves off by default
+    ///
+    /// Without them, deleting a user from `auth` would leave their rows in `user_privileges` behind,
+    /// as the `ON DELETE CASCADE` only applies while they are on.
+    fn enable_foreign_keys(conn: &Connection) -> Result<(), WorldError> {
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(|_| WorldError::DatabaseError("Failed to enable foreign keys".to_string()))
+    }
+
+    /// Creates an empty auth database in memory
+    pub fn open_memory() -> AuthSqlBackend {
         let conn = Connection::open_in_memory().unwrap();
-        conn.execute("CREATE TABLE auth (id INTEGER PRIMARY KEY AUTOINCREMENT, name VARCHAR(32) UNIQUE, password VARCHAR(512), last_login INTEGER)", []).unwrap();
-        conn.execute("CREATE TABLE user_privileges (id INTEGER, privilege VARCHAR(32), PRIMARY KEY (id, privilege), CONSTRAINT fk_id FOREIGN KEY (id) REFERENCES auth (id) ON DELETE CASCADE)", []).unwrap();
+        Self::enable_foreign_keys(&conn).unwrap();
+        Self::create_schema(&conn).unwrap();
         AuthSqlBackend {
             conn,
             users: Vec::new(),
@@ -195,45 +382,81 @@ impl AuthSqlBackend {
     }
 
     fn open_file(file: &str) -> AuthSqlBackend {
-        let conn = Connection::open(file).unwrap();
+        Self::try_open_file(file).unwrap()
+    }
+
+    /// Opens an auth database and loads its users
+    ///
+    /// # Arguments
+    /// - `file` - Path of the database, usually a world's `auth.sqlite`
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the file is not an SQLite database, or has no `auth` table
+    pub fn try_open_file(file: &str) -> Result<AuthSqlBackend, WorldError> {
+        let conn = Connection::open(file).map_err(|_| {
+            WorldError::DatabaseError(format!("Failed to open auth database {}", file))
+        })?;
+        Self::enable_foreign_keys(&conn)?;
 
         let mut backend = AuthSqlBackend {
             conn,
             users: Vec::new(),
         };
-
-        backend.reload();
-
-        backend
+        backend.try_reload()?;
+        Ok(backend)
     }
 
     fn reload(&mut self) {
-        self.users.clear();
+        self.try_reload().unwrap();
+    }
+
+    /// Replaces the loaded users with those in the database
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the users could not be read. The loaded users are left as
+    ///   they were.
+    pub fn try_reload(&mut self) -> Result<(), WorldError> {
+        let error = |_| WorldError::DatabaseError("Failed to read users".to_string());
 
         let mut users = Vec::new();
         {
+            // Other Luanti versions add columns to (or leave them out of) the auth table, so only
+            // the known ones that are actually there are read
+            let columns = self.auth_columns()?;
+            let column = |name: &'static str| {
+                if columns.iter().any(|column| column == name) {
+                    name
+                } else {
+                    "NULL"
+                }
+            };
             let mut stmt = self
                 .conn
-                .prepare("SELECT name, password, last_login FROM auth")
-                .unwrap();
+                .prepare(&format!(
+                    "SELECT name, {}, {} FROM auth",
+                    column("password"),
+                    column("last_login")
+                ))
+                .map_err(error)?;
 
             for row in stmt
                 .query_map([], |row| {
                     Ok(AuthSqlBackendUser {
                         name: row.get(0)?,
-                        password: row.get(1)?,
-                        last_login: row.get(2)?,
+                        // Freshly-created accounts may not have a password yet
+                        password: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                        last_login: row.get::<_, Option<i32>>(2)?.unwrap_or_default(),
                         privileges: Vec::new(),
                     })
                 })
-                .unwrap()
+                .map_err(error)?
             {
-                users.push(row.unwrap());
+                users.push(row.map_err(error)?);
             }
         }
         {
             // Get the privileges for each user
-            let mut stmt = self.conn.prepare("SELECT name, privilege FROM auth JOIN user_privileges ON auth.id = user_privileges.id").unwrap();
+            let mut stmt = self.conn.prepare("SELECT name, privilege FROM auth JOIN user_privileges ON auth.id = user_privileges.id").map_err(error)?;
 
             struct PrivDataPoint {
                 name: String,
@@ -247,15 +470,28 @@ impl AuthSqlBackend {
                         privilege: row.get(1)?,
                     })
                 })
-                .unwrap()
+                .map_err(error)?
             {
-                let row = row.unwrap();
-                let user = users.iter_mut().find(|user| user.name == row.name).unwrap();
-                user.privileges.push(row.privilege);
+                let row = row.map_err(error)?;
+                if let Some(user) = users.iter_mut().find(|user| user.name == row.name) {
+                    user.privileges.push(row.privilege);
+                }
             }
         }
 
         self.users = users;
+        Ok(())
+    }
+
+    /// Names of the columns of the `auth` table, in order
+    fn auth_columns(&self) -> Result<Vec<String>, WorldError> {
+        let error = |_| WorldError::DatabaseError("Failed to read the auth table".to_string());
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM pragma_table_info('auth')")
+            .map_err(error)?;
+        let names = stmt.query_map([], |row| row.get(0)).map_err(error)?;
+        names.map(|name| name.map_err(error)).collect()
     }
 
     fn save(&mut self) {
@@ -421,6 +657,41 @@ impl AuthSqlBackend {
         // Apply changes
         self.conn.execute("COMMIT", []).unwrap();
     }
+
+    /// Exports the loaded users as a JSON array of `{name, last_login, privileges}` objects,
+    /// leaving out password hashes
+    #[cfg(feature = "serde")]
+    pub fn export_json(&self) -> String {
+        self.export_json_with(false)
+    }
+
+    /// Exports the loaded users as a JSON array of `{name, last_login, privileges}` objects
+    ///
+    /// # Arguments
+    /// - `include_passwords` - Whether to add each user's password hash as `password`
+    #[cfg(feature = "serde")]
+    pub fn export_json_with(&self, include_passwords: bool) -> String {
+        #[derive(serde::Serialize)]
+        struct ExportedUser<'a> {
+            name: &'a str,
+            last_login: i32,
+            privileges: &'a [String],
+            #[serde(skip_serializing_if = "Option::is_none")]
+            password: Option<&'a str>,
+        }
+
+        let users: Vec<ExportedUser> = self
+            .users
+            .iter()
+            .map(|user| ExportedUser {
+                name: &user.name,
+                last_login: user.last_login,
+                privileges: &user.privileges,
+                password: include_passwords.then_some(user.password.as_str()),
+            })
+            .collect();
+        serde_json::to_string(&users).unwrap()
+    }
 }
 
 impl AuthBackend<AuthSqlBackendUser> for AuthSqlBackend {
@@ -443,6 +714,67 @@ mod auth_sql_backend_tests {
         assert_eq!(backend.users().len(), 0);
     }
 
+    #[test]
+    fn delete_cascade() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.sqlite");
+        AuthSqlBackend::create_schema(&Connection::open(&path).unwrap()).unwrap();
+        let mut backend = AuthSqlBackend::open_file(&path.to_string_lossy());
+        for name in ["celeron55", "sfan5", "singleplayer"] {
+            backend.users_mut().push(AuthSqlBackendUser {
+                name: name.to_string(),
+                password: String::new(),
+                last_login: 0,
+                privileges: vec!["interact".to_string(), "shout".to_string()],
+            });
+        }
+        backend.save();
+        let privileges = |backend: &AuthSqlBackend| -> i64 {
+            backend
+                .conn
+                .query_row("SELECT COUNT(*) FROM user_privileges", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(privileges(&backend), 6);
+
+        // Deleting the auth row directly takes the user's privileges with it
+        backend
+            .conn
+            .execute("DELETE FROM auth WHERE name = 'celeron55'", [])
+            .unwrap();
+        assert_eq!(privileges(&backend), 4);
+
+        // As does removing a user on save
+        backend.reload();
+        backend.users_mut().retain(|user| user.name != "sfan5");
+        backend.save();
+        assert_eq!(privileges(&backend), 2);
+
+        // Cascading isn't left to whoever opens the database
+        let reopened = AuthSqlBackend::open_file(&path.to_string_lossy());
+        reopened.conn.execute("DELETE FROM auth", []).unwrap();
+        assert_eq!(privileges(&reopened), 0);
+    }
+
+    #[test]
+    fn create_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        AuthSqlBackend::create_schema(&conn).unwrap();
+
+        let columns = |table: &str| -> Vec<String> {
+            let mut stmt = conn
+                .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+                .unwrap();
+            let names = stmt.query_map([], |row| row.get(0)).unwrap();
+            names.map(|name| name.unwrap()).collect()
+        };
+        assert_eq!(columns("auth"), ["id", "name", "password", "last_login"]);
+        assert_eq!(columns("user_privileges"), ["id", "privilege"]);
+
+        // The tables already exist now
+        assert!(AuthSqlBackend::create_schema(&conn).is_err());
+    }
+
     #[test]
     fn open_file() {
         let backend = AuthSqlBackend::open_file("assets/world_luanti_5.10/auth.sqlite");
@@ -458,6 +790,69 @@ mod auth_sql_backend_tests {
             .iter()
             .find(|p| p.to_string() == "interact")
             .is_some());
+        assert!(backend.users()[0]
+            .privilege_set()
+            .contains(&crate::auth::Privilege::Interact));
+    }
+
+    #[test]
+    fn null_password() {
+        let mut backend = AuthSqlBackend::open_memory();
+        backend
+            .conn
+            .execute(
+                "INSERT INTO auth (name, password, last_login) VALUES (?, NULL, ?)",
+                params!["newcomer", 0],
+            )
+            .unwrap();
+
+        backend.reload();
+        assert_eq!(backend.users().len(), 1);
+        assert_eq!(backend.users()[0].name(), "newcomer");
+        assert_eq!(backend.users()[0].password(), "");
+    }
+
+    #[test]
+    fn extra_columns() {
+        let mut backend = AuthSqlBackend::open_memory();
+        backend
+            .conn
+            .execute_batch(
+                "ALTER TABLE auth ADD COLUMN srp_verifier BLOB;
+                INSERT INTO auth (name, password, last_login, srp_verifier) VALUES ('newer', '#1#salt#verifier', 1700000000, x'00ff');
+                INSERT INTO user_privileges (id, privilege) VALUES (1, 'interact');",
+            )
+            .unwrap();
+
+        backend.reload();
+        assert_eq!(backend.users().len(), 1);
+        let user = &backend.users()[0];
+        assert_eq!(user.name(), "newer");
+        assert_eq!(user.password(), "#1#salt#verifier");
+        assert_eq!(user.last_login(), 1700000000);
+        assert_eq!(user.privileges(), ["interact"]);
+    }
+
+    #[test]
+    fn missing_columns() {
+        let mut backend = AuthSqlBackend {
+            conn: Connection::open_in_memory().unwrap(),
+            users: Vec::new(),
+        };
+        backend
+            .conn
+            .execute_batch(
+                "CREATE TABLE auth (id INTEGER PRIMARY KEY AUTOINCREMENT, name VARCHAR(32) UNIQUE, password VARCHAR(512));
+                CREATE TABLE user_privileges (id INTEGER, privilege VARCHAR(32));
+                INSERT INTO auth (name, password) VALUES ('older', 'hash');",
+            )
+            .unwrap();
+
+        backend.reload();
+        assert_eq!(backend.users().len(), 1);
+        assert_eq!(backend.users()[0].name(), "older");
+        assert_eq!(backend.users()[0].password(), "hash");
+        assert_eq!(backend.users()[0].last_login(), 0);
     }
 
     #[test]
@@ -544,4 +939,33 @@ mod auth_sql_backend_tests {
             .find(|p| p.to_string() == "fly")
             .is_some());
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn export_json() {
+        let mut backend = AuthSqlBackend::open_memory();
+        backend.users_mut().push(AuthSqlBackendUser {
+            name: "alice".to_string(),
+            password: "hash-of-alice".to_string(),
+            last_login: 1700000000,
+            privileges: vec!["interact".to_string(), "fly".to_string()],
+        });
+        backend.users_mut().push(AuthSqlBackendUser {
+            name: "bob".to_string(),
+            password: "hash-of-bob".to_string(),
+            last_login: 0,
+            privileges: vec!["shout".to_string()],
+        });
+
+        let json = backend.export_json();
+        assert_eq!(
+            json,
+            r#"[{"name":"alice","last_login":1700000000,"privileges":["interact","fly"]},{"name":"bob","last_login":0,"privileges":["shout"]}]"#
+        );
+        assert!(!json.contains("password"));
+
+        let json = backend.export_json_with(true);
+        assert!(json.contains(r#""password":"hash-of-alice""#));
+        assert!(json.contains(r#""password":"hash-of-bob""#));
+    }
 }
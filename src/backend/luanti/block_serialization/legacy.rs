@@ -0,0 +1,120 @@
+// Luanti MapBlock Serialization Format Versions 26 to 28
+//
+// Written by Minetest 0.4 and Luanti 5.0 to 5.4. They hold the same data as version 29 (see
+// `MapBlock29::deserialize_legacy` for the layout), so a block is decoded into a `MapBlock29` and
+// written back in its original version.
+
+use crate::SpatialCoordinate;
+
+use super::{v29::MapBlock29, BlockDeserializeError, LightBank, MapBlockData};
+
+/// A block read from, and written back as, serialization version `VERSION`
+pub struct LegacyMapBlock<const VERSION: u8> {
+    block: MapBlock29,
+}
+
+/// Luanti 5.0 to 5.4
+pub type MapBlock28 = LegacyMapBlock<28>;
+/// Minetest 0.4.16 and up - node metadata vars have no "private" flag
+pub type MapBlock27 = LegacyMapBlock<27>;
+/// Minetest 0.4 before 0.4.16 - additionally, there is no `lighting_complete`
+pub type MapBlock26 = LegacyMapBlock<26>;
+
+impl<const VERSION: u8> LegacyMapBlock<VERSION> {
+    /// Decodes a block, given the data following the version byte
+    pub fn deserialize(data: &[u8]) -> Result<Self, BlockDeserializeError> {
+        Ok(LegacyMapBlock {
+            block: MapBlock29::deserialize_legacy(data, VERSION)?,
+        })
+    }
+
+    /// The block's content, as it would be held in the current version
+    pub fn block(&self) -> &MapBlock29 {
+        &self.block
+    }
+
+    /// Upgrades the block to the current version
+    pub fn into_v29(self) -> MapBlock29 {
+        self.block
+    }
+}
+
+impl<const VERSION: u8> MapBlockData for LegacyMapBlock<VERSION> {
+    fn serialize(&self) -> Vec<u8> {
+        self.block
+            .to_version(VERSION)
+            .expect("every legacy version can be written")
+    }
+
+    fn underground(&self) -> bool {
+        self.block.underground()
+    }
+
+    fn day_night_differs(&self) -> bool {
+        self.block.day_night_differs()
+    }
+
+    fn light_dirty(&self) -> bool {
+        self.block.light_dirty()
+    }
+
+    fn was_generated(&self) -> bool {
+        self.block.was_generated()
+    }
+
+    fn light_complete(&self, bank: LightBank, direction: SpatialCoordinate) -> bool {
+        self.block.light_complete(bank, direction)
+    }
+
+    fn timestamp(&self) -> u32 {
+        self.block.timestamp()
+    }
+}
+
+#[cfg(test)]
+mod legacy_map_block_tests {
+    use crate::{
+        backend::luanti::{
+            block_serialization::{decode_block, deserialize_block_data},
+            map::{HashedCoordinate, MapReader, SQLite3MapReader},
+        },
+        WorldError,
+    };
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let map = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let data = map
+            .get_block(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        let block = decode_block(&data).unwrap();
+
+        let v28 = MapBlock28::deserialize(&block.to_version(28).unwrap()[1..]).unwrap();
+        assert!(v28.block().content_eq(&block));
+        assert_eq!(v28.serialize()[0], 28);
+        assert_eq!(v28.timestamp(), block.timestamp());
+
+        let v27 = MapBlock27::deserialize(&block.to_version(27).unwrap()[1..]).unwrap();
+        assert_eq!(v27.serialize()[0], 27);
+        assert_eq!(v27.day_night_differs(), block.day_night_differs());
+
+        let v26 = MapBlock26::deserialize(&block.to_version(26).unwrap()[1..]).unwrap();
+        assert_eq!(v26.serialize()[0], 26);
+        assert!(v26.into_v29().content_eq(&block));
+
+        // Each version is read through the dispatcher, and upgraded by decode_block
+        for version in 26..=29 {
+            let data = block.to_version(version).unwrap();
+            assert!(decode_block(&data).unwrap().content_eq(&block));
+            assert!(deserialize_block_data(&data).is_ok());
+        }
+        assert_eq!(
+            deserialize_block_data(&vec![25, 0]).err(),
+            Some(WorldError::CorruptData(
+                "Unsupported MapBlock version 25".to_string()
+            ))
+        );
+    }
+}
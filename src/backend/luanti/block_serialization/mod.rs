@@ -1,11 +1,91 @@
-use v29::MapBlock29;
+use std::{
+    fmt::{self, Display},
+    io::Read,
+};
 
-use crate::SpatialCoordinate;
+use flate2::bufread::ZlibDecoder;
+
+use legacy::{MapBlock26, MapBlock27, MapBlock28};
+use v29::{MapBlock29, MapBlock29Header};
+
+use crate::{Coordinate, SpatialCoordinate, WorldError};
+
+use super::map::{HashedCoordinate, MapReader};
+pub mod legacy;
 pub mod v29;
 
+/// Number of nodes in a MapBlock (16x16x16)
+pub const NODE_COUNT: usize = 4096;
+
+/// Index of the node at block-local `(x, y, z)` in the node data, which is stored Z-major: `z * 256 + y * 16 + x`
+///
+/// Every lookup of a node by position should go through this, so the storage order is defined in one place.
+///
+/// # Panics
+/// - If any coordinate is not in `0..16`, as it would otherwise alias another node of the block.
+pub(crate) fn node_order(x: u8, y: u8, z: u8) -> usize {
+    assert!(
+        x < 16 && y < 16 && z < 16,
+        "node ({}, {}, {}) is outside a block",
+        x,
+        y,
+        z
+    );
+    usize::from(z) * 256 + usize::from(y) * 16 + usize::from(x)
+}
+
 pub enum LightBank {
     Day,
-    Night
+    Night,
+}
+
+/// Errors raised while decoding a serialized MapBlock
+#[derive(Debug, PartialEq)]
+pub enum BlockDeserializeError {
+    /// The data ended before the named section was fully read
+    Truncated(&'static str),
+    /// A compressed section could not be decompressed
+    Decompression(String),
+    /// The data is structurally invalid
+    Corrupt(String),
+    /// The node data holds a different number of nodes than a block has
+    NodeCountMismatch { expected: usize, got: usize },
+    /// The node data is stored with a content or params width other than 2 bytes
+    NodeWidthMismatch { content_width: u8, params_width: u8 },
+    /// The block (or one of its sections) is, or would have to be, in a serialization version that isn't supported
+    UnsupportedVersion(u8),
+}
+
+impl Display for BlockDeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockDeserializeError::Truncated(section) => write!(f, "Truncated {}", section),
+            BlockDeserializeError::Decompression(error) => {
+                write!(f, "Failed to decompress block: {}", error)
+            }
+            BlockDeserializeError::Corrupt(error) => write!(f, "Corrupt block: {}", error),
+            BlockDeserializeError::NodeCountMismatch { expected, got } => {
+                write!(f, "Block has {} nodes instead of {}", got, expected)
+            }
+            BlockDeserializeError::NodeWidthMismatch {
+                content_width,
+                params_width,
+            } => write!(
+                f,
+                "Unexpected content width {} / params width {}",
+                content_width, params_width
+            ),
+            BlockDeserializeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported MapBlock version {}", version)
+            }
+        }
+    }
+}
+
+impl From<BlockDeserializeError> for WorldError {
+    fn from(error: BlockDeserializeError) -> Self {
+        WorldError::CorruptData(error.to_string())
+    }
 }
 
 pub trait MapBlockData {
@@ -22,9 +102,194 @@ pub trait MapBlockData {
     fn timestamp(&self) -> u32;
 }
 
-fn deserialize_block_data(data: &Vec<u8>) -> Result<Box<dyn MapBlockData>, ()> {
-    match data[0] {
-        29 => Ok(Box::new(MapBlock29::deserialize(&data[1..]))),
-        _ => Err(())
+/// Decodes a block of any supported version, keeping it in that version
+///
+/// # Errors
+/// - `WorldError::CorruptData` - If the data is empty, truncated or corrupt, or of an unsupported version
+pub(crate) fn deserialize_block_data(data: &Vec<u8>) -> Result<Box<dyn MapBlockData>, WorldError> {
+    let Some((version, data)) = data.split_first() else {
+        return Err(WorldError::CorruptData("Empty MapBlock".to_string()));
+    };
+    match version {
+        29 => Ok(Box::new(MapBlock29::deserialize(data)?)),
+        28 => Ok(Box::new(MapBlock28::deserialize(data)?)),
+        27 => Ok(Box::new(MapBlock27::deserialize(data)?)),
+        26 => Ok(Box::new(MapBlock26::deserialize(data)?)),
+        version => Err(BlockDeserializeError::UnsupportedVersion(*version).into()),
+    }
+}
+
+/// Decodes a block as stored in the map, upgrading blocks of versions 26 to 28 to the current version (29)
+pub(crate) fn decode_block(data: &[u8]) -> Result<MapBlock29, WorldError> {
+    match data.first() {
+        Some(29) => Ok(MapBlock29::deserialize(&data[1..])?),
+        Some(version @ 26..=28) => Ok(MapBlock29::deserialize_legacy(&data[1..], *version)?),
+        Some(version) => Err(BlockDeserializeError::UnsupportedVersion(*version).into()),
+        None => Err(WorldError::CorruptData("Empty MapBlock".to_string())),
+    }
+}
+
+/// Reads and decodes the block at `coord`
+///
+/// # Errors
+/// - `WorldError::CorruptPartition` - If the block could not be decoded, naming the block
+pub(crate) fn read_block<R: MapReader>(
+    reader: &R,
+    coord: HashedCoordinate,
+) -> Result<MapBlock29, WorldError> {
+    decode_block_at(coord, &reader.get_block(coord)?)
+}
+
+/// Decodes the data of the block at `coord`, naming the block in any error
+///
+/// # Errors
+/// - `WorldError::CorruptPartition` - If the block could not be decoded
+pub(crate) fn decode_block_at(
+    coord: HashedCoordinate,
+    data: &[u8],
+) -> Result<MapBlock29, WorldError> {
+    decode_block(data).map_err(|error| name_block_error(coord, error))
+}
+
+/// Reads just the header of a block as stored in the map, naming the block in any error
+///
+/// Only version 29 blocks can be read without decoding their node data; older blocks keep their
+/// timestamp behind it, so they are decoded in full.
+///
+/// # Errors
+/// - `WorldError::CorruptPartition` - If the header could not be decoded
+pub(crate) fn decode_block_header_at(
+    coord: HashedCoordinate,
+    data: &[u8],
+) -> Result<MapBlock29Header, WorldError> {
+    match data.first() {
+        Some(29) => MapBlock29::deserialize_header_only(&data[1..]).map_err(WorldError::from),
+        _ => decode_block(data).map(|block| block.header()),
+    }
+    .map_err(|error| name_block_error(coord, error))
+}
+
+/// Turns a `WorldError::CorruptData` raised while decoding the block at `coord` into one naming it
+fn name_block_error(coord: HashedCoordinate, error: WorldError) -> WorldError {
+    match error {
+        WorldError::CorruptData(message) => match <SpatialCoordinate as Coordinate>::from(coord) {
+            Ok(position) => WorldError::CorruptPartition(position, message),
+            Err(_) => WorldError::CorruptData(format!("{} (block {})", message, coord.value)),
+        },
+        error => error,
+    }
+}
+
+/// Reads big-endian fields from serialized block data, erroring rather than panicking on short input
+pub(crate) struct BlockReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BlockReader<'a> {
+    pub fn new(data: &'a [u8]) -> BlockReader<'a> {
+        BlockReader { data, position: 0 }
+    }
+
+    /// Takes the next `len` bytes, naming `section` in the error if there aren't enough
+    pub fn bytes(
+        &mut self,
+        len: usize,
+        section: &'static str,
+    ) -> Result<&'a [u8], BlockDeserializeError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(BlockDeserializeError::Truncated(section))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self, section: &'static str) -> Result<u8, BlockDeserializeError> {
+        Ok(self.bytes(1, section)?[0])
+    }
+
+    pub fn u16(&mut self, section: &'static str) -> Result<u16, BlockDeserializeError> {
+        Ok(u16::from_be_bytes(
+            self.bytes(2, section)?.try_into().unwrap(),
+        ))
+    }
+
+    pub fn u32(&mut self, section: &'static str) -> Result<u32, BlockDeserializeError> {
+        Ok(u32::from_be_bytes(
+            self.bytes(4, section)?.try_into().unwrap(),
+        ))
+    }
+
+    pub fn i32(&mut self, section: &'static str) -> Result<i32, BlockDeserializeError> {
+        Ok(i32::from_be_bytes(
+            self.bytes(4, section)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Decompresses a zlib stream, leaving the reader just after its end
+    pub fn zlib(&mut self, section: &'static str) -> Result<Vec<u8>, BlockDeserializeError> {
+        let rest = &self.data[self.position..];
+        let mut decoder = ZlibDecoder::new(rest);
+        let mut decompressed = Vec::new();
+        decoder
+            .read_to_end(&mut decompressed)
+            .map_err(|_| BlockDeserializeError::Truncated(section))?;
+        let consumed = rest.len() - decoder.into_inner().len();
+        self.position += consumed;
+        Ok(decompressed)
     }
-}
\ No newline at end of file
+
+    /// Takes everything up to and including the next `\n`
+    pub fn line(&mut self, section: &'static str) -> Result<&'a [u8], BlockDeserializeError> {
+        let len = self.data[self.position..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .ok_or(BlockDeserializeError::Truncated(section))?;
+        self.bytes(len + 1, section)
+    }
+
+    /// Number of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    /// Offset of the next byte to be read
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Everything read since the given position
+    pub fn since(&self, start: usize) -> &'a [u8] {
+        &self.data[start..self.position]
+    }
+}
+
+#[cfg(test)]
+mod block_serialization_tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_block_data_errors() {
+        let error = |data: Vec<u8>| deserialize_block_data(&data).err().unwrap();
+
+        assert_eq!(
+            error(vec![]),
+            WorldError::CorruptData("Empty MapBlock".to_string())
+        );
+        // A version byte and nothing else
+        for version in 26..=29 {
+            assert!(matches!(error(vec![version]), WorldError::CorruptData(_)));
+        }
+        assert!(matches!(
+            error(vec![28, 0, 0xff]),
+            WorldError::CorruptData(message) if message == "Truncated header"
+        ));
+        assert_eq!(
+            error(vec![7, 0, 0, 0]),
+            WorldError::CorruptData("Unsupported MapBlock version 7".to_string())
+        );
+    }
+}
@@ -1,51 +1,1851 @@
 // Luanti MapBlock Serialization Format Version 29
+//
+// Everything following the version byte is a single zstd frame:
+// u8 flags, u16 lighting_complete, u32 timestamp, name-id mapping,
+// u8 content_width, u8 params_width, node data, node metadata, static objects, node timers
+//
+// Unlike some other formats, v29 has no compact encoding for a block made of a single node: the
+// header is always followed by the full node data (4096 u16 param0, then 4096 u8 each of param1 and
+// param2), and a uniform block only gets small through zstd. content_width and params_width are
+// always 2 - the width 1 of older versions is not used.
 
-use crate::SpatialCoordinate;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{Read, Write},
+};
 
-use super::{LightBank, MapBlockData};
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    backend::luanti::node_def::{ColorProvider, NodeDefProvider},
+    SpatialCoordinate, WorldError,
+};
+
+use super::{node_order, BlockDeserializeError, BlockReader, LightBank, MapBlockData, NODE_COUNT};
 
 pub struct MapBlock29 {
-    header_bytes: [u8; 13]
+    flags: u8,
+    /// Per-face lighting flags, see `lighting_complete_mask`
+    lighting_complete: u16,
+    timestamp: u32,
+    /// Block-local content id -> node name
+    name_id_mapping: HashMap<u16, String>,
+    /// Content id of each node
+    param0: Vec<u16>,
+    /// Light of each node - day in the low nibble, night in the high nibble
+    param1: Vec<u8>,
+    param2: Vec<u8>,
+    /// Node index -> serialized metadata of that node (vars and inventory), kept as-is
+    node_metadata: Vec<(u16, Vec<u8>)>,
+    /// Serialized static objects section, kept as-is
+    static_objects: Vec<u8>,
+    /// Serialized node timers section, kept as-is
+    node_timers: Vec<u8>,
+}
+
+/// The raw data of a single node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeRef {
+    /// Block-local content id, resolved to a name with `MapBlock29::content_name`
+    pub content: u16,
+    /// Light - day in the low nibble, night in the high nibble
+    pub param1: u8,
+    /// Node-specific data, e.g. facing direction
+    pub param2: u8,
+}
+
+/// A node with its position and name, as yielded by `MapBlock29::iter_nodes`
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeEntry {
+    /// Block-local `(x, y, z)`, each in `0..16`
+    pub pos: (u8, u8, u8),
+    /// Node name, or `unknown` if the content id is missing from the block's mapping
+    pub name: String,
+    /// Light - day in the low nibble, night in the high nibble
+    pub param1: u8,
+    pub param2: u8,
+}
+
+/// The metadata of a single node, such as the text of a sign or the contents of a chest
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeMeta {
+    /// String vars set by the node's mod, e.g. `infotext`
+    pub fields: HashMap<String, String>,
+    /// Names of the vars that aren't sent to clients
+    pub private: HashSet<String>,
+    /// The serialized inventory, up to and including its `EndInventory` line
+    pub inventory: Vec<u8>,
+}
+
+/// A running timer of a node, such as a furnace cooking or a sapling growing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeTimer {
+    /// Index of the node, `z * 256 + y * 16 + x`
+    pub index: u16,
+    /// When the timer fires, in milliseconds
+    pub timeout: i32,
+    /// How far along the timer is, in milliseconds
+    pub elapsed: i32,
+}
+
+/// An object (entity) saved in a block while it was unloaded
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaticObject {
+    /// Luanti's active object type, e.g. 7 for Lua entities such as dropped items
+    pub object_type: u8,
+    /// Position in BS units (10 per node)
+    pub position: [f32; 3],
+    /// The object's serialized data, as written by its type
+    pub data: Vec<u8>,
+}
+
+/// The fields at the start of a block, which can be read without decoding the rest of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapBlock29Header {
+    /// Block flags, see `MapBlockData`
+    pub flags: u8,
+    /// Per-face lighting flags, see `MapBlockData::light_complete`
+    pub lighting_complete: u16,
+    /// The game time (in seconds) the block was last saved at, `0xffffffff` if it hasn't been stamped yet
+    pub timestamp: u32,
+}
+
+impl MapBlock29Header {
+    /// True if the mapgen has finished the block
+    pub fn was_generated(&self) -> bool {
+        // 0x08 flag - set while the block is *not* generated
+        self.flags & 0x08 == 0
+    }
 }
 
 impl MapBlock29 {
-    pub fn deserialize(data: &[u8]) -> Self {
-        MapBlock29 {
-            header_bytes: data[0..13].try_into().unwrap()
+    /// Decodes a version 29 block, given the data following the version byte
+    pub fn deserialize(data: &[u8]) -> Result<Self, BlockDeserializeError> {
+        let data = zstd::stream::decode_all(data)
+            .map_err(|e| BlockDeserializeError::Decompression(e.to_string()))?;
+        let mut reader = BlockReader::new(&data);
+
+        let flags = reader.u8("header")?;
+        let lighting_complete = reader.u16("header")?;
+        let timestamp = reader.u32("header")?;
+        let name_id_mapping = read_name_id_mapping(&mut reader)?;
+
+        read_node_widths(&mut reader)?;
+        let (param0, param1, param2) = read_node_data(&mut reader)?;
+
+        let node_metadata = read_node_metadata(&mut reader)?;
+
+        let start = reader.position();
+        skip_static_objects(&mut reader)?;
+        let static_objects = reader.since(start).to_vec();
+
+        let start = reader.position();
+        skip_node_timers(&mut reader)?;
+        let node_timers = reader.since(start).to_vec();
+
+        Ok(MapBlock29 {
+            flags,
+            lighting_complete,
+            timestamp,
+            name_id_mapping,
+            param0,
+            param1,
+            param2,
+            node_metadata,
+            static_objects,
+            node_timers,
+        })
+    }
+
+    /// Finds how many nodes the node data of a version 29 block holds, given the data following the
+    /// version byte
+    ///
+    /// The node data doesn't record its length, so it is taken to end where the rest of the block
+    /// decodes from, exactly up to the end of the data. A full block's worth of nodes is tried first.
+    ///
+    /// # Returns
+    /// - The node count, and how many bytes are left over after the block. Bytes are only left over
+    ///   when the rest of the block decodes after a full block's worth of nodes, but no node count
+    ///   fits exactly. Data too short for a full block that nothing fits counts as many whole nodes
+    ///   as it holds.
+    ///
+    /// # Errors
+    /// - `BlockDeserializeError::NodeWidthMismatch` - If the node data isn't stored 2 bytes wide
+    /// - Any other `BlockDeserializeError` - If the block is corrupt before its node data, or the
+    ///   rest of it doesn't decode after any node count
+    pub(crate) fn node_data_layout(data: &[u8]) -> Result<(usize, usize), BlockDeserializeError> {
+        let data = zstd::stream::decode_all(data)
+            .map_err(|e| BlockDeserializeError::Decompression(e.to_string()))?;
+        let mut reader = BlockReader::new(&data);
+        reader.bytes(7, "header")?;
+        read_name_id_mapping(&mut reader)?;
+        read_node_widths(&mut reader)?;
+        let node_data = &data[reader.position()..];
+
+        // Decodes the rest of the block after `nodes` nodes, returning how many bytes are left over
+        let rest_after = |nodes: usize| {
+            let mut reader = BlockReader::new(
+                node_data
+                    .get(nodes * 4..)
+                    .ok_or(BlockDeserializeError::Truncated("node data"))?,
+            );
+            read_node_metadata(&mut reader)?;
+            skip_static_objects(&mut reader)?;
+            skip_node_timers(&mut reader)?;
+            Ok::<usize, BlockDeserializeError>(reader.remaining())
+        };
+
+        let full = rest_after(NODE_COUNT);
+        if full == Ok(0) {
+            return Ok((NODE_COUNT, 0));
+        }
+        if let Some(nodes) = (0..=node_data.len() / 4).find(|&nodes| rest_after(nodes) == Ok(0)) {
+            return Ok((nodes, 0));
+        }
+        if node_data.len() < NODE_COUNT * 4 {
+            return Ok((node_data.len() / 4, 0));
         }
+        full.map(|trailing| (NODE_COUNT, trailing))
+    }
+
+    /// Reads only the header of a version 29 block, given the data following the version byte
+    ///
+    /// The zstd frame is decompressed just far enough to reach the end of the header, so the name-id
+    /// mapping, node data and everything after them are never decoded - and aren't checked either.
+    /// Use this for scans over flags or timestamps, which don't need the nodes.
+    ///
+    /// # Errors
+    /// - `BlockDeserializeError::Decompression` - If the start of the frame could not be decompressed
+    /// - `BlockDeserializeError::Truncated` - If the frame ends within the header
+    pub fn deserialize_header_only(data: &[u8]) -> Result<MapBlock29Header, BlockDeserializeError> {
+        let mut decoder = zstd::stream::read::Decoder::with_buffer(data)
+            .map_err(|e| BlockDeserializeError::Decompression(e.to_string()))?;
+        let mut header = [0; 7];
+        decoder
+            .read_exact(&mut header)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => BlockDeserializeError::Truncated("header"),
+                _ => BlockDeserializeError::Decompression(e.to_string()),
+            })?;
+
+        let mut reader = BlockReader::new(&header);
+        Ok(MapBlock29Header {
+            flags: reader.u8("header")?,
+            lighting_complete: reader.u16("header")?,
+            timestamp: reader.u32("header")?,
+        })
+    }
+
+    /// The block's header fields
+    pub fn header(&self) -> MapBlock29Header {
+        MapBlock29Header {
+            flags: self.flags,
+            lighting_complete: self.lighting_complete,
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// Decodes a block of version 26, 27 or 28, given the data following the version byte
+    ///
+    /// These hold the same data as version 29 in a different layout: u8 flags, u16 lighting_complete
+    /// (27 and up), u8 content_width, u8 params_width, zlib-compressed node data, zlib-compressed node
+    /// metadata, static objects, u32 timestamp, name-id mapping, node timers. Node metadata before
+    /// version 28 has no "private" flag on its vars, which are read as public.
+    ///
+    /// # Errors
+    /// - `BlockDeserializeError::UnsupportedVersion` - For any other version
+    pub fn deserialize_legacy(data: &[u8], version: u8) -> Result<Self, BlockDeserializeError> {
+        if !(26..=28).contains(&version) {
+            return Err(BlockDeserializeError::UnsupportedVersion(version));
+        }
+        let mut reader = BlockReader::new(data);
+
+        let flags = reader.u8("header")?;
+        let lighting_complete = if version >= 27 {
+            reader.u16("header")?
+        } else {
+            // Light was always assumed complete before it was tracked
+            0xffff
+        };
+        read_node_widths(&mut reader)?;
+
+        let node_data = reader.zlib("node data")?;
+        let (param0, param1, param2) = read_node_data(&mut BlockReader::new(&node_data))?;
+        let node_metadata =
+            read_node_metadata(&mut BlockReader::new(&reader.zlib("node metadata")?))?;
+
+        let start = reader.position();
+        skip_static_objects(&mut reader)?;
+        let static_objects = reader.since(start).to_vec();
+
+        let timestamp = reader.u32("timestamp")?;
+        let name_id_mapping = read_name_id_mapping(&mut reader)?;
+
+        let start = reader.position();
+        skip_node_timers(&mut reader)?;
+        let node_timers = reader.since(start).to_vec();
+
+        Ok(MapBlock29 {
+            flags,
+            lighting_complete,
+            timestamp,
+            name_id_mapping,
+            param0,
+            param1,
+            param2,
+            node_metadata,
+            static_objects,
+            node_timers,
+        })
+    }
+
+    /// Serializes the block for an older version of Luanti, version byte included
+    ///
+    /// # Arguments
+    /// - `target` - The version to write: 29 (the same as `serialize`), 28 (Luanti 5.0 to 5.4),
+    ///   27 (Minetest 0.4.16 and up) or 26. Writing 27 or 26 drops the "private" flag of node metadata
+    ///   vars, and 26 also drops `lighting_complete`.
+    ///
+    /// # Errors
+    /// - `BlockDeserializeError::UnsupportedVersion` - For any other version. Older versions lay out
+    ///   node timers differently, which isn't supported.
+    pub fn to_version(&self, target: u8) -> Result<Vec<u8>, BlockDeserializeError> {
+        match target {
+            29 => Ok(self.serialize()),
+            26..=28 => {
+                let mut data = vec![target, self.flags];
+                if target >= 27 {
+                    data.extend_from_slice(&self.lighting_complete.to_be_bytes());
+                }
+                data.extend_from_slice(&[2, 2]);
+
+                let mut node_data = Vec::with_capacity(NODE_COUNT * 4);
+                self.write_node_data(&mut node_data);
+                data.extend(zlib(&node_data));
+                let mut node_metadata = Vec::new();
+                self.write_node_metadata(&mut node_metadata, target >= 28);
+                data.extend(zlib(&node_metadata));
+
+                data.extend_from_slice(&self.static_objects);
+                data.extend_from_slice(&self.timestamp.to_be_bytes());
+                self.write_name_id_mapping(&mut data);
+                data.extend_from_slice(&self.node_timers);
+                Ok(data)
+            }
+            _ => Err(BlockDeserializeError::UnsupportedVersion(target)),
+        }
+    }
+
+    /// Writes the name-id mapping of the content ids in use, as Luanti does
+    fn write_name_id_mapping(&self, data: &mut Vec<u8>) {
+        let used: BTreeSet<u16> = self.param0.iter().copied().collect();
+        let mapped: Vec<(u16, &String)> = used
+            .into_iter()
+            .filter_map(|id| self.name_id_mapping.get(&id).map(|name| (id, name)))
+            .collect();
+        data.push(0);
+        data.extend_from_slice(&(mapped.len() as u16).to_be_bytes());
+        for (id, name) in mapped {
+            data.extend_from_slice(&id.to_be_bytes());
+            data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            data.extend_from_slice(name.as_bytes());
+        }
+    }
+
+    fn write_node_data(&self, data: &mut Vec<u8>) {
+        for id in self.param0.iter() {
+            data.extend_from_slice(&id.to_be_bytes());
+        }
+        data.extend_from_slice(&self.param1);
+        data.extend_from_slice(&self.param2);
+    }
+
+    /// Writes the node metadata list, as version 2 or - without the "private" flags - version 1
+    fn write_node_metadata(&self, data: &mut Vec<u8>, private: bool) {
+        if self.node_metadata.is_empty() {
+            data.push(0);
+        } else {
+            data.push(if private { 2 } else { 1 });
+            data.extend_from_slice(&(self.node_metadata.len() as u16).to_be_bytes());
+            for (index, metadata) in self.node_metadata.iter() {
+                data.extend_from_slice(&index.to_be_bytes());
+                if private {
+                    data.extend_from_slice(metadata);
+                } else {
+                    data.extend(
+                        convert_node_metadata(metadata, true, false)
+                            .expect("stored metadata was validated when read"),
+                    );
+                }
+            }
+        }
+    }
+
+    /// True if both blocks hold the same nodes, metadata and objects
+    ///
+    /// Unlike comparing the raw data, this ignores the timestamp, flags and light (param1), which Luanti
+    /// updates without the content of the block changing. Nodes are compared by name, so blocks with
+    /// differently numbered name-id mappings can still be equal.
+    pub fn content_eq(&self, other: &MapBlock29) -> bool {
+        self.param2 == other.param2
+            && self.node_metadata == other.node_metadata
+            && self.static_objects == other.static_objects
+            && self
+                .param0
+                .iter()
+                .zip(other.param0.iter())
+                .all(|(a, b)| self.content_name(*a) == other.content_name(*b))
+    }
+
+    /// Returns the indices of every node whose day and night light levels differ
+    ///
+    /// Useful for validating the block-level `day_night_differs` flag against the actual node data.
+    pub fn nodes_with_day_night_diff(&self) -> Vec<usize> {
+        self.param1
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| *light & 0x0f != *light >> 4)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// True if every node in the block has the same content
+    pub fn is_uniform(&self) -> bool {
+        self.param0.iter().all(|id| *id == self.param0[0])
+    }
+
+    /// True if every node in the block is `ignore`
+    ///
+    /// Unlike a uniform block of air (open sky) or stone, an all-`ignore` block holds no terrain at all:
+    /// it is a placeholder Luanti saved before the area was generated, and is usually also flagged as not generated.
+    pub fn is_all_ignore(&self) -> bool {
+        self.param0
+            .iter()
+            .all(|id| self.content_name(*id) == Some("ignore"))
+    }
+
+    /// Classifies each node as solid (walkable) or not, indexed by `z * 256 + y * 16 + x`
+    ///
+    /// Content ids missing from the name-id mapping are treated as solid, as Luanti does for unknown nodes.
+    pub fn solid_mask(&self, defs: &dyn NodeDefProvider) -> [bool; NODE_COUNT] {
+        let mut walkable: HashMap<u16, bool> = HashMap::new();
+        let mut mask = [false; NODE_COUNT];
+        for (solid, id) in mask.iter_mut().zip(self.param0.iter()) {
+            *solid = *walkable.entry(*id).or_insert_with(|| {
+                self.content_name(*id)
+                    .is_none_or(|name| defs.is_walkable(name))
+            });
+        }
+        mask
+    }
+
+    /// Colors each column of the block by its topmost visible node, as a minimap would
+    ///
+    /// `air` and `ignore`, and nodes the provider has no color for, are seen through.
+    ///
+    /// # Returns
+    /// - The color of each column, indexed by `z * 16 + x`. Columns with nothing visible are black.
+    pub fn top_colors(&self, provider: &dyn ColorProvider) -> [[u8; 3]; 256] {
+        let mut colors: HashMap<u16, Option<[u8; 3]>> = HashMap::new();
+        let mut top = [[0; 3]; 256];
+        for z in 0..16u8 {
+            for x in 0..16u8 {
+                top[usize::from(z) * 16 + usize::from(x)] = (0..16)
+                    .rev()
+                    .find_map(|y| {
+                        let id = self.param0[node_order(x, y, z)];
+                        *colors
+                            .entry(id)
+                            .or_insert_with(|| match self.content_name(id) {
+                                Some("air") | Some("ignore") | None => None,
+                                Some(name) => provider.color_of(name),
+                            })
+                    })
+                    .unwrap_or([0; 3]);
+            }
+        }
+        top
+    }
+
+    /// The raw `lighting_complete` field
+    ///
+    /// From the most significant bit: four unused bits (always set), then
+    /// night X-, Y-, Z-, Z+, Y+, X+, then day X-, Y-, Z-, Z+, Y+, X+.
+    /// A set bit means the light at that face is known to be correct. New blocks store 0xFFFF.
+    pub fn lighting_complete_mask(&self) -> u16 {
+        self.lighting_complete
+    }
+
+    /// Decodes the objects saved in the block
+    ///
+    /// # Errors
+    /// - `WorldError::CorruptData` - If the section holds fewer objects than it declares
+    pub fn static_objects(&self) -> Result<Vec<StaticObject>, WorldError> {
+        const SECTION: &str = "static objects";
+
+        let mut reader = BlockReader::new(&self.static_objects);
+        reader.u8(SECTION)?;
+        let count = reader.u16(SECTION)?;
+        // Each object takes at least its type, position and data length
+        if count as usize * 15 > reader.remaining() {
+            return Err(WorldError::CorruptData(format!(
+                "Block declares {} static objects, but only has room for {}",
+                count,
+                reader.remaining() / 15
+            )));
+        }
+        let mut objects = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let object_type = reader.u8(SECTION)?;
+            // Stored as thousandths
+            let mut position = [0.0; 3];
+            for component in position.iter_mut() {
+                *component = reader.i32(SECTION)? as f32 / 1000.0;
+            }
+            let data_len = reader.u16(SECTION)?;
+            objects.push(StaticObject {
+                object_type,
+                position,
+                data: reader.bytes(data_len as usize, SECTION)?.to_vec(),
+            });
+        }
+        Ok(objects)
+    }
+
+    /// Decodes the node timers running in the block
+    ///
+    /// # Errors
+    /// - `BlockDeserializeError::Corrupt` - If the timers are not 10 bytes each
+    /// - `BlockDeserializeError::Truncated` - If the section holds fewer timers than it declares
+    pub fn node_timers(&self) -> Result<Vec<NodeTimer>, BlockDeserializeError> {
+        read_node_timers(&mut BlockReader::new(&self.node_timers))
+    }
+
+    /// The game time (in seconds) the block was last saved at, or `None` if it hasn't been stamped yet
+    ///
+    /// Luanti stores `0xffffffff` for blocks that have no timestamp, such as freshly generated ones.
+    pub fn timestamp_opt(&self) -> Option<u32> {
+        (self.timestamp != 0xffffffff).then_some(self.timestamp)
+    }
+
+    /// Decodes the metadata of every node that has any
+    ///
+    /// # Returns
+    /// - Each node's metadata, keyed by its index (`z * 256 + y * 16 + x`)
+    ///
+    /// # Errors
+    /// - `WorldError::CorruptData` - If the metadata of a node could not be decoded
+    pub fn node_metadata(&self) -> Result<HashMap<u16, NodeMeta>, WorldError> {
+        const SECTION: &str = "node metadata";
+
+        let mut node_metadata = HashMap::with_capacity(self.node_metadata.len());
+        for (index, metadata) in self.node_metadata.iter() {
+            let mut reader = BlockReader::new(metadata);
+            let mut fields = HashMap::new();
+            let mut private = HashSet::new();
+            for _ in 0..reader.u32(SECTION)? {
+                let key_len = reader.u16(SECTION)?;
+                let key = String::from_utf8_lossy(reader.bytes(key_len as usize, SECTION)?);
+                let value_len = reader.u32(SECTION)?;
+                let value = String::from_utf8_lossy(reader.bytes(value_len as usize, SECTION)?);
+                if reader.u8(SECTION)? != 0 {
+                    private.insert(key.to_string());
+                }
+                fields.insert(key.into_owned(), value.into_owned());
+            }
+            let inventory = reader.bytes(reader.remaining(), SECTION)?.to_vec();
+            node_metadata.insert(
+                *index,
+                NodeMeta {
+                    fields,
+                    private,
+                    inventory,
+                },
+            );
+        }
+        Ok(node_metadata)
+    }
+
+    /// Resolves a block-local content id through the name-id mapping
+    ///
+    /// Returns None for an id the mapping has no entry for. That isn't treated as corruption: some
+    /// versions of Luanti save freshly generated blocks with an empty mapping, and Luanti itself
+    /// shows such nodes as `unknown`. A mapping that is actually corrupt - truncated, or of an
+    /// unsupported version - fails `deserialize` instead.
+    pub fn content_name(&self, id: u16) -> Option<&str> {
+        self.name_id_mapping.get(&id).map(|name| name.as_str())
+    }
+
+    /// The block's name-id mapping, translating the content ids of `content_ids` and `node_at` to node names
+    pub fn name_id_mapping(&self) -> &HashMap<u16, String> {
+        &self.name_id_mapping
+    }
+
+    /// Content id of each node in storage order, i.e. indexed by `z * 256 + y * 16 + x`
+    ///
+    /// Always holds an id for every node, uniform blocks included - v29 has no single-content encoding.
+    /// The ids are stored big-endian in the block, but are decoded to native integers here.
+    /// Resolve them to node names with `content_name`.
+    pub fn content_ids(&self) -> &[u16] {
+        &self.param0
+    }
+
+    /// Name of the node at block-local `(x, y, z)`, each in `0..16`
+    ///
+    /// None if the node's content id isn't in the name-id mapping, see `content_name`.
+    ///
+    /// # Panics
+    /// - If any coordinate is not in `0..16`
+    pub fn node_name_at(&self, x: u8, y: u8, z: u8) -> Option<&str> {
+        self.content_name(self.param0[node_order(x, y, z)])
+    }
+
+    /// Content id and params of the node at block-local `(x, y, z)`, each in `0..16`
+    ///
+    /// # Panics
+    /// - If any coordinate is not in `0..16`
+    pub fn node_at(&self, x: u8, y: u8, z: u8) -> NodeRef {
+        let index = node_order(x, y, z);
+        NodeRef {
+            content: self.param0[index],
+            param1: self.param1[index],
+            param2: self.param2[index],
+        }
+    }
+
+    /// Turns every `from` node into a `to` node, keeping their params, metadata and timers
+    ///
+    /// Only the name-id mapping and, if `to` already has an id of its own, the content ids change.
+    ///
+    /// # Returns
+    /// - How many nodes were replaced
+    pub(crate) fn replace_content(&mut self, from: &str, to: &str) -> u64 {
+        let id_of = |name: &str| {
+            self.name_id_mapping
+                .iter()
+                .find(|(_, mapped)| mapped.as_str() == name)
+                .map(|(id, _)| *id)
+        };
+        let Some(from_id) = id_of(from).filter(|_| from != to) else {
+            return 0;
+        };
+        let count = self.param0.iter().filter(|id| **id == from_id).count() as u64;
+        if count == 0 {
+            return 0;
+        }
+
+        match id_of(to) {
+            Some(to_id) => {
+                for id in self.param0.iter_mut().filter(|id| **id == from_id) {
+                    *id = to_id;
+                }
+                self.name_id_mapping.remove(&from_id);
+            }
+            None => {
+                self.name_id_mapping.insert(from_id, to.to_string());
+            }
+        }
+        count
+    }
+
+    /// Steps through every node of the block in storage order, from `(0, 0, 0)` to `(15, 15, 15)` with X
+    /// changing fastest
+    pub fn iter_nodes(&self) -> impl Iterator<Item = NodeEntry> + '_ {
+        (0..NODE_COUNT).map(|index| NodeEntry {
+            pos: (
+                (index % 16) as u8,
+                (index / 16 % 16) as u8,
+                (index / 256) as u8,
+            ),
+            name: self
+                .content_name(self.param0[index])
+                .unwrap_or("unknown")
+                .to_string(),
+            param1: self.param1[index],
+            param2: self.param2[index],
+        })
+    }
+
+    /// Replaces the node at block-local `(x, y, z)`, see `set_node`
+    ///
+    /// # Panics
+    /// - If any coordinate is not in `0..16`
+    pub(crate) fn set_node_at(&mut self, x: u8, y: u8, z: u8, name: &str, param2: u8) -> bool {
+        self.set_node(node_order(x, y, z), name, param2)
+    }
+
+    /// Replaces the node at `index`, as Luanti's `set_node` would
+    ///
+    /// Any metadata or timer of the old node is dropped, and the light of the node is reset with the
+    /// block marked for relighting.
+    ///
+    /// # Returns
+    /// - True if the node actually changed
+    pub(crate) fn set_node(&mut self, index: usize, name: &str, param2: u8) -> bool {
+        let id = match self
+            .name_id_mapping
+            .iter()
+            .find(|(_, mapped)| mapped.as_str() == name)
+        {
+            Some((id, _)) => *id,
+            None => {
+                let id = (0..=u16::MAX)
+                    .find(|id| !self.name_id_mapping.contains_key(id))
+                    .unwrap();
+                self.name_id_mapping.insert(id, name.to_string());
+                id
+            }
+        };
+        if self.param0[index] == id && self.param2[index] == param2 {
+            return false;
+        }
+
+        self.param0[index] = id;
+        self.param1[index] = 0;
+        self.param2[index] = param2;
+        self.node_metadata
+            .retain(|(node, _)| *node as usize != index);
+        let mut timers = self
+            .node_timers()
+            .expect("stored timers were validated when read");
+        let timer_count = timers.len();
+        timers.retain(|timer| timer.index as usize != index);
+        if timers.len() != timer_count {
+            self.node_timers.clear();
+            write_node_timers(&mut self.node_timers, &timers);
+        }
+        // Keep the unused high bits set, but have Luanti recompute light at every face
+        self.lighting_complete &= 0xf000;
+        true
+    }
+}
+
+/// Builds a new block node by node, with every node not set filled with a background node
+pub struct MapBlock29Builder {
+    /// Node placed wherever no other node is set
+    fill: String,
+    /// The nodes set so far, by index, with their param2
+    nodes: HashMap<usize, (String, u8)>,
+}
+
+impl Default for MapBlock29Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapBlock29Builder {
+    /// Starts a block filled with `air`
+    pub fn new() -> MapBlock29Builder {
+        MapBlock29Builder {
+            fill: "air".to_string(),
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Sets the node placed wherever no other node is set, e.g. `ignore` for a partly generated block
+    ///
+    /// The fill is always in the built block's name-id mapping, even if no node ends up using it.
+    pub fn with_fill(&mut self, name: &str) -> &mut MapBlock29Builder {
+        self.fill = name.to_string();
+        self
+    }
+
+    /// Sets the node at block-local `(x, y, z)`, each in `0..16`, replacing any set there before
+    ///
+    /// # Panics
+    /// - If any coordinate is not in `0..16`
+    pub fn set_node(
+        &mut self,
+        x: u8,
+        y: u8,
+        z: u8,
+        name: &str,
+        param2: u8,
+    ) -> &mut MapBlock29Builder {
+        let index = node_order(x, y, z);
+        self.nodes.insert(index, (name.to_string(), param2));
+        self
+    }
+
+    /// Builds the block
+    ///
+    /// It is marked as generated but not yet lit, so Luanti computes its light when it loads it.
+    pub fn build(&self) -> MapBlock29 {
+        let mut block = MapBlock29 {
+            flags: 0,
+            // Keep the unused high bits set, as Luanti does
+            lighting_complete: 0xf000,
+            timestamp: 0xffffffff,
+            name_id_mapping: HashMap::from([(0, self.fill.clone())]),
+            param0: vec![0; NODE_COUNT],
+            param1: vec![0; NODE_COUNT],
+            param2: vec![0; NODE_COUNT],
+            node_metadata: Vec::new(),
+            // Version 0, no objects
+            static_objects: vec![0, 0, 0],
+            // 10 byte timers, none of them
+            node_timers: vec![10, 0, 0],
+        };
+        for (index, (name, param2)) in &self.nodes {
+            block.set_node(*index, name, *param2);
+        }
+        block
+    }
+}
+
+/// Reads the name-id mapping: u8 version (0), u16 count, then (u16 id, u16 name_len, name) triples
+fn read_name_id_mapping(
+    reader: &mut BlockReader,
+) -> Result<HashMap<u16, String>, BlockDeserializeError> {
+    const SECTION: &str = "name-id mapping";
+
+    // Only version 0 has ever been written - a newer one could lay out the count differently
+    let version = reader.u8(SECTION)?;
+    if version != 0 {
+        return Err(BlockDeserializeError::UnsupportedVersion(version));
+    }
+    let count = reader.u16(SECTION)?;
+    let mut name_id_mapping = HashMap::with_capacity(count as usize);
+    for _ in 0..count {
+        let id = reader.u16(SECTION)?;
+        let name_len = reader.u16(SECTION)?;
+        let name = reader.bytes(name_len as usize, SECTION)?;
+        name_id_mapping.insert(id, String::from_utf8_lossy(name).into_owned());
+    }
+    Ok(name_id_mapping)
+}
+
+/// Checks the u8 content_width and u8 params_width preceding the node data, which are always 2
+fn read_node_widths(reader: &mut BlockReader) -> Result<(), BlockDeserializeError> {
+    let content_width = reader.u8("node data")?;
+    let params_width = reader.u8("node data")?;
+    if content_width != 2 || params_width != 2 {
+        return Err(BlockDeserializeError::NodeWidthMismatch {
+            content_width,
+            params_width,
+        });
+    }
+    Ok(())
+}
+
+/// param0, param1 and param2 of every node
+type NodeData = (Vec<u16>, Vec<u8>, Vec<u8>);
+
+/// Reads the node data: u16 param0 of every node, then u8 param1 of every node, then u8 param2 of every node
+fn read_node_data(reader: &mut BlockReader) -> Result<NodeData, BlockDeserializeError> {
+    // 4 bytes per node (u16 param0, u8 param1, u8 param2)
+    if reader.remaining() < NODE_COUNT * 4 {
+        return Err(BlockDeserializeError::NodeCountMismatch {
+            expected: NODE_COUNT,
+            got: reader.remaining() / 4,
+        });
+    }
+    let param0 = reader
+        .bytes(NODE_COUNT * 2, "node data")?
+        .chunks_exact(2)
+        .map(|id| u16::from_be_bytes([id[0], id[1]]))
+        .collect();
+    let param1 = reader.bytes(NODE_COUNT, "node data")?.to_vec();
+    let param2 = reader.bytes(NODE_COUNT, "node data")?.to_vec();
+    Ok((param0, param1, param2))
+}
+
+/// Compresses a section of a version 28 block
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .expect("compressing to memory cannot fail");
+    encoder.finish().expect("compressing to memory cannot fail")
+}
+
+/// Reads the node metadata list: u8 version (0 if empty, else 1 or 2), u16 count, then per node
+/// u16 position, u32 var count, (u16 key_len, key, u32 value_len, value, u8 private) vars and an inventory
+///
+/// Version 1 has no `private` byte. Its metadata is converted to version 2, with every var public.
+fn read_node_metadata(
+    reader: &mut BlockReader,
+) -> Result<Vec<(u16, Vec<u8>)>, BlockDeserializeError> {
+    const SECTION: &str = "node metadata";
+
+    let private = match reader.u8(SECTION)? {
+        0 => return Ok(Vec::new()),
+        1 => false,
+        2 => true,
+        version => {
+            return Err(BlockDeserializeError::Corrupt(format!(
+                "Unexpected node metadata version {}",
+                version
+            )))
+        }
+    };
+    let count = reader.u16(SECTION)?;
+    let mut node_metadata = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let index = reader.u16(SECTION)?;
+        let start = reader.position();
+        skip_node_metadata(reader, private)?;
+        let metadata = reader.since(start);
+        node_metadata.push((
+            index,
+            if private {
+                metadata.to_vec()
+            } else {
+                convert_node_metadata(metadata, false, true)?
+            },
+        ));
+    }
+    Ok(node_metadata)
+}
+
+/// Steps over the metadata of a single node: its vars, then its inventory
+fn skip_node_metadata(
+    reader: &mut BlockReader,
+    private: bool,
+) -> Result<(), BlockDeserializeError> {
+    const SECTION: &str = "node metadata";
+
+    for _ in 0..reader.u32(SECTION)? {
+        let key_len = reader.u16(SECTION)?;
+        reader.bytes(key_len as usize, SECTION)?;
+        let value_len = reader.u32(SECTION)?;
+        reader.bytes(value_len as usize, SECTION)?;
+        if private {
+            reader.u8(SECTION)?;
+        }
+    }
+    // The inventory is text, terminated by an `EndInventory` line
+    while reader.line(SECTION)?.trim_ascii() != b"EndInventory" {}
+    Ok(())
+}
+
+/// Re-encodes the metadata of a single node with or without the `private` byte of each var
+///
+/// Vars lose their private flag when it is dropped, and are public when it is added.
+fn convert_node_metadata(
+    metadata: &[u8],
+    from_private: bool,
+    to_private: bool,
+) -> Result<Vec<u8>, BlockDeserializeError> {
+    const SECTION: &str = "node metadata";
+
+    let mut reader = BlockReader::new(metadata);
+    let var_count = reader.u32(SECTION)?;
+    let mut converted = var_count.to_be_bytes().to_vec();
+    for _ in 0..var_count {
+        let start = reader.position();
+        let key_len = reader.u16(SECTION)?;
+        reader.bytes(key_len as usize, SECTION)?;
+        let value_len = reader.u32(SECTION)?;
+        reader.bytes(value_len as usize, SECTION)?;
+        converted.extend_from_slice(reader.since(start));
+        if from_private {
+            reader.u8(SECTION)?;
+        }
+        if to_private {
+            converted.push(0);
+        }
+    }
+    // The inventory is kept as-is
+    converted.extend_from_slice(reader.bytes(reader.remaining(), SECTION)?);
+    Ok(converted)
+}
+
+/// Steps over the static objects: u8 version, u16 count, then per object
+/// u8 type, 3x s32 position, u16 data_len, data
+fn skip_static_objects(reader: &mut BlockReader) -> Result<(), BlockDeserializeError> {
+    const SECTION: &str = "static objects";
+
+    reader.u8(SECTION)?;
+    for _ in 0..reader.u16(SECTION)? {
+        reader.bytes(13, SECTION)?;
+        let data_len = reader.u16(SECTION)?;
+        reader.bytes(data_len as usize, SECTION)?;
+    }
+    Ok(())
+}
+
+/// Steps over the node timers, see `read_node_timers`
+fn skip_node_timers(reader: &mut BlockReader) -> Result<(), BlockDeserializeError> {
+    const SECTION: &str = "node timers";
+
+    let timer_len = reader.u8(SECTION)?;
+    if timer_len != 10 {
+        return Err(BlockDeserializeError::Corrupt(format!(
+            "Unexpected node timer length {}",
+            timer_len
+        )));
+    }
+    let count = reader.u16(SECTION)?;
+    reader.bytes(count as usize * 10, SECTION)?;
+    Ok(())
+}
+
+/// Reads the node timers: u8 timer length (10), u16 count, then per timer
+/// u16 position, s32 timeout and s32 elapsed time in milliseconds
+fn read_node_timers(reader: &mut BlockReader) -> Result<Vec<NodeTimer>, BlockDeserializeError> {
+    const SECTION: &str = "node timers";
+
+    let timer_len = reader.u8(SECTION)?;
+    if timer_len != 10 {
+        return Err(BlockDeserializeError::Corrupt(format!(
+            "Unexpected node timer length {}",
+            timer_len
+        )));
+    }
+    let count = reader.u16(SECTION)?;
+    let mut node_timers = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        node_timers.push(NodeTimer {
+            index: reader.u16(SECTION)?,
+            timeout: reader.i32(SECTION)?,
+            elapsed: reader.i32(SECTION)?,
+        });
+    }
+    Ok(node_timers)
+}
+
+/// Writes the node timers section, as read by `read_node_timers`
+fn write_node_timers(data: &mut Vec<u8>, timers: &[NodeTimer]) {
+    data.push(10);
+    data.extend_from_slice(&(timers.len() as u16).to_be_bytes());
+    for timer in timers {
+        data.extend_from_slice(&timer.index.to_be_bytes());
+        data.extend_from_slice(&timer.timeout.to_be_bytes());
+        data.extend_from_slice(&timer.elapsed.to_be_bytes());
     }
 }
 
 impl MapBlockData for MapBlock29 {
     fn serialize(&self) -> Vec<u8> {
-        Vec::new()
+        let mut data = vec![self.flags];
+        data.extend_from_slice(&self.lighting_complete.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        self.write_name_id_mapping(&mut data);
+
+        data.extend_from_slice(&[2, 2]);
+        self.write_node_data(&mut data);
+        self.write_node_metadata(&mut data, true);
+        data.extend_from_slice(&self.static_objects);
+        data.extend_from_slice(&self.node_timers);
+
+        let mut serialized = vec![29];
+        serialized.extend(
+            zstd::stream::encode_all(data.as_slice(), 0)
+                .expect("compressing to memory cannot fail"),
+        );
+        serialized
     }
 
     fn underground(&self) -> bool {
-        // byte 2, 0x01 flag
-        self.header_bytes[2] & 0x01 != 0
+        // 0x01 flag
+        self.flags & 0x01 != 0
     }
 
     fn day_night_differs(&self) -> bool {
-        // byte 2, 0x02 flag
-        self.header_bytes[2] & 0x02 != 0
+        // 0x02 flag
+        self.flags & 0x02 != 0
     }
 
     fn light_dirty(&self) -> bool {
-        // byte 2, 0x04 flag
-        self.header_bytes[2] & 0x04 != 0
+        // 0x04 flag
+        self.flags & 0x04 != 0
     }
 
     fn was_generated(&self) -> bool {
-        // byte 2, 0x08 flag
-        self.header_bytes[2] & 0x08 != 0
+        // 0x08 flag - set while the block is *not* generated
+        self.flags & 0x08 == 0
     }
 
     fn light_complete(&self, bank: LightBank, direction: SpatialCoordinate) -> bool {
-        false
+        // Bits from the least significant: X+, Y+, Z+, Z-, Y-, X- of the day bank, then of the night bank
+        let face = match (direction.x, direction.y, direction.z) {
+            (1, 0, 0) => 0,
+            (0, 1, 0) => 1,
+            (0, 0, 1) => 2,
+            (0, 0, -1) => 3,
+            (0, -1, 0) => 4,
+            (-1, 0, 0) => 5,
+            // Not a face of the block
+            _ => return false,
+        };
+        let bit = match bank {
+            LightBank::Day => face,
+            LightBank::Night => face + 6,
+        };
+        self.lighting_complete & (1 << bit) != 0
     }
 
     fn timestamp(&self) -> u32 {
-        0
+        self.timestamp
+    }
+}
+
+#[cfg(test)]
+mod map_block_29_tests {
+    use rusqlite::{params, Connection};
+
+    use super::*;
+
+    /// Reads the raw data of the block at the given packed position from the sample world
+    fn sample_block(pos: i64) -> Vec<u8> {
+        let db = Connection::open("assets/world_luanti_5.10/map.sqlite").unwrap();
+        db.query_row(
+            "SELECT data FROM blocks WHERE pos = ?",
+            params![pos],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn deserialize() {
+        let data = sample_block(335560685);
+        assert_eq!(data[0], 29);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert!(block.day_night_differs());
+        assert!(block.was_generated());
+        assert!(!block.is_uniform());
+        assert!(!block.is_all_ignore());
+    }
+
+    #[test]
+    fn node_at() {
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+
+        // Index 3107
+        let viola = block.node_at(3, 2, 12);
+        assert_eq!(block.content_name(viola.content), Some("flowers:viola"));
+        assert_eq!(viola.content, block.content_ids()[3107]);
+        // Index 527
+        let stone = block.node_at(15, 0, 2);
+        assert_eq!(block.content_name(stone.content), Some("default:stone"));
+
+        let mut block = uniform_block("air");
+        block.set_node_at(4, 5, 6, "default:furnace", 3);
+        assert_eq!(
+            block.node_at(4, 5, 6),
+            NodeRef {
+                content: 1,
+                param1: 0,
+                param2: 3
+            }
+        );
+        assert_eq!(block.node_at(6, 5, 4).content, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside a block")]
+    fn node_at_out_of_range() {
+        // (16, 0, 0) would otherwise alias (0, 1, 0)
+        uniform_block("air").node_at(16, 0, 0);
+    }
+
+    /// Builds a block where every node is `name`
+    fn uniform_block(name: &str) -> MapBlock29 {
+        MapBlock29 {
+            flags: 0x08,
+            lighting_complete: 0xffff,
+            timestamp: 0xffffffff,
+            name_id_mapping: HashMap::from([(0, name.to_string())]),
+            param0: vec![0; NODE_COUNT],
+            param1: vec![0; NODE_COUNT],
+            param2: vec![0; NODE_COUNT],
+            node_metadata: Vec::new(),
+            static_objects: vec![0, 0, 0],
+            node_timers: vec![10, 0, 0],
+        }
+    }
+
+    #[test]
+    fn top_colors() {
+        struct Palette;
+        impl ColorProvider for Palette {
+            fn color_of(&self, name: &str) -> Option<[u8; 3]> {
+                match name {
+                    "default:stone" => Some([128, 128, 128]),
+                    "default:water_source" => Some([0, 0, 255]),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut block = uniform_block("air");
+        for z in 0..16 {
+            for x in 0..16 {
+                block.set_node_at(x, 3, z, "default:stone", 0);
+            }
+        }
+        block.set_node_at(2, 9, 5, "default:water_source", 0);
+        // Seen through, as the palette has no color for it
+        block.set_node_at(7, 12, 1, "default:glass", 0);
+        block.set_node_at(15, 3, 15, "air", 0);
+
+        let colors = block.top_colors(&Palette);
+        assert_eq!(colors[5 * 16 + 2], [0, 0, 255]);
+        assert_eq!(colors[16 + 7], [128, 128, 128]);
+        assert_eq!(colors[0], [128, 128, 128]);
+        assert_eq!(colors[15 * 16 + 15], [0, 0, 0]);
+    }
+
+    #[test]
+    fn uniform_stone() {
+        let data = uniform_block("default:stone").serialize();
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert!(block.is_uniform());
+        assert_eq!(block.content_ids().len(), NODE_COUNT);
+        for z in 0..16 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    assert_eq!(block.node_name_at(x, y, z), Some("default:stone"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn is_all_ignore() {
+        let block = uniform_block("ignore");
+        assert!(block.is_uniform());
+        assert!(block.is_all_ignore());
+        assert!(!block.was_generated());
+
+        let block = uniform_block("air");
+        assert!(block.is_uniform());
+        assert!(!block.is_all_ignore());
+    }
+
+    #[test]
+    fn is_all_ignore_sample() {
+        // An ungenerated placeholder in the sample world
+        let data = sample_block(218136553);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert!(block.is_all_ignore());
+        assert!(!block.was_generated());
+    }
+
+    /// Treats everything but air as solid
+    struct StubNodeDefs;
+
+    impl NodeDefProvider for StubNodeDefs {
+        fn is_walkable(&self, name: &str) -> bool {
+            name != "air"
+        }
+    }
+
+    #[test]
+    fn solid_mask() {
+        let mut block = uniform_block("air");
+        block.name_id_mapping.insert(1, "default:stone".to_string());
+        // Bottom layer (y = 0) of stone, and a node with an unmapped id above it
+        for z in 0..16 {
+            for x in 0..16 {
+                block.param0[z * 256 + x] = 1;
+            }
+        }
+        block.param0[16] = 7;
+
+        let mask = block.solid_mask(&StubNodeDefs);
+        for (index, solid) in mask.iter().enumerate() {
+            let y = (index / 16) % 16;
+            assert_eq!(*solid, y == 0 || index == 16, "node {}", index);
+        }
+        assert!(!uniform_block("air")
+            .solid_mask(&StubNodeDefs)
+            .contains(&true));
+    }
+
+    /// Compresses a block of air with the given header fields, as Luanti would store it (minus the version byte)
+    fn crafted_block(flags: u8, lighting_complete: u16, timestamp: u32) -> Vec<u8> {
+        let mut data = vec![flags];
+        data.extend_from_slice(&lighting_complete.to_be_bytes());
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        // name-id mapping: version 0, a single entry 0 -> "air"
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 3]);
+        data.extend_from_slice(b"air");
+        data.extend_from_slice(&[2, 2]);
+        data.extend_from_slice(&[0; NODE_COUNT * 4]);
+        // No metadata, static objects version 0 with none, no timers
+        data.extend_from_slice(&[0, 0, 0, 0, 10, 0, 0]);
+        zstd::stream::encode_all(data.as_slice(), 0).unwrap()
+    }
+
+    #[test]
+    fn deserialize_header_only() {
+        let header =
+            MapBlock29::deserialize_header_only(&crafted_block(0x08, 0x0fff, 86400)).unwrap();
+        assert_eq!(
+            header,
+            MapBlock29Header {
+                flags: 0x08,
+                lighting_complete: 0x0fff,
+                timestamp: 86400
+            }
+        );
+        assert!(!header.was_generated());
+
+        for coord in [335560685, 218136553, 218128370] {
+            let data = sample_block(coord);
+            let block = MapBlock29::deserialize(&data[1..]).unwrap();
+            assert_eq!(
+                MapBlock29::deserialize_header_only(&data[1..]).unwrap(),
+                block.header()
+            );
+        }
+
+        // The header in a zstd block of its own, followed by a block whose contents are garbage
+        let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 0).unwrap();
+        encoder.write_all(&[0, 0xff, 0xff, 0, 0, 0, 1]).unwrap();
+        encoder.flush().unwrap();
+        let header_len = encoder.get_ref().len();
+        encoder.write_all(&[0; NODE_COUNT * 4]).unwrap();
+        let mut data = encoder.finish().unwrap();
+        data[header_len + 3..].fill(0xa5);
+
+        assert!(MapBlock29::deserialize(&data).is_err());
+        let header = MapBlock29::deserialize_header_only(&data).unwrap();
+        assert!(header.was_generated());
+        assert_eq!(header.timestamp, 1);
+
+        assert_eq!(
+            MapBlock29::deserialize_header_only(
+                &zstd::stream::encode_all(&[0, 0xff][..], 0).unwrap()
+            ),
+            Err(BlockDeserializeError::Truncated("header"))
+        );
+    }
+
+    #[test]
+    fn builder() {
+        let block = MapBlock29Builder::new()
+            .with_fill("ignore")
+            .set_node(0, 0, 0, "default:stone", 0)
+            .set_node(15, 8, 3, "default:chest", 3)
+            .build();
+        assert!(block.was_generated());
+        assert!(block
+            .name_id_mapping()
+            .values()
+            .any(|name| name == "ignore"));
+
+        // Survives serialization, with every node left unset resolving to the fill
+        let block = MapBlock29::deserialize(&block.serialize()[1..]).unwrap();
+        assert_eq!(block.node_name_at(0, 0, 0), Some("default:stone"));
+        assert_eq!(block.node_name_at(15, 8, 3), Some("default:chest"));
+        assert_eq!(block.node_at(15, 8, 3).param2, 3);
+        let ignored = block
+            .iter_nodes()
+            .filter(|node| node.name == "ignore")
+            .count();
+        assert_eq!(ignored, NODE_COUNT - 2);
+
+        // Air unless told otherwise
+        let block = MapBlock29Builder::new()
+            .set_node(1, 2, 3, "default:dirt", 0)
+            .build();
+        assert_eq!(block.node_name_at(1, 2, 4), Some("air"));
+        assert_eq!(block.node_name_at(1, 2, 3), Some("default:dirt"));
+    }
+
+    #[test]
+    fn unmapped_content() {
+        // An empty mapping is legitimate, and its nodes read as unknown
+        let mut block = uniform_block("air");
+        block.name_id_mapping.clear();
+        let block = MapBlock29::deserialize(&block.serialize()[1..]).unwrap();
+        assert!(block.name_id_mapping().is_empty());
+        assert_eq!(block.content_name(0), None);
+        assert_eq!(block.node_name_at(4, 5, 6), None);
+        assert!(block.iter_nodes().all(|node| node.name == "unknown"));
+
+        // A mapping that claims more entries than it holds is corrupt
+        assert!(read_name_id_mapping(&mut BlockReader::new(&[0, 0, 0]))
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            read_name_id_mapping(&mut BlockReader::new(&[0, 0, 1, 0, 0])),
+            Err(BlockDeserializeError::Truncated("name-id mapping"))
+        ));
+    }
+
+    #[test]
+    fn iter_nodes() {
+        let mut block = uniform_block("air");
+        block.set_node_at(15, 15, 15, "default:chest", 2);
+        let nodes: Vec<NodeEntry> = block.iter_nodes().collect();
+        assert_eq!(nodes.len(), NODE_COUNT);
+        assert_eq!(
+            nodes[0],
+            NodeEntry {
+                pos: (0, 0, 0),
+                name: "air".to_string(),
+                param1: block.node_at(0, 0, 0).param1,
+                param2: 0
+            }
+        );
+        assert_eq!(
+            nodes[NODE_COUNT - 1],
+            NodeEntry {
+                pos: (15, 15, 15),
+                name: "default:chest".to_string(),
+                param1: 0,
+                param2: 2
+            }
+        );
+
+        // Every entry matches the node at its position
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        for node in block.iter_nodes() {
+            let (x, y, z) = node.pos;
+            let expected = block.node_at(x, y, z);
+            assert_eq!(
+                Some(node.name.as_str()),
+                block.content_name(expected.content)
+            );
+            assert_eq!(
+                (node.param1, node.param2),
+                (expected.param1, expected.param2)
+            );
+        }
+        assert_eq!(block.iter_nodes().nth(3107).unwrap().name, "flowers:viola");
+    }
+
+    #[test]
+    fn name_id_mapping() {
+        let block = MapBlock29::deserialize(&crafted_block(0, 0xffff, 0)).unwrap();
+        assert_eq!(
+            block.name_id_mapping(),
+            &HashMap::from([(0, "air".to_string())])
+        );
+
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        let mapping = block.name_id_mapping();
+        assert!(mapping.values().any(|name| name == "default:stone"));
+        assert!(block
+            .content_ids()
+            .iter()
+            .all(|id| mapping.contains_key(id)));
+
+        // A mapping cut off mid-name
+        let mut data = vec![0, 0xff, 0xff, 0, 0, 0, 0];
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 3]);
+        data.extend_from_slice(b"ai");
+        let data = zstd::stream::encode_all(data.as_slice(), 0).unwrap();
+        assert_eq!(
+            MapBlock29::deserialize(&data).err(),
+            Some(BlockDeserializeError::Truncated("name-id mapping"))
+        );
+    }
+
+    #[test]
+    fn name_id_mapping_version() {
+        let mut data = zstd::stream::decode_all(crafted_block(0, 0xffff, 0).as_slice()).unwrap();
+        // The mapping follows the flags, lighting_complete and timestamp
+        data[7] = 1;
+        let data = zstd::stream::encode_all(data.as_slice(), 0).unwrap();
+        assert_eq!(
+            MapBlock29::deserialize(&data).err(),
+            Some(BlockDeserializeError::UnsupportedVersion(1))
+        );
+    }
+
+    #[test]
+    fn node_metadata() {
+        let data = sample_block(335548405);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        let node_metadata = block.node_metadata().unwrap();
+
+        let chest = &node_metadata[&2907];
+        assert_eq!(block.node_name_at(11, 5, 11), Some("default:chest"));
+        assert_eq!(
+            chest.fields.get("infotext").map(String::as_str),
+            Some("\u{1b}(T@default)Chest\u{1b}E")
+        );
+        assert!(chest.private.is_empty());
+        let inventory = String::from_utf8_lossy(&chest.inventory);
+        assert!(inventory.starts_with("List main 32\n"));
+        assert!(inventory.contains("Item default:flint 3\n"));
+        assert!(inventory.ends_with("EndInventory\n"));
+
+        // A private var
+        let mut metadata = vec![0, 0, 0, 1, 0, 5];
+        metadata.extend_from_slice(b"owner");
+        metadata.extend_from_slice(&[0, 0, 0, 3]);
+        metadata.extend_from_slice(b"sam");
+        metadata.push(1);
+        metadata.extend_from_slice(b"EndInventory\n");
+        let mut block = uniform_block("default:chest_locked");
+        block.node_metadata = vec![(0, metadata)];
+        let node_metadata = block.node_metadata().unwrap();
+        assert_eq!(node_metadata[&0].fields["owner"], "sam");
+        assert!(node_metadata[&0].private.contains("owner"));
+
+        assert!(uniform_block("air").node_metadata().unwrap().is_empty());
+    }
+
+    #[test]
+    fn static_objects() {
+        // A dropped item: a Lua entity (type 7) holding its entity name and Lua state
+        let mut item = vec![1, 0, 14];
+        item.extend_from_slice(b"__builtin:item");
+        let state = b"return {[\"itemstring\"] = \"default:dirt 5\"}";
+        item.extend_from_slice(&(state.len() as u32).to_be_bytes());
+        item.extend_from_slice(state);
+
+        let mut static_objects = vec![0, 0, 1, 7];
+        for component in [12500, -3000, 160250] {
+            static_objects.extend_from_slice(&i32::to_be_bytes(component));
+        }
+        static_objects.extend_from_slice(&(item.len() as u16).to_be_bytes());
+        static_objects.extend_from_slice(&item);
+        let mut block = uniform_block("air");
+        block.static_objects = static_objects.clone();
+
+        let data = block.serialize();
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert_eq!(
+            block.static_objects().unwrap(),
+            [StaticObject {
+                object_type: 7,
+                position: [12.5, -3.0, 160.25],
+                data: item,
+            }]
+        );
+        assert!(uniform_block("air").static_objects().unwrap().is_empty());
+
+        // Claims more objects than there is data for
+        let mut block = uniform_block("air");
+        static_objects[2] = 9;
+        block.static_objects = static_objects;
+        assert!(matches!(
+            block.static_objects(),
+            Err(WorldError::CorruptData(_))
+        ));
+    }
+
+    #[test]
+    fn node_timers() {
+        let data = sample_block(285216758);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert_eq!(
+            block.node_timers().unwrap(),
+            [NodeTimer {
+                index: 3123,
+                timeout: 1000,
+                elapsed: 399
+            }]
+        );
+        assert_eq!(
+            block.content_name(block.content_ids()[3123]),
+            Some("fireflies:hidden_firefly")
+        );
+        assert!(uniform_block("air").node_timers().unwrap().is_empty());
+
+        // Two timers declared, none there
+        let mut block = uniform_block("air");
+        block.node_timers = vec![10, 0, 2];
+        assert!(matches!(
+            block.node_timers(),
+            Err(BlockDeserializeError::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn timestamp() {
+        let block = MapBlock29::deserialize(&crafted_block(0, 0xffff, 86400)).unwrap();
+        assert_eq!(block.timestamp(), 86400);
+        assert_eq!(block.timestamp_opt(), Some(86400));
+
+        let data = sample_block(218128370);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert_eq!(block.timestamp_opt(), Some(1));
+
+        // Never stamped
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert_eq!(block.timestamp(), 0xffffffff);
+        assert_eq!(block.timestamp_opt(), None);
+    }
+
+    #[test]
+    fn lighting_complete_mask() {
+        let block = MapBlock29::deserialize(&crafted_block(0, 0xf7be, 0)).unwrap();
+        assert_eq!(block.lighting_complete_mask(), 0xf7be);
+
+        let block = MapBlock29::deserialize(&crafted_block(0, 0xffff, 0)).unwrap();
+        assert_eq!(block.lighting_complete_mask(), 0xffff);
+    }
+
+    #[test]
+    fn light_complete() {
+        use crate::Coordinate;
+
+        let faces = [
+            SpatialCoordinate::right(),
+            SpatialCoordinate::up(),
+            SpatialCoordinate::forward(),
+            SpatialCoordinate::back(),
+            SpatialCoordinate::down(),
+            SpatialCoordinate::left(),
+        ];
+        for (bit, face) in faces.iter().enumerate() {
+            // Only the face's flag set, in the day and then the night bank
+            let day = MapBlock29::deserialize(&crafted_block(0, 1 << bit, 0)).unwrap();
+            let night = MapBlock29::deserialize(&crafted_block(0, 1 << (bit + 6), 0)).unwrap();
+            for other in &faces {
+                let expected = other == face;
+                assert_eq!(day.light_complete(LightBank::Day, *other), expected);
+                assert!(!day.light_complete(LightBank::Night, *other));
+                assert_eq!(night.light_complete(LightBank::Night, *other), expected);
+                assert!(!night.light_complete(LightBank::Day, *other));
+            }
+        }
+
+        let block = MapBlock29::deserialize(&crafted_block(0, 0xffff, 0)).unwrap();
+        assert!(block.light_complete(LightBank::Night, SpatialCoordinate::down()));
+        assert!(!block.light_complete(LightBank::Day, SpatialCoordinate::zero()));
+        assert!(!block.light_complete(LightBank::Day, SpatialCoordinate::new(1, 1, 0)));
+    }
+
+    #[test]
+    fn content_eq() {
+        let earlier = crafted_block(0, 0xffff, 1000);
+        let later = crafted_block(0, 0xffff, 2000);
+        assert_ne!(earlier, later);
+
+        let earlier = MapBlock29::deserialize(&earlier).unwrap();
+        let mut later = MapBlock29::deserialize(&later).unwrap();
+        assert!(earlier.content_eq(&later));
+
+        // Light changes don't count, nodes do
+        later.param1[0] = 0xff;
+        assert!(earlier.content_eq(&later));
+        later.name_id_mapping.insert(1, "default:stone".to_string());
+        later.param0[0] = 1;
+        assert!(!earlier.content_eq(&later));
+
+        // The same content under a different content id
+        let mut renumbered = MapBlock29::deserialize(&crafted_block(0, 0xffff, 0)).unwrap();
+        renumbered.name_id_mapping = HashMap::from([(5, "air".to_string())]);
+        renumbered.param0 = vec![5; NODE_COUNT];
+        assert!(earlier.content_eq(&renumbered));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn set_node() {
+        let mut block = MapBlock29::deserialize(&crafted_block(0, 0xffff, 0)).unwrap();
+        block.node_timers.clear();
+        write_node_timers(
+            &mut block.node_timers,
+            &[NodeTimer {
+                index: 17,
+                timeout: 1000,
+                elapsed: 0,
+            }],
+        );
+        block
+            .node_metadata
+            .push((17, b"\0\0\0\0EndInventory\n".to_vec()));
+
+        assert!(block.set_node(17, "default:chest", 3));
+        assert!(!block.set_node(17, "default:chest", 3));
+        assert!(block.set_node(18, "default:chest", 0));
+        assert!(block.node_timers().unwrap().is_empty());
+        assert!(block.node_metadata.is_empty());
+        assert_eq!(block.lighting_complete_mask(), 0xf000);
+
+        let reparsed = MapBlock29::deserialize(&block.serialize()[1..]).unwrap();
+        assert!(reparsed.content_eq(&block));
+        assert_eq!(
+            reparsed.content_name(reparsed.param0[17]),
+            Some("default:chest")
+        );
+        assert_eq!(reparsed.param2[17], 3);
+        assert_eq!(reparsed.content_name(reparsed.param0[0]), Some("air"));
+    }
+
+    #[test]
+    fn to_version() {
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+
+        let v28 = block.to_version(28).unwrap();
+        assert_eq!(v28[0], 28);
+        let downgraded = MapBlock29::deserialize_legacy(&v28[1..], 28).unwrap();
+        assert_eq!(downgraded.param0, block.param0);
+        assert!(downgraded.content_eq(&block));
+        assert_eq!(downgraded.flags, block.flags);
+        assert_eq!(downgraded.timestamp, block.timestamp);
+        assert_eq!(downgraded.lighting_complete, block.lighting_complete);
+        assert_eq!(downgraded.param1, block.param1);
+
+        assert_eq!(block.to_version(29).unwrap(), block.serialize());
+        assert_eq!(
+            block.to_version(25).err(),
+            Some(BlockDeserializeError::UnsupportedVersion(25))
+        );
+    }
+
+    #[test]
+    fn legacy_node_metadata() {
+        // One private var, "text" = "hi", and an empty inventory
+        let mut metadata = vec![0, 0, 0, 1, 0, 4];
+        metadata.extend_from_slice(b"text");
+        metadata.extend_from_slice(&[0, 0, 0, 2]);
+        metadata.extend_from_slice(b"hi");
+        metadata.push(1);
+        metadata.extend_from_slice(b"EndInventory\n");
+        let mut block = uniform_block("default:chest");
+        block.node_metadata = vec![(273, metadata.clone())];
+
+        let v28 = block.to_version(28).unwrap();
+        let read = MapBlock29::deserialize_legacy(&v28[1..], 28).unwrap();
+        assert_eq!(read.node_metadata, block.node_metadata);
+
+        // Version 27 has no private flag, so the var comes back public
+        let v27 = block.to_version(27).unwrap();
+        let read = MapBlock29::deserialize_legacy(&v27[1..], 27).unwrap();
+        // The private flag follows the var count, key and value
+        metadata[16] = 0;
+        assert_eq!(read.node_metadata, vec![(273, metadata)]);
+        assert_eq!(read.lighting_complete, block.lighting_complete);
+
+        // Version 26 has no lighting_complete either
+        block.lighting_complete = 0xf000;
+        let v26 = block.to_version(26).unwrap();
+        assert_eq!(v26.len(), v27.len() - 2);
+        let read = MapBlock29::deserialize_legacy(&v26[1..], 26).unwrap();
+        assert_eq!(read.lighting_complete, 0xffff);
+        assert_eq!(read.param0, block.param0);
+        assert_eq!(read.node_metadata.len(), 1);
+
+        assert_eq!(
+            MapBlock29::deserialize_legacy(&v26[1..], 25).err(),
+            Some(BlockDeserializeError::UnsupportedVersion(25))
+        );
+    }
+
+    #[test]
+    fn deserialize_all_samples() {
+        let db = Connection::open("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let mut stmt = db.prepare("SELECT data FROM blocks").unwrap();
+        let blocks = stmt.query_map([], |row| row.get::<_, Vec<u8>>(0)).unwrap();
+        for data in blocks {
+            let data = data.unwrap();
+            MapBlock29::deserialize(&data[1..]).unwrap();
+        }
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        // Terrain, a chest with metadata, and a block with a node timer
+        for pos in [335560685, 335548405, 285216758] {
+            let data = sample_block(pos);
+            let block = MapBlock29::deserialize(&data[1..]).unwrap();
+            let serialized = block.serialize();
+            assert_eq!(serialized[0], 29);
+            let reparsed = MapBlock29::deserialize(&serialized[1..]).unwrap();
+
+            assert_eq!(reparsed.param0, block.param0);
+            assert_eq!(reparsed.param1, block.param1);
+            assert_eq!(reparsed.param2, block.param2);
+            assert_eq!(reparsed.name_id_mapping, block.name_id_mapping);
+            assert_eq!(reparsed.node_metadata, block.node_metadata);
+            assert_eq!(reparsed.static_objects, block.static_objects);
+            assert_eq!(reparsed.node_timers, block.node_timers);
+            assert_eq!(reparsed.flags, block.flags);
+            assert_eq!(reparsed.lighting_complete, block.lighting_complete);
+            assert_eq!(reparsed.timestamp, block.timestamp);
+            // Serializing is deterministic
+            assert_eq!(reparsed.serialize(), serialized);
+        }
+    }
+
+    #[test]
+    fn node_count_mismatch() {
+        let mut data = vec![0, 0xff, 0xff, 0, 0, 0, 0];
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 3]);
+        data.extend_from_slice(b"air");
+        data.extend_from_slice(&[2, 2]);
+        data.extend_from_slice(&[0; 100 * 4]);
+        let data = zstd::stream::encode_all(data.as_slice(), 0).unwrap();
+
+        assert_eq!(
+            MapBlock29::deserialize(&data).err(),
+            Some(BlockDeserializeError::NodeCountMismatch {
+                expected: NODE_COUNT,
+                got: 100
+            })
+        );
+    }
+
+    #[test]
+    fn content_ids() {
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        let ids = block.content_ids();
+        assert_eq!(ids.len(), NODE_COUNT);
+        // Node (3, 2, 12) is the only viola in the block
+        assert_eq!(block.content_name(ids[3107]), Some("flowers:viola"));
+        assert_eq!(block.content_name(ids[527]), Some("default:stone"));
+        assert_eq!(block.content_name(ids[0]), Some("air"));
+    }
+
+    #[test]
+    fn node_order_corners() {
+        let mut block = MapBlock29::deserialize(&crafted_block(0, 0xffff, 0)).unwrap();
+        let corners: Vec<(u8, u8, u8)> = (0..8)
+            .map(|corner| {
+                let side = |bit: u8| if corner & bit == 0 { 0 } else { 15 };
+                (side(1), side(2), side(4))
+            })
+            .collect();
+        for (x, y, z) in corners.iter() {
+            block.set_node_at(*x, *y, *z, &format!("test:corner_{}_{}_{}", x, y, z), 0);
+        }
+
+        let block = MapBlock29::deserialize(&block.serialize()[1..]).unwrap();
+        for (x, y, z) in corners.iter() {
+            assert_eq!(
+                block.node_name_at(*x, *y, *z),
+                Some(format!("test:corner_{}_{}_{}", x, y, z).as_str())
+            );
+        }
+        // X varies fastest in storage, then Y, then Z
+        let name = |index: usize| block.content_name(block.content_ids()[index]);
+        assert_eq!(name(15), Some("test:corner_15_0_0"));
+        assert_eq!(name(15 * 16), Some("test:corner_0_15_0"));
+        assert_eq!(name(15 * 256), Some("test:corner_0_0_15"));
+        assert_eq!(name(1), Some("air"));
+    }
+
+    #[test]
+    fn deserialize_truncated() {
+        let data = sample_block(335560685);
+        assert!(MapBlock29::deserialize(&data[1..data.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn nodes_with_day_night_diff() {
+        let data = sample_block(335560685);
+        let block = MapBlock29::deserialize(&data[1..]).unwrap();
+        assert!(block.day_night_differs());
+
+        let differing = block.nodes_with_day_night_diff();
+        assert!(!differing.is_empty());
+        assert!(differing.windows(2).all(|pair| pair[0] < pair[1]));
+        for index in differing.iter() {
+            let light = block.param1[*index];
+            assert_ne!(light & 0x0f, light >> 4);
+        }
+        let uniform = (0..NODE_COUNT).filter(|index| !differing.contains(index));
+        for index in uniform {
+            let light = block.param1[index];
+            assert_eq!(light & 0x0f, light >> 4);
+        }
+    }
+}
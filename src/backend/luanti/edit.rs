@@ -0,0 +1,441 @@
+// Bulk edits over a Luanti map
+
+use crate::{Area, Coordinate, SpatialCoordinate, WorldError};
+
+use super::{
+    block_serialization::{decode_block_at, decode_block_header_at, read_block, MapBlockData},
+    map::{HashedCoordinate, MapReader, MapWriter},
+};
+
+type Scalar = <SpatialCoordinate as Coordinate>::Scalar;
+
+/// Sets every node within an area to the same node, like WorldEdit's `//set`
+///
+/// Only blocks already in the map are edited - Ungenerated parts of the area, and any part beyond
+/// Luanti's map limits, are skipped. Metadata and timers of replaced nodes are dropped.
+///
+/// # Arguments
+/// - `map` - The map to edit
+/// - `area` - The nodes to set, inclusive of both corners, which may be given in either order
+/// - `name` - Name of the node to place, e.g. `default:stone`
+/// - `param2` - param2 of the placed nodes
+///
+/// # Returns
+/// - How many nodes changed
+pub fn fill_area<RW: MapReader + MapWriter>(
+    map: &RW,
+    area: &Area,
+    name: &str,
+    param2: u8,
+) -> Result<u64, WorldError> {
+    let Area { from, to } = area.normalized();
+    let mut changed = 0;
+    for block_x in from.x.div_euclid(16)..=to.x.div_euclid(16) {
+        for block_y in from.y.div_euclid(16)..=to.y.div_euclid(16) {
+            for block_z in from.z.div_euclid(16)..=to.z.div_euclid(16) {
+                let position = (
+                    i16::try_from(block_x),
+                    i16::try_from(block_y),
+                    i16::try_from(block_z),
+                );
+                let coord = match position {
+                    (Ok(x), Ok(y), Ok(z)) => match HashedCoordinate::at(x, y, z) {
+                        Ok(coord) => coord,
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+                if !map.block_exists(coord)? {
+                    continue;
+                }
+
+                let mut block = read_block(map, coord)?;
+                let mut block_changed = 0;
+                // The part of the area within this block, in block-local coordinates
+                let local = |node: Scalar, block: Scalar| (node - block * 16).clamp(0, 15) as u8;
+                for z in local(from.z, block_z)..=local(to.z, block_z) {
+                    for y in local(from.y, block_y)..=local(to.y, block_y) {
+                        for x in local(from.x, block_x)..=local(to.x, block_x) {
+                            if block.set_node_at(x, y, z, name, param2) {
+                                block_changed += 1;
+                            }
+                        }
+                    }
+                }
+                if block_changed > 0 {
+                    map.set_block(coord, &block.serialize())?;
+                    changed += block_changed;
+                }
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Replaces one kind of node with another across the whole map
+///
+/// The replaced nodes keep their params, metadata and timers. Blocks without any `from` node are
+/// left untouched, and changed blocks are written back in batches.
+///
+/// # Arguments
+/// - `map` - The map to edit
+/// - `from` - Name of the node to replace, e.g. `oldmod:stone`
+/// - `to` - Name of the node to put in its place
+///
+/// # Returns
+/// - How many nodes were replaced
+pub fn replace_node_world<RW: MapReader + MapWriter>(
+    map: &RW,
+    from: &str,
+    to: &str,
+) -> Result<u64, WorldError> {
+    // Changed blocks held back before being written in one go
+    const BATCH_SIZE: usize = 256;
+
+    let mut replaced = 0;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    for coord in map.blocks_iter()? {
+        let coord = coord?;
+        let mut block = read_block(map, coord)?;
+        let count = block.replace_content(from, to);
+        if count == 0 {
+            continue;
+        }
+        batch.push((coord, block.serialize()));
+        replaced += count;
+        if batch.len() == BATCH_SIZE {
+            map.set_blocks(&batch)?;
+            batch.clear();
+        }
+    }
+    map.set_blocks(&batch)?;
+    Ok(replaced)
+}
+
+/// Removes the placeholder blocks Luanti saved for areas it never generated
+///
+/// A block is removed if it is flagged as not generated and holds nothing but `ignore` or `air`.
+/// Ungenerated blocks with other content, such as terrain spilling over from a neighbouring mapchunk,
+/// are kept. Follow up with `SQLite3MapReader::vacuum` to shrink the database file.
+///
+/// # Arguments
+/// - `map` - The map to trim
+///
+/// # Returns
+/// - How many blocks were removed
+pub fn trim_ungenerated<RW: MapReader + MapWriter>(map: &RW) -> Result<u64, WorldError> {
+    let mut removed = 0;
+    for coord in map.blocks()? {
+        let data = map.get_block(coord)?;
+        // Most blocks are generated, and those are settled by the header alone
+        if decode_block_header_at(coord, &data)?.was_generated() {
+            continue;
+        }
+        let block = decode_block_at(coord, &data)?;
+        let empty = block.is_all_ignore()
+            || (block.is_uniform() && block.node_name_at(0, 0, 0) == Some("air"));
+        if empty {
+            map.remove_block(coord)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Which block to keep when both maps being merged have one at the same position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergePolicy {
+    /// A's block
+    PreferA,
+    /// B's block
+    PreferB,
+    /// The block the mapgen has finished, falling back to A's if both or neither are generated
+    PreferGenerated,
+}
+
+/// What `merge_worlds` copied, in blocks
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MergeReport {
+    /// Blocks only A has
+    pub only_a: u64,
+    /// Blocks only B has
+    pub only_b: u64,
+    /// Blocks both have with the same data
+    pub identical: u64,
+    /// Blocks both have with different data, resolved to A's
+    pub conflicts_from_a: u64,
+    /// Blocks both have with different data, resolved to B's
+    pub conflicts_from_b: u64,
+}
+
+/// Merges two maps into a third, e.g. to combine the worlds of two players
+///
+/// # Arguments
+/// - `a` - The first map
+/// - `b` - The second map
+/// - `out` - The map to write the merged blocks to. Blocks it already has are overwritten.
+/// - `policy` - Which block to keep where the maps overlap
+///
+/// # Returns
+/// - How many blocks were copied from where
+///
+/// # Errors
+/// - `WorldError::CorruptPartition` - With `PreferGenerated`, if an overlapping block could not be decoded
+pub fn merge_worlds<R1: MapReader, R2: MapReader, W: MapWriter>(
+    a: &R1,
+    b: &R2,
+    out: &W,
+    policy: MergePolicy,
+) -> Result<MergeReport, WorldError> {
+    let mut report = MergeReport::default();
+    for coord in a.blocks_iter()? {
+        let coord = coord?;
+        let data_a = a.get_block(coord)?;
+        if !b.block_exists(coord)? {
+            out.set_block(coord, &data_a)?;
+            report.only_a += 1;
+            continue;
+        }
+
+        let data_b = b.get_block(coord)?;
+        if data_a == data_b {
+            out.set_block(coord, &data_a)?;
+            report.identical += 1;
+            continue;
+        }
+        let prefer_a = match policy {
+            MergePolicy::PreferA => true,
+            MergePolicy::PreferB => false,
+            MergePolicy::PreferGenerated => {
+                decode_block_header_at(coord, &data_a)?.was_generated()
+                    || !decode_block_header_at(coord, &data_b)?.was_generated()
+            }
+        };
+        if prefer_a {
+            out.set_block(coord, &data_a)?;
+            report.conflicts_from_a += 1;
+        } else {
+            out.set_block(coord, &data_b)?;
+            report.conflicts_from_b += 1;
+        }
+    }
+
+    for coord in b.blocks_iter()? {
+        let coord = coord?;
+        if !a.block_exists(coord)? {
+            out.set_block(coord, &b.get_block(coord)?)?;
+            report.only_b += 1;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod luanti_edit_tests {
+    use super::*;
+    use crate::backend::luanti::map::{MemoryMapReader, SQLite3MapReader};
+
+    #[test]
+    fn fill_area() {
+        let terrain = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite")
+            .unwrap()
+            .get_block(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        let map = MemoryMapReader::new();
+        let left = HashedCoordinate::at(0, 0, 0).unwrap();
+        let right = HashedCoordinate::at(1, 0, 0).unwrap();
+        map.set_block(left, &terrain).unwrap();
+        map.set_block(right, &terrain).unwrap();
+
+        // Spans the two blocks, and reaches up into the missing block above them
+        let area = Area {
+            from: SpatialCoordinate { x: 14, y: 2, z: 3 },
+            to: SpatialCoordinate { x: 17, y: 17, z: 5 },
+        };
+        assert_eq!(
+            super::fill_area(&map, &area, "test:marker", 7).unwrap(),
+            4 * 14 * 3
+        );
+        assert!(!map
+            .block_exists(HashedCoordinate::at(0, 1, 0).unwrap())
+            .unwrap());
+        // Nothing left to change
+        assert_eq!(super::fill_area(&map, &area, "test:marker", 7).unwrap(), 0);
+
+        let node = |coord: HashedCoordinate, x: u8, y: u8, z: u8| {
+            let block = read_block(&map, coord).unwrap();
+            block.node_name_at(x, y, z).unwrap().to_string()
+        };
+        assert_eq!(node(left, 14, 2, 3), "test:marker");
+        assert_eq!(node(left, 15, 15, 5), "test:marker");
+        assert_eq!(node(right, 0, 9, 4), "test:marker");
+        assert_eq!(node(right, 1, 2, 5), "test:marker");
+        assert_ne!(node(left, 13, 2, 3), "test:marker");
+        assert_ne!(node(right, 2, 2, 3), "test:marker");
+        assert_ne!(node(right, 1, 1, 3), "test:marker");
+        assert_ne!(node(right, 1, 2, 6), "test:marker");
+
+        // The same area with its corners swapped
+        let swapped = Area {
+            from: area.to,
+            to: area.from,
+        };
+        assert_eq!(
+            super::fill_area(&map, &swapped, "test:other", 0).unwrap(),
+            4 * 14 * 3
+        );
+        assert_eq!(node(left, 14, 2, 3), "test:other");
+        assert_eq!(node(right, 1, 2, 5), "test:other");
+    }
+
+    #[test]
+    fn replace_node_world() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let terrain = sample
+            .get_block(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        let placeholder = sample
+            .get_block(HashedCoordinate { value: 218136553 })
+            .unwrap();
+        let map = SQLite3MapReader::open_memory().unwrap();
+        let blocks = [
+            HashedCoordinate::at(0, 0, 0).unwrap(),
+            HashedCoordinate::at(0, 1, 0).unwrap(),
+            HashedCoordinate::at(-3, 0, 7).unwrap(),
+        ];
+        for coord in blocks {
+            map.set_block(coord, &terrain).unwrap();
+        }
+        let untouched = HashedCoordinate::at(0, 0, 1).unwrap();
+        map.set_block(untouched, &placeholder).unwrap();
+
+        let count = |name: &str| {
+            let block = read_block(&sample, HashedCoordinate { value: 335560685 }).unwrap();
+            block
+                .content_ids()
+                .iter()
+                .filter(|id| block.content_name(**id) == Some(name))
+                .count() as u64
+        };
+        let stone = count("default:stone");
+        let dirt = count("default:dirt");
+        assert!(stone > 0 && dirt > 0);
+
+        // Into a node the blocks already have, and into a new one
+        assert_eq!(
+            super::replace_node_world(&map, "default:stone", "default:dirt").unwrap(),
+            3 * stone
+        );
+        assert_eq!(
+            super::replace_node_world(&map, "flowers:viola", "test:violet").unwrap(),
+            3 * count("flowers:viola")
+        );
+        assert_eq!(
+            super::replace_node_world(&map, "default:stone", "default:dirt").unwrap(),
+            0
+        );
+
+        for coord in blocks {
+            let block = read_block(&map, coord).unwrap();
+            assert_eq!(block.node_name_at(15, 0, 2), Some("default:dirt"));
+            assert_eq!(block.node_name_at(3, 2, 12), Some("test:violet"));
+            let dirt_now = block
+                .content_ids()
+                .iter()
+                .filter(|id| block.content_name(**id) == Some("default:dirt"))
+                .count() as u64;
+            assert_eq!(dirt_now, stone + dirt);
+        }
+        assert_eq!(map.get_block(untouched).unwrap(), placeholder);
+    }
+
+    #[test]
+    fn merge_worlds() {
+        let a = MemoryMapReader::new();
+        let b = MemoryMapReader::new();
+        let only_a = HashedCoordinate::at(0, 0, 0).unwrap();
+        let only_b = HashedCoordinate::at(5, 0, 0).unwrap();
+        let shared = HashedCoordinate::at(1, 2, 3).unwrap();
+        let same = HashedCoordinate::at(-1, 0, 0).unwrap();
+        a.set_block(only_a, &vec![29, 1]).unwrap();
+        a.set_block(shared, &vec![29, 2]).unwrap();
+        a.set_block(same, &vec![29, 4]).unwrap();
+        b.set_block(only_b, &vec![29, 3]).unwrap();
+        b.set_block(shared, &vec![29, 20]).unwrap();
+        b.set_block(same, &vec![29, 4]).unwrap();
+
+        let out = MemoryMapReader::new();
+        let report = super::merge_worlds(&a, &b, &out, MergePolicy::PreferB).unwrap();
+        assert_eq!(
+            report,
+            MergeReport {
+                only_a: 1,
+                only_b: 1,
+                identical: 1,
+                conflicts_from_a: 0,
+                conflicts_from_b: 1,
+            }
+        );
+        assert_eq!(out.get_block(shared).unwrap(), vec![29, 20]);
+        assert_eq!(out.get_block(only_a).unwrap(), vec![29, 1]);
+        assert_eq!(out.get_block(only_b).unwrap(), vec![29, 3]);
+        assert_eq!(out.blocks().unwrap().len(), 4);
+
+        let out = MemoryMapReader::new();
+        let report = super::merge_worlds(&a, &b, &out, MergePolicy::PreferA).unwrap();
+        assert_eq!(report.conflicts_from_a, 1);
+        assert_eq!(out.get_block(shared).unwrap(), vec![29, 2]);
+    }
+
+    #[test]
+    fn merge_worlds_prefer_generated() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let generated = sample
+            .get_block(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        let placeholder = sample
+            .get_block(HashedCoordinate { value: 218136553 })
+            .unwrap();
+        let coord = HashedCoordinate::at(0, 0, 0).unwrap();
+
+        let a = MemoryMapReader::new();
+        let b = MemoryMapReader::new();
+        a.set_block(coord, &placeholder).unwrap();
+        b.set_block(coord, &generated).unwrap();
+        let out = MemoryMapReader::new();
+        let report = super::merge_worlds(&a, &b, &out, MergePolicy::PreferGenerated).unwrap();
+        assert_eq!(report.conflicts_from_b, 1);
+        assert_eq!(out.get_block(coord).unwrap(), generated);
+
+        // Neither is generated - A's is kept
+        let spilled = sample
+            .get_block(HashedCoordinate { value: 117424115 })
+            .unwrap();
+        a.set_block(coord, &spilled).unwrap();
+        b.set_block(coord, &placeholder).unwrap();
+        let report = super::merge_worlds(&a, &b, &out, MergePolicy::PreferGenerated).unwrap();
+        assert_eq!(report.conflicts_from_a, 1);
+        assert_eq!(out.get_block(coord).unwrap(), spilled);
+    }
+
+    #[test]
+    fn trim_ungenerated() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let map = SQLite3MapReader::open_memory().unwrap();
+        // Generated terrain, an ungenerated all-ignore placeholder, and ungenerated terrain
+        let kept = [335560685, 117424115].map(|value| HashedCoordinate { value });
+        let placeholder = HashedCoordinate { value: 218136553 };
+        for coord in kept.iter().chain([&placeholder]) {
+            map.set_block(*coord, &sample.get_block(*coord).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(super::trim_ungenerated(&map).unwrap(), 1);
+        let mut remaining = map.blocks().unwrap();
+        remaining.sort_by_key(|coord| coord.value);
+        assert_eq!(remaining, [kept[1], kept[0]]);
+
+        map.vacuum().unwrap();
+        assert_eq!(super::trim_ungenerated(&map).unwrap(), 0);
+    }
+}
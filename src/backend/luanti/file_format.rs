@@ -4,6 +4,13 @@ use std::collections::HashMap;
 // key=value
 // key2 = value2
 // key3 = value3 - Comment
+// key4 = """
+// multi-line
+// value
+// """
+/// Opens and closes a multi-line value, as in `key = """`
+const MULTILINE_DELIMITER: &str = "\"\"\"";
+
 #[derive(Debug, Clone)]
 pub struct KeyValue {
     data: Vec<(String, String)>,
@@ -16,7 +23,12 @@ impl KeyValue {
 
     pub fn from(serialized: &str) -> KeyValue {
         let mut data = Vec::new();
-        for line in serialized.lines() {
+        // `lines()` already splits on "\r\n", but a lone trailing '\r' (e.g. on the last line of a file
+        // written on Windows without a final newline) would otherwise end up in the value
+        let mut lines = serialized
+            .lines()
+            .map(|line| line.strip_suffix('\r').unwrap_or(line));
+        while let Some(line) = lines.next() {
             let line = line.trim();
             if line.is_empty() {
                 continue;
@@ -25,6 +37,19 @@ impl KeyValue {
             let key = parts.next().unwrap().trim();
             let value = parts.next().unwrap_or("").trim();
 
+            // Multi-line values are wrapped in `"""` - Everything up to the closing `"""` line is kept verbatim
+            if value == MULTILINE_DELIMITER {
+                let mut multiline = Vec::new();
+                for line in lines.by_ref() {
+                    if line.trim_end() == MULTILINE_DELIMITER {
+                        break;
+                    }
+                    multiline.push(line);
+                }
+                data.push((key.to_string(), multiline.join("\n")));
+                continue;
+            }
+
             // Ignore comments ("xyz - Comment" -> "xyz", the "-" symbol is the beginning of a comment)
             if let Some(comment_start) = value.find('-') {
                 let value = &value[..comment_start].trim();
@@ -40,7 +65,12 @@ impl KeyValue {
         // concat, not push
         let mut result = String::new();
         for (key, value) in self.data.iter() {
-            result = result + key + "=" + value + "\n";
+            if value.contains('\n') {
+                result = result + key + "=" + MULTILINE_DELIMITER + "\n" + value + "\n";
+                result = result + MULTILINE_DELIMITER + "\n";
+            } else {
+                result = result + key + "=" + value + "\n";
+            }
         }
 
         result
@@ -50,6 +80,14 @@ impl KeyValue {
         self.data.push((key, value));
     }
 
+    /// Sets the value of `key`, keeping its place if it is already present and appending it otherwise
+    pub fn set(&mut self, key: &str, value: String) {
+        match self.data.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.data.push((key.to_string(), value)),
+        }
+    }
+
     pub fn get(&self, key: &str) -> Option<String> {
         for (k, v) in self.data.iter() {
             if k == key {
@@ -107,6 +145,17 @@ mod key_value_tests {
         assert_eq!(kv.get("key5"), None);
     }
 
+    #[test]
+    fn test_key_value_set() {
+        let mut kv = KeyValue::from("gameid = minetest\nbackend = sqlite3\nworld_name = test\n");
+        kv.set("backend", "leveldb".to_string());
+        kv.set("server_announce", "false".to_string());
+        assert_eq!(
+            kv.as_str(),
+            "gameid=minetest\nbackend=leveldb\nworld_name=test\nserver_announce=false\n"
+        );
+    }
+
     #[test]
     fn test_key_value_from() {
         let bytes = r#"key1=value1
@@ -124,6 +173,51 @@ key5 = value5 - Comment
         assert_eq!(kv.get("key5"), Some("value5".to_string()));
     }
 
+    #[test]
+    fn test_key_value_from_multiline() {
+        let bytes = r#"key1 = value1
+key2 = """
+first line
+  second line - not a comment
+
+last line
+"""
+key3 = value3
+"#;
+
+        let kv = KeyValue::from(bytes);
+        assert_eq!(kv.get("key1"), Some("value1".to_string()));
+        assert_eq!(
+            kv.get("key2"),
+            Some("first line\n  second line - not a comment\n\nlast line".to_string())
+        );
+        assert_eq!(kv.get("key3"), Some("value3".to_string()));
+
+        // Serializing keeps the value whole
+        let reparsed = KeyValue::from(&kv.as_str());
+        assert_eq!(reparsed.get("key2"), kv.get("key2"));
+    }
+
+    #[test]
+    fn test_key_value_from_crlf() {
+        let lf = "gameid = minetest\nbackend = sqlite3 - Comment\nmotd = \"\"\"\nline one\nline two\n\"\"\"\nserver_announce = false";
+        let crlf = lf.replace('\n', "\r\n") + "\r";
+
+        let kv = KeyValue::from(&crlf);
+        for (_, value) in kv.clone() {
+            assert!(!value.ends_with('\r'), "{:?}", value);
+        }
+        assert_eq!(kv.get("backend"), Some("sqlite3".to_string()));
+        assert_eq!(kv.get("motd"), Some("line one\nline two".to_string()));
+        assert_eq!(kv.get("server_announce"), Some("false".to_string()));
+        assert_eq!(
+            kv.clone().collect::<Vec<_>>(),
+            KeyValue::from(lf).collect::<Vec<_>>()
+        );
+        assert_eq!(kv.as_str(), KeyValue::from(lf).as_str());
+        assert!(!kv.as_str().contains('\r'));
+    }
+
     #[test]
     fn test_key_value_as_str() {
         let mut kv = KeyValue::new();
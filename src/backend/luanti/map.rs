@@ -5,17 +5,42 @@
 /* -------------------------------------------------------------------------- */
 
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     fmt::Display,
     hash::Hash,
     ops::{Add, Sub},
 };
 
-use rusqlite::{params, Connection};
+use rusqlite::{params, params_from_iter, Connection, DatabaseName};
 
 use crate::{Coordinate, CoordinateError, SpatialCoordinate, WorldError};
 
-trait MapReader {
+mod files;
+pub use files::FilesMapReader;
+#[cfg(feature = "postgres")]
+mod postgresql;
+#[cfg(feature = "postgres")]
+pub use postgresql::PostgresMapReader;
+
+/// What a map backend can do efficiently, so generic code can pick the best approach
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities {
+    /// Blocks within a range of positions can be found without listing every block
+    pub supports_area_query: bool,
+    /// Many blocks can be looked up in one request, e.g. through `blocks_exist`
+    pub supports_batch: bool,
+    /// Blocks can be written and removed
+    pub writable: bool,
+}
+
+/// The lowest and highest block position of a map on each axis
+pub type BlockBounds = (SpatialCoordinate, SpatialCoordinate);
+
+pub trait MapReader {
+    /// Reports what the backend can do efficiently
+    fn capabilities(&self) -> Capabilities;
+
     /// Gets the block at the given coordinate
     ///
     /// # Arguments
@@ -25,6 +50,21 @@ trait MapReader {
     /// - The block data, or an error if the block is not found
     fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError>;
 
+    /// Gets the block at the given coordinate exactly as it is stored
+    ///
+    /// The data is never decoded or re-serialized, so it can be written back with `set_block_raw` to
+    /// restore the block byte for byte. Backends store blocks as opaque data, so this is `get_block`
+    /// under a name that promises it.
+    ///
+    /// # Arguments
+    /// - `coord` - The coordinate to get the block at
+    ///
+    /// # Returns
+    /// - The stored bytes, or an error if the block is not found
+    fn get_block_raw(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
+        self.get_block(coord)
+    }
+
     /// Checks if a block exists at the given coordinate
     ///
     /// # Arguments
@@ -34,14 +74,134 @@ trait MapReader {
     /// - True if the block exists, false otherwise
     fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError>;
 
+    /// Gets many blocks at once
+    ///
+    /// # Arguments
+    /// - `coords` - The coordinates to get the blocks at
+    ///
+    /// # Returns
+    /// - The data of each block in `coords` that exists, keyed by its coordinate
+    fn get_blocks(
+        &self,
+        coords: &[HashedCoordinate],
+    ) -> Result<HashMap<HashedCoordinate, Vec<u8>>, WorldError> {
+        let mut blocks = HashMap::new();
+        for coord in coords {
+            match self.get_block(*coord) {
+                Ok(data) => {
+                    blocks.insert(*coord, data);
+                }
+                Err(WorldError::PartitionNotFound(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Checks which of the given coordinates have a block
+    ///
+    /// # Arguments
+    /// - `coords` - The coordinates to check
+    ///
+    /// # Returns
+    /// - The subset of `coords` that exist
+    fn blocks_exist(
+        &self,
+        coords: &[HashedCoordinate],
+    ) -> Result<HashSet<HashedCoordinate>, WorldError> {
+        let mut existing = HashSet::new();
+        for coord in coords {
+            if self.block_exists(*coord)? {
+                existing.insert(*coord);
+            }
+        }
+        Ok(existing)
+    }
+
+    /// Steps through all blocks in the world, without holding every position in memory at once
+    ///
+    /// # Returns
+    /// - An iterator over all blocks in the world. Errors met while stepping are yielded by the iterator.
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>;
+
     /// Gets all blocks in the world
     ///
     /// # Returns
-    /// - An iterator over all blocks in the world
-    fn blocks(&self) -> Result<Vec<HashedCoordinate>, WorldError>;
+    /// - Every block in the world, as collected from `blocks_iter`
+    fn blocks(&self) -> Result<Vec<HashedCoordinate>, WorldError> {
+        self.blocks_iter()?.collect()
+    }
+
+    /// Gets the blocks whose packed position falls in `[lo, hi)`
+    ///
+    /// This is a range of the packed values, not a region of the world: a range spans whole z layers
+    /// and wraps around in y and x. Splitting the full `i64` range into pieces divides the blocks between
+    /// them, e.g. to hand each piece to a different worker.
+    ///
+    /// # Arguments
+    /// - `lo` - The lowest packed position to include
+    /// - `hi` - The packed position to stop before
+    ///
+    /// # Returns
+    /// - The blocks in the range, in no particular order
+    fn blocks_in_hash_range(&self, lo: i64, hi: i64) -> Result<Vec<HashedCoordinate>, WorldError> {
+        self.blocks_iter()?
+            .filter(|coord| {
+                coord
+                    .as_ref()
+                    .map_or(true, |coord| (lo..hi).contains(&coord.value))
+            })
+            .collect()
+    }
+
+    /// Finds the block-space bounding box of the map, from the block positions alone
+    ///
+    /// Unlike [`world_bounds`](super::analysis::world_bounds), no block data is read or decoded.
+    ///
+    /// # Returns
+    /// - The lowest and highest block position on each axis, or None if the map has no blocks
+    fn block_bounds(&self) -> Result<Option<BlockBounds>, WorldError> {
+        let mut bounds: Option<BlockBounds> = None;
+        for coord in self.blocks_iter()? {
+            let (x, y, z) = coord?.position();
+            let position = SpatialCoordinate::new(x.into(), y.into(), z.into());
+            bounds = Some(match bounds {
+                None => (position, position),
+                Some((min, max)) => (
+                    SpatialCoordinate::new(
+                        min.x.min(position.x),
+                        min.y.min(position.y),
+                        min.z.min(position.z),
+                    ),
+                    SpatialCoordinate::new(
+                        max.x.max(position.x),
+                        max.y.max(position.y),
+                        max.z.max(position.z),
+                    ),
+                ),
+            });
+        }
+        Ok(bounds)
+    }
+
+    /// Gets all blocks in the world, in a reproducible order
+    ///
+    /// # Returns
+    /// - The blocks sorted by position: by z, then y, then x. Unlike the packed values, this is sorted
+    ///   along each axis, as negative components borrow from the ones above them.
+    fn blocks_sorted(&self) -> Result<Vec<HashedCoordinate>, WorldError> {
+        let mut blocks = self.blocks()?;
+        blocks.sort_by_key(|coord| {
+            let (x, y, z) = coord.position();
+            (z, y, x)
+        });
+        Ok(blocks)
+    }
 }
 
-trait MapWriter {
+pub trait MapWriter {
     /// Sets the block at the given coordinate to contain the given data
     ///
     /// # Arguments
@@ -49,6 +209,30 @@ trait MapWriter {
     /// - `data` - The data to set the block to
     fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError>;
 
+    /// Stores `data` as the block at the given coordinate exactly as given
+    ///
+    /// The data is never decoded or re-serialized, see `MapReader::get_block_raw`.
+    ///
+    /// # Arguments
+    /// - `coord` - The coordinate to set the block at
+    /// - `data` - The bytes to store
+    fn set_block_raw(&self, coord: HashedCoordinate, data: &[u8]) -> Result<(), WorldError> {
+        self.set_block(coord, &data.to_vec())
+    }
+
+    /// Sets many blocks at once, as a single transaction where the backend supports it
+    ///
+    /// In a transaction, either every block is set or, on an error, none are.
+    ///
+    /// # Arguments
+    /// - `blocks` - The coordinates and data of the blocks to set
+    fn set_blocks(&self, blocks: &[(HashedCoordinate, Vec<u8>)]) -> Result<(), WorldError> {
+        for (coord, data) in blocks {
+            self.set_block(*coord, data)?;
+        }
+        Ok(())
+    }
+
     /// Removes the block at the given coordinate
     ///
     /// # Arguments
@@ -62,17 +246,21 @@ trait MapWriter {
 
 /// To store world data efficiently, Luanti uses a *SINGLE* i64 to represent a 3D coordinate.
 /// While this allows it to be stored and queried quickly, it unfortunately limits the world size to
-/// ~65536x65536x65536 blocks.
+/// 4096x4096x4096 blocks.
+///
+/// The block position is packed as `z * 4096^2 + y * 4096 + x`, with each component a signed 12-bit value.
+/// Negative lower components borrow from the ones above them, so they can't simply be divided back out.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
-struct HashedCoordinate {
+pub struct HashedCoordinate {
     pub value: i64,
 }
 
 impl HashedCoordinate {
-    const LIMIT_MIN: i16 = -30920i16;
-    const LIMIT_MAX: i16 = 30920i16;
-    const LIMIT_MIN_64: i64 = -30920i64;
-    const LIMIT_MAX_64: i64 = 30920i64;
+    /// The range of each component of a block position - Beyond it, components overflow into each other
+    const LIMIT_MIN: i16 = -2048i16;
+    const LIMIT_MAX: i16 = 2047i16;
+    const LIMIT_MIN_64: i64 = -2048i64;
+    const LIMIT_MAX_64: i64 = 2047i64;
 
     pub fn at(x: i16, y: i16, z: i16) -> Result<HashedCoordinate, CoordinateError> {
         // AABB check for in bounds
@@ -86,10 +274,26 @@ impl HashedCoordinate {
             return Err(CoordinateError::OutOfBounds);
         }
 
+        // Luanti's getBlockAsInteger
         Ok(HashedCoordinate {
-            value: i64::from(x) * 16777216i64 + i64::from(y) * 4096i64 + i64::from(z),
+            value: i64::from(z) * 16777216i64 + i64::from(y) * 4096i64 + i64::from(x),
         })
     }
+
+    /// Unpacks the block position, as passed to `at`
+    pub fn position(&self) -> (i16, i16, i16) {
+        // Luanti's getIntegerAsBlock - Each component is a signed 12-bit value, and a negative one
+        // borrows from the rest. Worked from the quotient, so even values no position packs to (as
+        // in corrupt maps) can't overflow.
+        let split = |value: i64| match value.rem_euclid(4096) {
+            low @ 0..=2047 => (low as i16, value.div_euclid(4096)),
+            high => ((high - 4096) as i16, value.div_euclid(4096) + 1),
+        };
+        let (x, rest) = split(self.value);
+        let (y, rest) = split(rest);
+        let (z, _) = split(rest);
+        (x, y, z)
+    }
 }
 
 impl Coordinate for HashedCoordinate {
@@ -97,15 +301,15 @@ impl Coordinate for HashedCoordinate {
     type Internal = i64;
 
     fn x(&self) -> Self::Scalar {
-        return i16::try_from(self.value / 16777216i64).unwrap();
+        self.position().0
     }
 
     fn y(&self) -> Self::Scalar {
-        return i16::try_from(self.value / 4096i64).unwrap();
+        self.position().1
     }
 
     fn z(&self) -> Self::Scalar {
-        return i16::try_from(self.value).unwrap();
+        self.position().2
     }
 
     fn zero() -> Self {
@@ -145,14 +349,14 @@ impl Coordinate for HashedCoordinate {
     /// - The hashed coordinate
     ///
     /// # Errors
-    /// - `WorldError::OutOfBounds` - If the coordinate is outside the bounds of the world
-    fn from<T: Coordinate>(coord: T) -> Result<Self, CoordinateError>
-    where
-        Self::Internal: From<T::Scalar>,
-    {
-        let from_x = i64::from(coord.x());
-        let from_y = i64::from(coord.y());
-        let from_z = i64::from(coord.z());
+    /// - `CoordinateError::OutOfBounds` - If the coordinate is outside the bounds of the world
+    fn from<T: Coordinate>(coord: T) -> Result<Self, CoordinateError> {
+        let cast = |component: T::Scalar| {
+            <i64 as num::NumCast>::from(component).ok_or(CoordinateError::OutOfBounds)
+        };
+        let from_x = cast(coord.x())?;
+        let from_y = cast(coord.y())?;
+        let from_z = cast(coord.z())?;
         // AABB check for in bounds (-65535 to 65535 in all directions)
         if from_x < Self::LIMIT_MIN_64
             || from_x > Self::LIMIT_MAX_64
@@ -164,12 +368,43 @@ impl Coordinate for HashedCoordinate {
             return Err(CoordinateError::OutOfBounds);
         }
 
-        let hashed = from_x * 16777216i64 + from_y * 4096i64 + from_z;
+        let hashed = from_z * 16777216i64 + from_y * 4096i64 + from_x;
 
         Ok(HashedCoordinate { value: hashed })
     }
 }
 
+/// Formats a packed block position as `(x, y, z)`, for reading database keys by hand
+pub fn hash_to_string(value: i64) -> String {
+    let (x, y, z) = HashedCoordinate { value }.position();
+    format!("({}, {}, {})", x, y, z)
+}
+
+/// Parses a block position written as `(x, y, z)` into its packed form
+///
+/// The parentheses are optional, and whitespace around each component is ignored.
+///
+/// # Errors
+/// - `CoordinateError::InvalidFormat` - If the text is not three comma-separated integers
+/// - `CoordinateError::OutOfBounds` - If the position is outside of Luanti's map
+pub fn string_to_hash(s: &str) -> Result<i64, CoordinateError> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(s);
+    let components = s
+        .split(',')
+        .map(|component| component.trim().parse::<i64>())
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(|_| CoordinateError::InvalidFormat)?;
+    let [x, y, z] = components[..] else {
+        return Err(CoordinateError::InvalidFormat);
+    };
+    let component = |value: i64| i16::try_from(value).map_err(|_| CoordinateError::OutOfBounds);
+    Ok(HashedCoordinate::at(component(x)?, component(y)?, component(z)?)?.value)
+}
+
 impl Add for HashedCoordinate {
     type Output = HashedCoordinate;
 
@@ -208,17 +443,27 @@ impl Display for HashedCoordinate {
 
 /* --------------------------- SQLite3 map reader --------------------------- */
 
+/// The most parameters bound to a single statement - SQLite's compile-time default is 999
+const SQLITE_MAX_PARAMS: usize = 900;
+
 /// A map reader for SQLite3 databases
 ///
 /// This struct is responsible for managing the SQLite3 database file, and querying it for block data.
 ///
 /// Expected schema: `CREATE TABLE `blocks` (`pos` INT NOT NULL PRIMARY KEY, `data` BLOB);`
-struct SQLite3MapReader {
+pub struct SQLite3MapReader {
     db: Connection,
 }
 
 impl SQLite3MapReader {
-    fn open_file(file_path: &str) -> Result<SQLite3MapReader, WorldError> {
+    /// Opens a map database, such as a world's `map.sqlite`
+    ///
+    /// # Arguments
+    /// - `file_path` - Path of the database file
+    ///
+    /// # Errors
+    /// - `WorldError::FileNotFound` - If the file could not be opened
+    pub fn open_file(file_path: &str) -> Result<SQLite3MapReader, WorldError> {
         let db = Connection::open(file_path).map_err(|_| {
             WorldError::FileNotFound(
                 "Failed to open SQLite3 database file: ".to_string() + file_path,
@@ -227,7 +472,7 @@ impl SQLite3MapReader {
         Ok(SQLite3MapReader { db })
     }
 
-    fn open_memory() -> Result<SQLite3MapReader, WorldError> {
+    pub fn open_memory() -> Result<SQLite3MapReader, WorldError> {
         let db = Connection::open_in_memory().map_err(|_| {
             WorldError::FileNotFound("Failed to open SQLite3 database in memory".to_string())
         })?;
@@ -241,9 +486,49 @@ impl SQLite3MapReader {
 
         Ok(SQLite3MapReader { db })
     }
+
+    /// Reads the database's `user_version`, which Luanti leaves at 0 for tools to use as they see fit
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the version could not be read
+    pub fn user_version(&self) -> Result<i32, WorldError> {
+        self.db
+            .query_row("PRAGMA user_version", params![], |row| row.get(0))
+            .map_err(|_| WorldError::DatabaseError("Failed to read user_version".to_string()))
+    }
+
+    /// Stamps the database with a `user_version`, e.g. to track a tool's own migrations
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the version could not be written
+    pub fn set_user_version(&self, version: i32) -> Result<(), WorldError> {
+        self.db
+            .pragma_update(None, "user_version", version)
+            .map_err(|_| WorldError::DatabaseError("Failed to set user_version".to_string()))
+    }
+
+    /// Rebuilds the database file, returning the space freed by removed blocks to the filesystem
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the database could not be vacuumed (e.g. it is in a transaction)
+    pub fn vacuum(&self) -> Result<(), WorldError> {
+        self.db
+            .execute("VACUUM", params![])
+            .map_err(|_| WorldError::DatabaseError("Failed to vacuum database".to_string()))?;
+        Ok(())
+    }
 }
 
 impl MapReader for SQLite3MapReader {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            // Positions are the primary key, so ranges of them are indexed
+            supports_area_query: true,
+            supports_batch: true,
+            writable: !self.db.is_readonly(DatabaseName::Main).unwrap_or(true),
+        }
+    }
+
     fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError> {
         // Query block at position
         let mut stmt = self
@@ -256,13 +541,95 @@ impl MapReader for SQLite3MapReader {
         Ok(count > 0)
     }
 
-    fn blocks(&self) -> Result<Vec<HashedCoordinate>, WorldError> {
+    fn get_blocks(
+        &self,
+        coords: &[HashedCoordinate],
+    ) -> Result<HashMap<HashedCoordinate, Vec<u8>>, WorldError> {
+        let mut blocks = HashMap::new();
+        // Stay under SQLite's bound parameter limit
+        for chunk in coords.chunks(SQLITE_MAX_PARAMS) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut stmt = self
+                .db
+                .prepare(&format!(
+                    "SELECT pos, data FROM blocks WHERE pos IN ({})",
+                    placeholders
+                ))
+                .map_err(|_| {
+                    WorldError::DatabaseError("Failed to prepare statement".to_string())
+                })?;
+            let mut rows = stmt
+                .query(params_from_iter(chunk.iter().map(|coord| coord.value)))
+                .map_err(|_| WorldError::DatabaseError("Failed to query blocks".to_string()))?;
+            while let Some(row) = rows
+                .next()
+                .map_err(|_| WorldError::DatabaseError("Failed to get next row".to_string()))?
+            {
+                let coord: i64 = row.get(0).map_err(|_| {
+                    WorldError::DatabaseError("Failed to get coordinate".to_string())
+                })?;
+                let data: Vec<u8> = row
+                    .get(1)
+                    .map_err(|_| WorldError::DatabaseError("Failed to get block".to_string()))?;
+                blocks.insert(HashedCoordinate { value: coord }, data);
+            }
+        }
+        Ok(blocks)
+    }
+
+    fn blocks_exist(
+        &self,
+        coords: &[HashedCoordinate],
+    ) -> Result<HashSet<HashedCoordinate>, WorldError> {
+        let mut existing = HashSet::new();
+        // Stay under SQLite's bound parameter limit
+        for chunk in coords.chunks(SQLITE_MAX_PARAMS) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut stmt = self
+                .db
+                .prepare(&format!(
+                    "SELECT pos FROM blocks WHERE pos IN ({})",
+                    placeholders
+                ))
+                .map_err(|_| {
+                    WorldError::DatabaseError("Failed to prepare statement".to_string())
+                })?;
+            let mut rows = stmt
+                .query(params_from_iter(chunk.iter().map(|coord| coord.value)))
+                .map_err(|_| WorldError::DatabaseError("Failed to query blocks".to_string()))?;
+            while let Some(row) = rows
+                .next()
+                .map_err(|_| WorldError::DatabaseError("Failed to get next row".to_string()))?
+            {
+                let coord: i64 = row.get(0).map_err(|_| {
+                    WorldError::DatabaseError("Failed to get coordinate".to_string())
+                })?;
+                existing.insert(HashedCoordinate { value: coord });
+            }
+        }
+        Ok(existing)
+    }
+
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>
+    {
+        Ok(Box::new(SQLite3BlockIter {
+            db: &self.db,
+            page: Vec::new().into_iter(),
+            after: None,
+            done: false,
+        }))
+    }
+
+    fn blocks_in_hash_range(&self, lo: i64, hi: i64) -> Result<Vec<HashedCoordinate>, WorldError> {
+        // Positions are the primary key, so this is a range scan of the index
         let mut stmt = self
             .db
-            .prepare("SELECT pos FROM blocks")
+            .prepare("SELECT pos FROM blocks WHERE pos >= ? AND pos < ?")
             .map_err(|_| WorldError::DatabaseError("Failed to prepare statement".to_string()))?;
         let mut rows = stmt
-            .query(params![])
+            .query(params![lo, hi])
             .map_err(|_| WorldError::DatabaseError("Failed to query blocks".to_string()))?;
         let mut coords = Vec::new();
         while let Some(row) = rows
@@ -277,6 +644,42 @@ impl MapReader for SQLite3MapReader {
         Ok(coords)
     }
 
+    fn block_bounds(&self) -> Result<Option<BlockBounds>, WorldError> {
+        // Unpacks each position as HashedCoordinate::position does, letting SQLite find the extremes.
+        // SQLite's % keeps the sign of the dividend, hence the extra + 4096.
+        let mut stmt = self
+            .db
+            .prepare(
+                "WITH xs AS (SELECT pos, ((pos + 2048) % 4096 + 4096) % 4096 - 2048 AS x FROM blocks), \
+                 ys AS (SELECT x, (pos - x) / 4096 AS rest FROM xs), \
+                 yzs AS (SELECT x, ((rest + 2048) % 4096 + 4096) % 4096 - 2048 AS y, rest FROM ys), \
+                 xyzs AS (SELECT x, y, (((rest - y) / 4096 + 2048) % 4096 + 4096) % 4096 - 2048 AS z FROM yzs) \
+                 SELECT MIN(x), MIN(y), MIN(z), MAX(x), MAX(y), MAX(z) FROM xyzs",
+            )
+            .map_err(|_| WorldError::DatabaseError("Failed to prepare statement".to_string()))?;
+        let bounds: [Option<i16>; 6] = stmt
+            .query_row(params![], |row| {
+                Ok([
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ])
+            })
+            .map_err(|_| WorldError::DatabaseError("Failed to query block bounds".to_string()))?;
+        // The aggregates are all NULL for an empty map
+        let [Some(min_x), Some(min_y), Some(min_z), Some(max_x), Some(max_y), Some(max_z)] = bounds
+        else {
+            return Ok(None);
+        };
+        Ok(Some((
+            SpatialCoordinate::new(min_x.into(), min_y.into(), min_z.into()),
+            SpatialCoordinate::new(max_x.into(), max_y.into(), max_z.into()),
+        )))
+    }
+
     fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
         // Query block at position
         let mut stmt = self
@@ -301,18 +704,110 @@ impl MapReader for SQLite3MapReader {
     }
 }
 
+/// Number of positions fetched per query while iterating a SQLite3 map
+const SQLITE_ITER_PAGE: i64 = 1024;
+
+/// Iterates the blocks of a SQLite3 map a page at a time, in order of their packed positions
+///
+/// A `rusqlite::Rows` can't be kept alongside the statement it borrows from, so rather than holding
+/// a cursor open, each page resumes from the last position of the one before it using the primary key.
+struct SQLite3BlockIter<'a> {
+    db: &'a Connection,
+    page: std::vec::IntoIter<HashedCoordinate>,
+    /// The last position returned, or `None` before the first page
+    after: Option<i64>,
+    done: bool,
+}
+
+impl SQLite3BlockIter<'_> {
+    fn next_page(&mut self) -> Result<Vec<HashedCoordinate>, WorldError> {
+        let mut stmt = self
+            .db
+            .prepare_cached("SELECT pos FROM blocks WHERE pos > ? ORDER BY pos LIMIT ?")
+            .map_err(|_| WorldError::DatabaseError("Failed to prepare statement".to_string()))?;
+        let mut rows = stmt
+            .query(params![self.after.unwrap_or(i64::MIN), SQLITE_ITER_PAGE])
+            .map_err(|_| WorldError::DatabaseError("Failed to query blocks".to_string()))?;
+        let mut coords = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .map_err(|_| WorldError::DatabaseError("Failed to get next row".to_string()))?
+        {
+            let coord: i64 = row
+                .get(0)
+                .map_err(|_| WorldError::DatabaseError("Failed to get coordinate".to_string()))?;
+            coords.push(HashedCoordinate { value: coord });
+        }
+        Ok(coords)
+    }
+}
+
+impl Iterator for SQLite3BlockIter<'_> {
+    type Item = Result<HashedCoordinate, WorldError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(coord) = self.page.next() {
+            return Some(Ok(coord));
+        }
+        if self.done {
+            return None;
+        }
+        // A block at i64::MIN would be skipped by `pos > ?`, but no block position packs to it
+        match self.next_page() {
+            Ok(page) => {
+                self.done = (page.len() as i64) < SQLITE_ITER_PAGE;
+                self.after = page.last().map(|coord| coord.value).or(self.after);
+                self.page = page.into_iter();
+                self.page.next().map(Ok)
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
 impl MapWriter for SQLite3MapReader {
     fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError> {
         // Query block at position
         let mut stmt = self
             .db
-            .prepare("INSERT INTO blocks (pos, data) VALUES (?, ?)")
+            .prepare(
+                "INSERT INTO blocks (pos, data) VALUES (?, ?) \
+                 ON CONFLICT(pos) DO UPDATE SET data = excluded.data",
+            )
             .map_err(|_| WorldError::DatabaseError("Failed to prepare statement".to_string()))?;
         stmt.execute(params![coord.value, data])
             .map_err(|_| WorldError::DatabaseError("Failed to insert block".to_string()))?;
         Ok(())
     }
 
+    fn set_blocks(&self, blocks: &[(HashedCoordinate, Vec<u8>)]) -> Result<(), WorldError> {
+        // Rolled back when dropped, so an error part way leaves the map as it was
+        let transaction = self
+            .db
+            .unchecked_transaction()
+            .map_err(|_| WorldError::DatabaseError("Failed to begin transaction".to_string()))?;
+        {
+            let mut stmt = transaction
+                .prepare(
+                    "INSERT INTO blocks (pos, data) VALUES (?, ?) \
+                     ON CONFLICT(pos) DO UPDATE SET data = excluded.data",
+                )
+                .map_err(|_| {
+                    WorldError::DatabaseError("Failed to prepare statement".to_string())
+                })?;
+            for (coord, data) in blocks {
+                stmt.execute(params![coord.value, data])
+                    .map_err(|_| WorldError::DatabaseError("Failed to insert block".to_string()))?;
+            }
+        }
+        transaction
+            .commit()
+            .map_err(|_| WorldError::DatabaseError("Failed to commit blocks".to_string()))
+    }
+
     fn remove_block(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
         // Query block at position
         let mut stmt = self
@@ -325,10 +820,908 @@ impl MapWriter for SQLite3MapReader {
     }
 }
 
+/* -------------------------------------------------------------------------- */
+/*                               Memory Backend                               */
+/* -------------------------------------------------------------------------- */
+
+/// A map reader/writer holding every block in memory
+///
+/// Useful as scratch space, and as a reference implementation of the map traits.
+#[derive(Default)]
+pub struct MemoryMapReader {
+    blocks: RefCell<HashMap<HashedCoordinate, Vec<u8>>>,
+}
+
+impl MemoryMapReader {
+    /// Creates an empty map
+    pub fn new() -> MemoryMapReader {
+        MemoryMapReader::default()
+    }
+}
+
+impl MapReader for MemoryMapReader {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supports_area_query: false,
+            supports_batch: false,
+            writable: true,
+        }
+    }
+
+    fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
+        match self.blocks.borrow().get(&coord) {
+            Some(data) => Ok(data.clone()),
+            None => Err(WorldError::PartitionNotFound(
+                <SpatialCoordinate as Coordinate>::from(coord).unwrap(),
+            )),
+        }
+    }
+
+    fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError> {
+        Ok(self.blocks.borrow().contains_key(&coord))
+    }
+
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>
+    {
+        // The keys can't outlive the borrow, so take a snapshot of them
+        let coords: Vec<HashedCoordinate> = self.blocks.borrow().keys().copied().collect();
+        Ok(Box::new(coords.into_iter().map(Ok)))
+    }
+}
+
+impl MapWriter for MemoryMapReader {
+    fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError> {
+        self.blocks.borrow_mut().insert(coord, data.clone());
+        Ok(())
+    }
+
+    fn remove_block(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+        self.blocks.borrow_mut().remove(&coord);
+        Ok(())
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Overlay                                  */
+/* -------------------------------------------------------------------------- */
+
+/// Stages edits to a map in memory, leaving the map itself untouched until `commit`
+///
+/// Reads see the staged edits first and fall through to the base map otherwise. Removing a block
+/// hides it even if the base map still has it.
+pub struct OverlayMap<R: MapReader> {
+    base: R,
+    /// Staged blocks - `None` for a removed block
+    changes: RefCell<HashMap<HashedCoordinate, Option<Vec<u8>>>>,
+}
+
+impl<R: MapReader> OverlayMap<R> {
+    /// Creates an overlay with nothing staged on top of `base`
+    pub fn new(base: R) -> OverlayMap<R> {
+        OverlayMap {
+            base,
+            changes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The map the edits are staged on top of
+    pub fn base(&self) -> &R {
+        &self.base
+    }
+
+    /// Drops every staged edit
+    pub fn discard(&self) {
+        self.changes.borrow_mut().clear();
+    }
+
+    /// Writes every staged edit to `writer`, usually the base map, then drops them
+    ///
+    /// # Errors
+    /// - Any error of `writer`. The edits written before it are dropped from the overlay, the rest are kept.
+    pub fn commit(&self, writer: &impl MapWriter) -> Result<(), WorldError> {
+        // Take the edits out first, so `writer` may read or write through the overlay itself
+        let mut changes = std::mem::take(&mut *self.changes.borrow_mut()).into_iter();
+        while let Some((coord, data)) = changes.next() {
+            let written = match &data {
+                Some(data) => writer.set_block(coord, data),
+                None => writer.remove_block(coord),
+            };
+            if let Err(error) = written {
+                // Stage the unwritten edits again, unless newer ones were staged meanwhile
+                let mut staged = self.changes.borrow_mut();
+                for (coord, data) in std::iter::once((coord, data)).chain(changes) {
+                    staged.entry(coord).or_insert(data);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: MapReader> MapReader for OverlayMap<R> {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supports_area_query: false,
+            supports_batch: false,
+            writable: true,
+        }
+    }
+
+    fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
+        match self.changes.borrow().get(&coord) {
+            Some(Some(data)) => Ok(data.clone()),
+            Some(None) => Err(WorldError::PartitionNotFound(
+                <SpatialCoordinate as Coordinate>::from(coord).unwrap(),
+            )),
+            None => self.base.get_block(coord),
+        }
+    }
+
+    fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError> {
+        match self.changes.borrow().get(&coord) {
+            Some(data) => Ok(data.is_some()),
+            None => self.base.block_exists(coord),
+        }
+    }
+
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>
+    {
+        let changes = self.changes.borrow();
+        let staged: HashSet<HashedCoordinate> = changes.keys().copied().collect();
+        let added: Vec<HashedCoordinate> = changes
+            .iter()
+            .filter(|(_, data)| data.is_some())
+            .map(|(coord, _)| *coord)
+            .collect();
+        // Staged blocks come from the overlay, whether or not the base has them too
+        let base = self
+            .base
+            .blocks_iter()?
+            .filter(move |coord| coord.as_ref().map_or(true, |coord| !staged.contains(coord)));
+        Ok(Box::new(base.chain(added.into_iter().map(Ok))))
+    }
+}
+
+impl<R: MapReader> MapWriter for OverlayMap<R> {
+    fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError> {
+        self.changes.borrow_mut().insert(coord, Some(data.clone()));
+        Ok(())
+    }
+
+    fn remove_block(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+        self.changes.borrow_mut().insert(coord, None);
+        Ok(())
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Journal                                  */
+/* -------------------------------------------------------------------------- */
+
+/// The stored bytes of a block at one moment, or that there was no block, so it can be put back
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockSnapshot {
+    pub coord: HashedCoordinate,
+    /// The block's raw data, or None if there was no block
+    pub data: Option<Vec<u8>>,
+}
+
+impl BlockSnapshot {
+    /// Captures the block at `coord`, see `MapReader::get_block_raw`
+    ///
+    /// # Arguments
+    /// - `map` - The map to read from
+    /// - `coord` - The position of the block
+    pub fn take<R: MapReader + ?Sized>(
+        map: &R,
+        coord: HashedCoordinate,
+    ) -> Result<BlockSnapshot, WorldError> {
+        let data = if map.block_exists(coord)? {
+            Some(map.get_block_raw(coord)?)
+        } else {
+            None
+        };
+        Ok(BlockSnapshot { coord, data })
+    }
+
+    /// Puts the block back as it was captured, removing it if there was no block
+    ///
+    /// # Arguments
+    /// - `map` - The map to write to
+    pub fn restore<W: MapWriter + ?Sized>(&self, map: &W) -> Result<(), WorldError> {
+        match &self.data {
+            Some(data) => map.set_block_raw(self.coord, data),
+            None => map.remove_block(self.coord),
+        }
+    }
+}
+
+/// Writes through to a map, remembering what each write replaced so it can be undone
+pub struct JournaledWriter<W: MapReader + MapWriter> {
+    map: W,
+    /// The state of each written block before the write, oldest first
+    journal: RefCell<Vec<BlockSnapshot>>,
+}
+
+impl<W: MapReader + MapWriter> JournaledWriter<W> {
+    /// Starts an empty journal of writes to `map`
+    pub fn new(map: W) -> JournaledWriter<W> {
+        JournaledWriter {
+            map,
+            journal: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The map being written to
+    pub fn map(&self) -> &W {
+        &self.map
+    }
+
+    /// Number of writes that can be undone
+    pub fn len(&self) -> usize {
+        self.journal.borrow().len()
+    }
+
+    /// True if there are no writes to undo
+    pub fn is_empty(&self) -> bool {
+        self.journal.borrow().is_empty()
+    }
+
+    /// Forgets every recorded write, keeping them in the map
+    pub fn clear(&self) {
+        self.journal.borrow_mut().clear();
+    }
+
+    /// Undoes the most recent write that hasn't been undone yet
+    ///
+    /// # Returns
+    /// - False if there was nothing to undo
+    ///
+    /// # Errors
+    /// - Any error of the map. The write stays in the journal, so undoing can be retried.
+    pub fn undo_last(&self) -> Result<bool, WorldError> {
+        let mut journal = self.journal.borrow_mut();
+        let Some(previous) = journal.last() else {
+            return Ok(false);
+        };
+        previous.restore(&self.map)?;
+        journal.pop();
+        Ok(true)
+    }
+
+    /// Undoes every recorded write, newest first, returning the map to its state when the journal was started
+    ///
+    /// # Errors
+    /// - Any error of the map, leaving the writes not yet undone in the journal
+    pub fn undo_all(&self) -> Result<(), WorldError> {
+        while self.undo_last()? {}
+        Ok(())
+    }
+
+    /// Records the current state of the block at `coord`, before it is written
+    fn record(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+        let previous = BlockSnapshot::take(&self.map, coord)?;
+        self.journal.borrow_mut().push(previous);
+        Ok(())
+    }
+}
+
+impl<W: MapReader + MapWriter> MapReader for JournaledWriter<W> {
+    fn capabilities(&self) -> Capabilities {
+        self.map.capabilities()
+    }
+
+    fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
+        self.map.get_block(coord)
+    }
+
+    fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError> {
+        self.map.block_exists(coord)
+    }
+
+    fn get_blocks(
+        &self,
+        coords: &[HashedCoordinate],
+    ) -> Result<HashMap<HashedCoordinate, Vec<u8>>, WorldError> {
+        self.map.get_blocks(coords)
+    }
+
+    fn blocks_exist(
+        &self,
+        coords: &[HashedCoordinate],
+    ) -> Result<HashSet<HashedCoordinate>, WorldError> {
+        self.map.blocks_exist(coords)
+    }
+
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>
+    {
+        self.map.blocks_iter()
+    }
+
+    fn blocks_in_hash_range(&self, lo: i64, hi: i64) -> Result<Vec<HashedCoordinate>, WorldError> {
+        self.map.blocks_in_hash_range(lo, hi)
+    }
+}
+
+impl<W: MapReader + MapWriter> MapWriter for JournaledWriter<W> {
+    fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError> {
+        self.record(coord)?;
+        self.map.set_block(coord, data)
+    }
+
+    fn remove_block(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+        self.record(coord)?;
+        self.map.remove_block(coord)
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Backup                                   */
+/* -------------------------------------------------------------------------- */
+
+/// Computes a checksum of a block's raw data (64-bit FNV-1a)
+///
+/// Stable across runs and platforms, so checksums can be persisted between backups.
+fn block_checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Copies every block of `src` whose data changed since the last backup into `dst`
+///
+/// # Arguments
+/// - `src` - The map to back up
+/// - `dst` - The map to copy changed blocks into
+/// - `known` - The checksums returned by the previous backup (empty for a full backup)
+///
+/// # Returns
+/// - The checksums of every block currently in `src`, to pass as `known` next time
+/// - The number of blocks copied
+///
+/// Blocks removed from `src` since the last backup are left in `dst`.
+pub fn incremental_backup<R: MapReader, W: MapWriter>(
+    src: &R,
+    dst: &W,
+    known: &HashMap<HashedCoordinate, u64>,
+) -> Result<(HashMap<HashedCoordinate, u64>, u64), WorldError> {
+    let mut checksums = HashMap::new();
+    let mut copied = 0;
+    for coord in src.blocks()? {
+        let data = src.get_block(coord)?;
+        let checksum = block_checksum(&data);
+        if known.get(&coord) != Some(&checksum) {
+            dst.set_block(coord, &data)?;
+            copied += 1;
+        }
+        checksums.insert(coord, checksum);
+    }
+    Ok((checksums, copied))
+}
+
+#[cfg(test)]
+mod luanti_map_backup {
+    use super::*;
+
+    #[test]
+    fn block_checksum() {
+        assert_eq!(super::block_checksum(&[]), 0xcbf29ce484222325);
+        assert_eq!(super::block_checksum(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(
+            super::block_checksum(&[29, 0, 1]),
+            super::block_checksum(&[29, 1, 0])
+        );
+    }
+
+    #[test]
+    fn incremental_backup() {
+        let src = MemoryMapReader::new();
+        for i in 0..10 {
+            src.set_block(HashedCoordinate::at(i, 0, 0).unwrap(), &vec![29, i as u8])
+                .unwrap();
+        }
+
+        // Everything is copied into an empty destination
+        let dst = MemoryMapReader::new();
+        let (known, copied) = super::incremental_backup(&src, &dst, &HashMap::new()).unwrap();
+        assert_eq!(copied, 10);
+        assert_eq!(known.len(), 10);
+        for coord in src.blocks().unwrap() {
+            assert_eq!(dst.get_block(coord).unwrap(), src.get_block(coord).unwrap());
+        }
+
+        // Nothing changed, nothing copied
+        let (known, copied) = super::incremental_backup(&src, &dst, &known).unwrap();
+        assert_eq!(copied, 0);
+
+        // Only the changed block is copied
+        let changed = HashedCoordinate::at(3, 0, 0).unwrap();
+        src.set_block(changed, &vec![29, 255]).unwrap();
+        let (_, copied) = super::incremental_backup(&src, &dst, &known).unwrap();
+        assert_eq!(copied, 1);
+        assert_eq!(dst.get_block(changed).unwrap(), vec![29, 255]);
+    }
+}
+
+#[cfg(test)]
+mod luanti_map_overlay {
+    use super::*;
+
+    /// A base map holding blocks at x = 0..3
+    fn base() -> MemoryMapReader {
+        let base = MemoryMapReader::new();
+        for i in 0..3 {
+            base.set_block(HashedCoordinate::at(i, 0, 0).unwrap(), &vec![29, i as u8])
+                .unwrap();
+        }
+        base
+    }
+
+    #[test]
+    fn read_through() {
+        let overlay = OverlayMap::new(base());
+        let coord = HashedCoordinate::at(1, 0, 0).unwrap();
+        assert_eq!(overlay.get_block(coord).unwrap(), vec![29, 1]);
+        assert!(overlay.block_exists(coord).unwrap());
+        assert_eq!(overlay.blocks().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn override_base_block() {
+        let overlay = OverlayMap::new(base());
+        let coord = HashedCoordinate::at(1, 0, 0).unwrap();
+        let added = HashedCoordinate::at(9, 0, 0).unwrap();
+        overlay.set_block(coord, &vec![29, 100]).unwrap();
+        overlay.set_block(added, &vec![29, 9]).unwrap();
+
+        assert_eq!(overlay.get_block(coord).unwrap(), vec![29, 100]);
+        assert_eq!(overlay.get_block(added).unwrap(), vec![29, 9]);
+        assert_eq!(overlay.blocks().unwrap().len(), 4);
+        // The base is untouched
+        assert_eq!(overlay.base().get_block(coord).unwrap(), vec![29, 1]);
+        assert!(!overlay.base().block_exists(added).unwrap());
+
+        overlay.discard();
+        assert_eq!(overlay.get_block(coord).unwrap(), vec![29, 1]);
+    }
+
+    #[test]
+    fn delete_base_block() {
+        let overlay = OverlayMap::new(base());
+        let coord = HashedCoordinate::at(2, 0, 0).unwrap();
+        overlay.remove_block(coord).unwrap();
+
+        assert!(!overlay.block_exists(coord).unwrap());
+        assert!(overlay.get_block(coord).is_err());
+        assert!(!overlay.blocks().unwrap().contains(&coord));
+        assert!(overlay.base().block_exists(coord).unwrap());
+
+        // Setting it again brings it back
+        overlay.set_block(coord, &vec![29, 7]).unwrap();
+        assert_eq!(overlay.get_block(coord).unwrap(), vec![29, 7]);
+    }
+
+    #[test]
+    fn commit() {
+        let overlay = OverlayMap::new(base());
+        let changed = HashedCoordinate::at(0, 0, 0).unwrap();
+        let removed = HashedCoordinate::at(1, 0, 0).unwrap();
+        let added = HashedCoordinate::at(0, 5, 0).unwrap();
+        overlay.set_block(changed, &vec![29, 50]).unwrap();
+        overlay.remove_block(removed).unwrap();
+        overlay.set_block(added, &vec![29, 55]).unwrap();
+
+        overlay.commit(overlay.base()).unwrap();
+        let base = overlay.base();
+        assert_eq!(base.get_block(changed).unwrap(), vec![29, 50]);
+        assert!(!base.block_exists(removed).unwrap());
+        assert_eq!(base.get_block(added).unwrap(), vec![29, 55]);
+        assert_eq!(base.blocks().unwrap().len(), 3);
+
+        // Nothing is left staged
+        overlay.commit(base).unwrap();
+        assert_eq!(
+            overlay.blocks_sorted().unwrap(),
+            base.blocks_sorted().unwrap()
+        );
+    }
+
+    /// Writes to the overlay's base, reading back through the overlay as it goes
+    struct ReadBackWriter<'a> {
+        overlay: &'a OverlayMap<MemoryMapReader>,
+        /// What the overlay showed for each block just before it was written
+        seen: RefCell<Vec<(HashedCoordinate, Option<Vec<u8>>)>>,
+        /// A block to refuse to write
+        fail_at: Option<HashedCoordinate>,
+    }
+
+    impl ReadBackWriter<'_> {
+        fn write(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+            if self.fail_at == Some(coord) {
+                return Err(WorldError::PartitionNotFound(
+                    <SpatialCoordinate as Coordinate>::from(coord).unwrap(),
+                ));
+            }
+            let data = self.overlay.get_block(coord).ok();
+            self.seen.borrow_mut().push((coord, data));
+            Ok(())
+        }
+    }
+
+    impl MapWriter for ReadBackWriter<'_> {
+        fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError> {
+            self.write(coord)?;
+            self.overlay.base().set_block(coord, data)
+        }
+
+        fn remove_block(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+            self.write(coord)?;
+            self.overlay.base().remove_block(coord)
+        }
+    }
+
+    #[test]
+    fn commit_reads_back_through_overlay() {
+        let overlay = OverlayMap::new(base());
+        let changed = HashedCoordinate::at(0, 0, 0).unwrap();
+        let removed = HashedCoordinate::at(1, 0, 0).unwrap();
+        overlay.set_block(changed, &vec![29, 50]).unwrap();
+        overlay.remove_block(removed).unwrap();
+
+        let writer = ReadBackWriter {
+            overlay: &overlay,
+            seen: RefCell::new(Vec::new()),
+            fail_at: None,
+        };
+        overlay.commit(&writer).unwrap();
+
+        // The staged edits were taken out before writing, so the writer saw the base
+        let mut seen = writer.seen.into_inner();
+        seen.sort_by_key(|(coord, _)| coord.value);
+        assert_eq!(
+            seen,
+            vec![(changed, Some(vec![29, 0])), (removed, Some(vec![29, 1]))]
+        );
+        assert_eq!(overlay.get_block(changed).unwrap(), vec![29, 50]);
+        assert!(!overlay.block_exists(removed).unwrap());
+    }
+
+    #[test]
+    fn commit_keeps_unwritten_edits() {
+        let overlay = OverlayMap::new(base());
+        let failing = HashedCoordinate::at(0, 0, 0).unwrap();
+        overlay.set_block(failing, &vec![29, 50]).unwrap();
+
+        let writer = ReadBackWriter {
+            overlay: &overlay,
+            seen: RefCell::new(Vec::new()),
+            fail_at: Some(failing),
+        };
+        assert!(overlay.commit(&writer).is_err());
+
+        // The failed edit is still staged and the base is untouched
+        assert_eq!(overlay.get_block(failing).unwrap(), vec![29, 50]);
+        assert_eq!(overlay.base().get_block(failing).unwrap(), vec![29, 0]);
+    }
+}
+
+#[cfg(test)]
+mod luanti_map_journal {
+    use super::*;
+
+    /// Every block of the map and its data
+    fn snapshot<R: MapReader>(map: &R) -> HashMap<HashedCoordinate, Vec<u8>> {
+        map.blocks()
+            .unwrap()
+            .into_iter()
+            .map(|coord| (coord, map.get_block(coord).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn undo() {
+        let map = SQLite3MapReader::open_memory().unwrap();
+        for i in 0..3 {
+            map.set_block(HashedCoordinate::at(i, 0, 0).unwrap(), &vec![29, i as u8])
+                .unwrap();
+        }
+        let original = snapshot(&map);
+
+        let journaled = JournaledWriter::new(map);
+        let a = HashedCoordinate::at(0, 0, 0).unwrap();
+        let b = HashedCoordinate::at(1, 0, 0).unwrap();
+        let new = HashedCoordinate::at(0, 0, 4).unwrap();
+        journaled.set_block(a, &vec![29, 10]).unwrap();
+        journaled.set_block(new, &vec![29, 11]).unwrap();
+        journaled.remove_block(b).unwrap();
+        journaled.set_block(a, &vec![29, 12]).unwrap();
+        assert_eq!(journaled.len(), 4);
+
+        let mut states = vec![snapshot(journaled.map())];
+        while journaled.undo_last().unwrap() {
+            states.push(snapshot(journaled.map()));
+        }
+        assert_eq!(states.len(), 5);
+        assert_eq!(states[1][&a], vec![29, 10]);
+        assert!(!states[1].contains_key(&b));
+        assert_eq!(states[2][&b], vec![29, 1]);
+        assert!(!states[3].contains_key(&new));
+        assert_eq!(states[4], original);
+        assert!(!journaled.undo_last().unwrap());
+    }
+
+    #[test]
+    fn undo_all() {
+        let journaled = JournaledWriter::new(MemoryMapReader::new());
+        journaled
+            .set_block(HashedCoordinate::at(0, 0, 0).unwrap(), &vec![29])
+            .unwrap();
+        journaled.clear();
+        let original = snapshot(journaled.map());
+
+        for i in 0..5 {
+            journaled
+                .set_block(
+                    HashedCoordinate::at(i % 2, 0, 0).unwrap(),
+                    &vec![29, i as u8],
+                )
+                .unwrap();
+        }
+        journaled
+            .remove_block(HashedCoordinate::at(0, 0, 0).unwrap())
+            .unwrap();
+        journaled.undo_all().unwrap();
+        assert!(journaled.is_empty());
+        assert_eq!(snapshot(journaled.map()), original);
+    }
+
+    #[test]
+    fn block_snapshot() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let coord = HashedCoordinate { value: 335560685 };
+        let original = sample.get_block_raw(coord).unwrap();
+        let map = SQLite3MapReader::open_memory().unwrap();
+        map.set_block_raw(coord, &original).unwrap();
+
+        let before = BlockSnapshot::take(&map, coord).unwrap();
+        assert_eq!(before.data.as_ref(), Some(&original));
+        map.set_block_raw(coord, &[29, 1, 2, 3]).unwrap();
+        before.restore(&map).unwrap();
+        assert_eq!(map.get_block_raw(coord).unwrap(), original);
+
+        // A block that didn't exist is removed again
+        let absent = HashedCoordinate::at(0, 0, 0).unwrap();
+        let before = BlockSnapshot::take(&map, absent).unwrap();
+        assert_eq!(before.data, None);
+        map.set_block_raw(absent, &original).unwrap();
+        before.restore(&map).unwrap();
+        assert!(!map.block_exists(absent).unwrap());
+        assert_eq!(map.get_block_raw(coord).unwrap(), original);
+    }
+}
+
+#[cfg(test)]
+mod map_backend_contract {
+    use super::*;
+
+    /// Checks the behaviour every `MapReader + MapWriter` implementation must share
+    ///
+    /// `make` must return an empty map.
+    fn assert_map_backend_contract<RW: MapReader + MapWriter>(make: impl Fn() -> RW) {
+        let map = make();
+        let a = HashedCoordinate::at(0, 0, 0).unwrap();
+        let b = HashedCoordinate::at(1, 2, 3).unwrap();
+        let absent = HashedCoordinate::at(0, 0, 7).unwrap();
+        let data_a = vec![29, 0, 1, 2];
+        let data_b = vec![29, 3, 4, 5];
+
+        // Starts empty
+        assert!(map.blocks().unwrap().is_empty());
+        assert!(!map.block_exists(a).unwrap());
+        assert!(map.get_block(a).is_err());
+
+        // Insert
+        map.set_block(a, &data_a).unwrap();
+        map.set_block(b, &data_b).unwrap();
+        assert_eq!(map.get_block(a).unwrap(), data_a);
+        assert_eq!(map.get_block(b).unwrap(), data_b);
+        assert!(map.block_exists(a).unwrap());
+        assert!(!map.block_exists(absent).unwrap());
+        assert!(map.get_block(absent).is_err());
+
+        // Enumerate
+        let blocks: HashSet<HashedCoordinate> = map.blocks().unwrap().into_iter().collect();
+        assert_eq!(blocks, HashSet::from([a, b]));
+        assert_eq!(
+            map.blocks_exist(&[a, b, absent]).unwrap(),
+            HashSet::from([a, b])
+        );
+
+        // Overwrite
+        let data_a2 = vec![29, 9, 9];
+        map.set_block(a, &data_a2).unwrap();
+        assert_eq!(map.get_block(a).unwrap(), data_a2);
+        assert_eq!(map.blocks().unwrap().len(), 2);
+
+        // Remove
+        map.remove_block(a).unwrap();
+        assert!(!map.block_exists(a).unwrap());
+        assert!(map.get_block(a).is_err());
+        assert_eq!(map.blocks().unwrap(), vec![b]);
+
+        // Removing a missing block is not an error
+        map.remove_block(absent).unwrap();
+        assert_eq!(map.blocks().unwrap(), vec![b]);
+
+        // Batch
+        map.set_blocks(&[(a, data_a.clone()), (b, data_a2.clone())])
+            .unwrap();
+        assert_eq!(map.get_block(a).unwrap(), data_a);
+        assert_eq!(map.get_block(b).unwrap(), data_a2);
+        assert_eq!(map.blocks().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn sqlite3() {
+        assert_map_backend_contract(|| SQLite3MapReader::open_memory().unwrap());
+    }
+
+    #[test]
+    fn memory() {
+        assert_map_backend_contract(MemoryMapReader::new);
+    }
+
+    #[test]
+    fn overlay() {
+        assert_map_backend_contract(|| OverlayMap::new(MemoryMapReader::new()));
+    }
+
+    #[test]
+    fn journaled() {
+        assert_map_backend_contract(|| JournaledWriter::new(MemoryMapReader::new()));
+    }
+}
+
+#[cfg(test)]
+mod hashed_coordinate_tests {
+    use super::*;
+
+    #[test]
+    fn hash_string_round_trip() {
+        for (x, y, z) in [
+            (0, 0, 0),
+            (-1, -1, -1),
+            (5, -12, 300),
+            (-2000, 7, -1),
+            (1, 2047, -2048),
+        ] {
+            let text = format!("({}, {}, {})", x, y, z);
+            let value = string_to_hash(&text).unwrap();
+            assert_eq!(value, HashedCoordinate::at(x, y, z).unwrap().value);
+            assert_eq!(hash_to_string(value), text);
+        }
+        assert_eq!(string_to_hash(" 1,2 ,3"), string_to_hash("(1, 2, 3)"));
+    }
+
+    #[test]
+    fn accessors_round_trip() {
+        let (min, max) = (HashedCoordinate::LIMIT_MIN, HashedCoordinate::LIMIT_MAX);
+        let mut components: Vec<i16> = (min..=max).step_by(97).collect();
+        components.extend([min, min + 1, -2, -1, 0, 1, 2, max - 1, max]);
+        for &x in components.iter() {
+            for &y in components.iter().step_by(7) {
+                for &z in [min, -5, -1, 0, 1, 12, max].iter() {
+                    let coord = HashedCoordinate::at(x, y, z).unwrap();
+                    assert_eq!((coord.x(), coord.y(), coord.z()), (x, y, z));
+                }
+            }
+        }
+
+        // Mixed signs, where lower components borrow from higher ones
+        let coord = HashedCoordinate::at(-5, 12, -2000).unwrap();
+        assert_eq!(coord.value, -2000 * 16777216 + 12 * 4096 - 5);
+        assert_eq!((coord.x(), coord.y(), coord.z()), (-5, 12, -2000));
+        assert!(HashedCoordinate::at(-5, 12, -30000).is_err());
+
+        // The directions step along the matching axis
+        let coord = HashedCoordinate::at(-1, 0, -1).unwrap();
+        let right = coord + HashedCoordinate::right();
+        assert_eq!((right.x(), right.y(), right.z()), (0, 0, -1));
+        let forward = coord + HashedCoordinate::forward();
+        assert_eq!((forward.x(), forward.y(), forward.z()), (-1, 0, 0));
+        let down = coord + HashedCoordinate::down();
+        assert_eq!((down.x(), down.y(), down.z()), (-1, -1, -1));
+    }
+
+    #[test]
+    fn from_spatial_coordinate() {
+        let coord = SpatialCoordinate { x: 12, y: -3, z: 7 };
+        let hashed = <HashedCoordinate as Coordinate>::from(coord).unwrap();
+        assert_eq!(hashed, HashedCoordinate::at(12, -3, 7).unwrap());
+
+        let coord = SpatialCoordinate {
+            x: 12,
+            y: 100_000,
+            z: 7,
+        };
+        assert_eq!(
+            <HashedCoordinate as Coordinate>::from(coord),
+            Err(CoordinateError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn string_to_hash_invalid() {
+        assert_eq!(
+            string_to_hash("(1, 2)"),
+            Err(CoordinateError::InvalidFormat)
+        );
+        assert_eq!(
+            string_to_hash("(1, 2, 3, 4)"),
+            Err(CoordinateError::InvalidFormat)
+        );
+        assert_eq!(
+            string_to_hash("(a, 2, 3)"),
+            Err(CoordinateError::InvalidFormat)
+        );
+        assert_eq!(
+            string_to_hash("(1, 2, 40000)"),
+            Err(CoordinateError::OutOfBounds)
+        );
+    }
+}
+
 #[cfg(test)]
 mod luanti_map_sqlite_manager {
     use super::*;
 
+    #[test]
+    fn set_blocks() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        let blocks: Vec<(HashedCoordinate, Vec<u8>)> = (0..10_000)
+            .map(|i| {
+                let coord = HashedCoordinate::at(i % 100, i / 100, 0).unwrap();
+                (coord, vec![29, (i % 256) as u8])
+            })
+            .collect();
+        manager.set_blocks(&blocks).unwrap();
+        assert_eq!(manager.blocks().unwrap().len(), 10_000);
+        for (coord, data) in blocks.iter().step_by(997) {
+            assert_eq!(&manager.get_block(*coord).unwrap(), data);
+        }
+        // Committed, not left open
+        assert!(manager.db.is_autocommit());
+
+        // A failing insert part way through leaves none of the batch behind
+        manager
+            .db
+            .execute_batch(
+                "CREATE TRIGGER reject BEFORE INSERT ON blocks WHEN NEW.pos = 5000000 \
+                 BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+            )
+            .unwrap();
+        let first = HashedCoordinate::at(0, 0, 1).unwrap();
+        let changed = blocks[0].0;
+        let rejected = HashedCoordinate { value: 5000000 };
+        assert!(manager
+            .set_blocks(&[
+                (first, vec![29]),
+                (changed, vec![29, 99]),
+                (rejected, vec![29]),
+            ])
+            .is_err());
+        assert!(!manager.block_exists(first).unwrap());
+        assert_eq!(manager.get_block(changed).unwrap(), blocks[0].1);
+        assert!(manager.db.is_autocommit());
+    }
+
     #[test]
     fn open_simple() {
         let manager = super::SQLite3MapReader::open_memory().unwrap();
@@ -345,6 +1738,17 @@ mod luanti_map_sqlite_manager {
         assert!(block == data);
     }
 
+    #[test]
+    fn overwrite_block() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        let coord = HashedCoordinate::at(4, -2, 9).unwrap();
+        manager.set_block(coord, &vec![29, 1]).unwrap();
+        manager.set_block(coord, &vec![29, 2, 2]).unwrap();
+
+        assert_eq!(manager.get_block(coord).unwrap(), vec![29, 2, 2]);
+        assert_eq!(manager.blocks().unwrap(), vec![coord]);
+    }
+
     #[test]
     fn remove_block() {
         let manager = super::SQLite3MapReader::open_memory().unwrap();
@@ -385,6 +1789,200 @@ mod luanti_map_sqlite_manager {
         assert!(coords[0] == coord);
     }
 
+    #[test]
+    fn blocks_iter() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        for i in 0..1000 {
+            let coord = HashedCoordinate::at(i % 10 - 5, i / 10 - 50, i % 3).unwrap();
+            manager.set_block(coord, &vec![29]).unwrap();
+        }
+
+        let mut count = 0;
+        for coord in manager.blocks_iter().unwrap() {
+            assert!(manager.block_exists(coord.unwrap()).unwrap());
+            count += 1;
+        }
+        assert_eq!(count, 1000);
+        assert_eq!(manager.blocks().unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn blocks_in_hash_range() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        for i in 0..200 {
+            let coord = HashedCoordinate::at(i % 7 - 3, i / 7 - 10, i % 5 - 2).unwrap();
+            manager.set_block(coord, &vec![29]).unwrap();
+        }
+
+        let low = manager.blocks_in_hash_range(i64::MIN, 0).unwrap();
+        let high = manager.blocks_in_hash_range(0, i64::MAX).unwrap();
+        assert!(!low.is_empty() && !high.is_empty());
+        assert!(low.iter().all(|coord| coord.value < 0));
+        assert!(high.iter().all(|coord| coord.value >= 0));
+
+        let mut union: Vec<HashedCoordinate> = low.into_iter().chain(high).collect();
+        union.sort_by_key(|coord| coord.value);
+        let mut all = manager.blocks().unwrap();
+        all.sort_by_key(|coord| coord.value);
+        assert_eq!(union, all);
+
+        // The default implementation agrees with the indexed one
+        let memory = MemoryMapReader::new();
+        for coord in &all {
+            memory.set_block(*coord, &vec![29]).unwrap();
+        }
+        let mut from_memory = memory.blocks_in_hash_range(i64::MIN, 0).unwrap();
+        from_memory.sort_by_key(|coord| coord.value);
+        let mut from_sqlite = manager.blocks_in_hash_range(i64::MIN, 0).unwrap();
+        from_sqlite.sort_by_key(|coord| coord.value);
+        assert_eq!(from_memory, from_sqlite);
+    }
+
+    #[test]
+    fn block_bounds() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        let memory = MemoryMapReader::new();
+        assert_eq!(manager.block_bounds().unwrap(), None);
+        assert_eq!(memory.block_bounds().unwrap(), None);
+
+        for (x, y, z) in [
+            (0, 0, 0),
+            (-2048, 5, 7),
+            (300, -1, -900),
+            (-1, 2047, 1),
+            (12, -40, 2047),
+        ] {
+            let coord = HashedCoordinate::at(x, y, z).unwrap();
+            manager.set_block(coord, &vec![29]).unwrap();
+            memory.set_block(coord, &vec![29]).unwrap();
+        }
+
+        let expected = Some((
+            SpatialCoordinate::new(-2048, -40, -900),
+            SpatialCoordinate::new(300, 2047, 2047),
+        ));
+        assert_eq!(manager.block_bounds().unwrap(), expected);
+        assert_eq!(memory.block_bounds().unwrap(), expected);
+
+        // The query agrees with unpacking every position
+        let sample =
+            super::SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let memory = MemoryMapReader::new();
+        for coord in sample.blocks().unwrap() {
+            memory.set_block(coord, &vec![29]).unwrap();
+        }
+        assert_eq!(
+            sample.block_bounds().unwrap(),
+            memory.block_bounds().unwrap()
+        );
+    }
+
+    #[test]
+    fn user_version() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        assert_eq!(manager.user_version().unwrap(), 0);
+        manager.set_user_version(3).unwrap();
+        assert_eq!(manager.user_version().unwrap(), 3);
+        manager.set_user_version(-1).unwrap();
+        assert_eq!(manager.user_version().unwrap(), -1);
+    }
+
+    #[test]
+    fn capabilities() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        let capabilities = manager.capabilities();
+        assert!(capabilities.writable);
+        assert!(capabilities.supports_batch);
+
+        let read_only = super::SQLite3MapReader {
+            db: Connection::open_with_flags(
+                "assets/world_luanti_5.10/map.sqlite",
+                rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )
+            .unwrap(),
+        };
+        assert!(!read_only.capabilities().writable);
+    }
+
+    #[test]
+    fn blocks_sorted() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        let expected = [
+            (5, 0, -2),
+            (-1, -1, 0),
+            (1, -1, 0),
+            (-3, 0, 0),
+            (0, 0, 0),
+            (2, 0, 0),
+            (0, 1, 0),
+            (-4, -7, 1),
+        ]
+        .map(|(x, y, z)| HashedCoordinate::at(x, y, z).unwrap());
+        for index in [6, 0, 3, 7, 1, 5, 2, 4] {
+            manager.set_block(expected[index], &vec![29]).unwrap();
+        }
+
+        assert_eq!(manager.blocks_sorted().unwrap(), expected);
+        for coord in expected {
+            let (x, y, z) = coord.position();
+            assert_eq!(HashedCoordinate::at(x, y, z).unwrap(), coord);
+        }
+    }
+
+    #[test]
+    fn blocks_exist() {
+        let manager = super::SQLite3MapReader::open_memory().unwrap();
+        let data = vec![0, 1, 2, 3];
+        let present: Vec<HashedCoordinate> = (0..1000)
+            .map(|i| HashedCoordinate::at(i, 0, 0).unwrap())
+            .collect();
+        for coord in &present {
+            manager.set_block(*coord, &data).unwrap();
+        }
+        let absent: Vec<HashedCoordinate> = (0..1000)
+            .map(|i| HashedCoordinate::at(i, 1, 0).unwrap())
+            .collect();
+
+        let mut query = present.clone();
+        query.extend(absent.iter());
+        let existing = manager.blocks_exist(&query).unwrap();
+        assert_eq!(existing.len(), present.len());
+        assert!(present.iter().all(|coord| existing.contains(coord)));
+        assert!(absent.iter().all(|coord| !existing.contains(coord)));
+
+        assert!(manager.blocks_exist(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn get_blocks() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        // More than one chunk of parameters, plus some missing blocks
+        let mut coords: Vec<HashedCoordinate> =
+            sample.blocks().unwrap().into_iter().take(1500).collect();
+        let absent: Vec<HashedCoordinate> = (0..100)
+            .map(|i| HashedCoordinate::at(i, -2048, 0).unwrap())
+            .filter(|coord| !sample.block_exists(*coord).unwrap())
+            .collect();
+        coords.extend(absent.iter());
+
+        let blocks = sample.get_blocks(&coords).unwrap();
+        assert_eq!(blocks.len(), coords.len() - absent.len());
+        for coord in &coords {
+            match sample.get_block(*coord) {
+                Ok(data) => assert_eq!(blocks[coord], data),
+                Err(_) => assert!(!blocks.contains_key(coord)),
+            }
+        }
+        // The default, block by block, agrees
+        let journaled = JournaledWriter::new(MemoryMapReader::new());
+        for (coord, data) in &blocks {
+            journaled.set_block(*coord, data).unwrap();
+        }
+        assert_eq!(journaled.get_blocks(&coords).unwrap(), blocks);
+
+        assert!(sample.get_blocks(&[]).unwrap().is_empty());
+    }
+
     #[test]
     fn out_of_bounds() {
         let coord = HashedCoordinate::at(32500, 0, 0);
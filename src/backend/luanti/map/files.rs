@@ -0,0 +1,253 @@
+// Luanti "files" map reader
+//
+// The map backend of early Minetest versions, storing every block in a file of its own. Blocks are
+// grouped into sectors - columns of blocks sharing an X and Z - each a directory holding one file
+// per block, named after the block's Y:
+//
+// - `sectors/XXXXZZZZ/blocks/YYYY` - The original flat layout, with X and Z as 4 hex digits each
+// - `sectors2/XXX/ZZZ/blocks/YYYY` - The later hashed layout, splitting sectors over directories by
+//   X, with X and Z as 3 hex digits each
+//
+// All components are written as two's complement hex. Each file holds the serialized block.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{Coordinate, SpatialCoordinate, WorldError};
+
+use super::{Capabilities, HashedCoordinate, MapReader};
+
+/// A map reader for worlds saved with the files backend
+///
+/// Reads both sector layouts; where a block is in both, the hashed layout wins, as it is the newer.
+pub struct FilesMapReader {
+    world_dir: PathBuf,
+}
+
+impl FilesMapReader {
+    /// Opens the map of a world
+    ///
+    /// # Arguments
+    /// - `world_dir` - The world directory, holding `sectors` and/or `sectors2`
+    ///
+    /// # Errors
+    /// - `WorldError::FileNotFound` - If the world directory doesn't exist
+    pub fn open(world_dir: &Path) -> Result<FilesMapReader, WorldError> {
+        if !world_dir.is_dir() {
+            return Err(WorldError::FileNotFound(format!(
+                "World directory not found: {}",
+                world_dir.display()
+            )));
+        }
+        Ok(FilesMapReader {
+            world_dir: world_dir.to_path_buf(),
+        })
+    }
+
+    /// Where the block at `coord` is stored in the flat layout
+    fn flat_path(&self, coord: HashedCoordinate) -> PathBuf {
+        let (x, y, z) = coord.position();
+        self.world_dir
+            .join("sectors")
+            .join(format!("{:04x}{:04x}", x as u16, z as u16))
+            .join("blocks")
+            .join(format!("{:04x}", y as u16))
+    }
+
+    /// Where the block at `coord` is stored in the hashed layout
+    fn hashed_path(&self, coord: HashedCoordinate) -> PathBuf {
+        let (x, y, z) = coord.position();
+        self.world_dir
+            .join("sectors2")
+            .join(format!("{:03x}", x as u16 & 0xfff))
+            .join(format!("{:03x}", z as u16 & 0xfff))
+            .join("blocks")
+            .join(format!("{:04x}", y as u16))
+    }
+
+    /// Lists the blocks of one sector, given the sector's X and Z
+    fn sector_blocks(
+        sector_dir: &Path,
+        x: i16,
+        z: i16,
+        blocks: &mut Vec<HashedCoordinate>,
+    ) -> Result<(), WorldError> {
+        for name in dir_names(&sector_dir.join("blocks"))? {
+            // Anything else in the directory isn't a block
+            let Some(y) = parse_hex(&name, 4) else {
+                continue;
+            };
+            if let Ok(coord) = HashedCoordinate::at(x, y, z) {
+                blocks.push(coord);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Names of the entries of a directory, or none if it doesn't exist
+fn dir_names(dir: &Path) -> Result<Vec<String>, WorldError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(_) => {
+            return Err(WorldError::UnknownError(format!(
+                "Failed to list {}",
+                dir.display()
+            )))
+        }
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry
+            .map_err(|_| WorldError::UnknownError(format!("Failed to list {}", dir.display())))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    Ok(names)
+}
+
+/// Parses a component written as `digits` hex digits of two's complement
+///
+/// # Returns
+/// - The component, or None if `text` isn't exactly that many hex digits
+fn parse_hex(text: &str, digits: usize) -> Option<i16> {
+    if text.len() != digits || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u16::from_str_radix(text, 16).ok()?;
+    // Sign-extend from the top written bit
+    let unused = 16 - 4 * digits as u32;
+    Some(((value << unused) as i16) >> unused)
+}
+
+impl MapReader for FilesMapReader {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supports_area_query: false,
+            supports_batch: false,
+            writable: false,
+        }
+    }
+
+    fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
+        for path in [self.hashed_path(coord), self.flat_path(coord)] {
+            match fs::read(&path) {
+                Ok(data) => return Ok(data),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(_) => {
+                    return Err(WorldError::UnknownError(format!(
+                        "Failed to read {}",
+                        path.display()
+                    )))
+                }
+            }
+        }
+        Err(WorldError::PartitionNotFound(
+            <SpatialCoordinate as Coordinate>::from(coord).unwrap(),
+        ))
+    }
+
+    fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError> {
+        Ok(self.hashed_path(coord).is_file() || self.flat_path(coord).is_file())
+    }
+
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>
+    {
+        let mut blocks = Vec::new();
+
+        let flat_dir = self.world_dir.join("sectors");
+        for name in dir_names(&flat_dir)? {
+            if let (Some(x), Some(z)) = (
+                name.get(..4).and_then(|x| parse_hex(x, 4)),
+                name.get(4..).and_then(|z| parse_hex(z, 4)),
+            ) {
+                Self::sector_blocks(&flat_dir.join(&name), x, z, &mut blocks)?;
+            }
+        }
+
+        let hashed_dir = self.world_dir.join("sectors2");
+        for x_name in dir_names(&hashed_dir)? {
+            let Some(x) = parse_hex(&x_name, 3) else {
+                continue;
+            };
+            for z_name in dir_names(&hashed_dir.join(&x_name))? {
+                if let Some(z) = parse_hex(&z_name, 3) {
+                    let sector_dir = hashed_dir.join(&x_name).join(&z_name);
+                    Self::sector_blocks(&sector_dir, x, z, &mut blocks)?;
+                }
+            }
+        }
+
+        // A block in both layouts is still one block
+        blocks.sort_by_key(|coord| coord.value);
+        blocks.dedup();
+        Ok(Box::new(blocks.into_iter().map(Ok)))
+    }
+}
+
+#[cfg(test)]
+mod luanti_map_files {
+    use super::*;
+    use crate::backend::luanti::map::SQLite3MapReader;
+
+    const FIXTURE: &str = "assets/world_luanti_files";
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(super::parse_hex("0014", 4), Some(20));
+        assert_eq!(super::parse_hex("ffed", 4), Some(-19));
+        assert_eq!(super::parse_hex("fe9", 3), Some(-23));
+        assert_eq!(super::parse_hex("7ff", 3), Some(2047));
+        assert_eq!(super::parse_hex("800", 3), Some(-2048));
+        assert_eq!(super::parse_hex("meta", 4), None);
+        assert_eq!(super::parse_hex("014", 4), None);
+    }
+
+    #[test]
+    fn paths() {
+        let map = FilesMapReader::open(Path::new(FIXTURE)).unwrap();
+        let coord = HashedCoordinate::at(-19, 4, 20).unwrap();
+        assert_eq!(
+            map.flat_path(coord),
+            Path::new(FIXTURE).join("sectors/ffed0014/blocks/0004")
+        );
+        assert_eq!(
+            map.hashed_path(coord),
+            Path::new(FIXTURE).join("sectors2/fed/014/blocks/0004")
+        );
+    }
+
+    #[test]
+    fn read_fixture() {
+        let sample = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+        let map = FilesMapReader::open(Path::new(FIXTURE)).unwrap();
+        // One block in each layout
+        let flat = HashedCoordinate::at(-19, 4, 20).unwrap();
+        let hashed = HashedCoordinate::at(-23, 8, 13).unwrap();
+
+        let mut blocks = map.blocks().unwrap();
+        blocks.sort_by_key(|coord| coord.value);
+        assert_eq!(blocks, vec![hashed, flat]);
+        for coord in [flat, hashed] {
+            assert!(map.block_exists(coord).unwrap());
+            assert_eq!(
+                map.get_block(coord).unwrap(),
+                sample.get_block(coord).unwrap()
+            );
+        }
+
+        let absent = HashedCoordinate::at(-19, 5, 20).unwrap();
+        assert!(!map.block_exists(absent).unwrap());
+        assert!(matches!(
+            map.get_block(absent),
+            Err(WorldError::PartitionNotFound(_))
+        ));
+        assert!(!map.capabilities().writable);
+
+        assert!(FilesMapReader::open(Path::new("assets/no_such_world")).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Luanti PostgreSQL map reader/writer
+//
+// Blocks are stored in a `blocks` table keyed by the block's position as three integer columns,
+// rather than the packed position the other backends use.
+
+use std::cell::RefCell;
+
+use postgres::{Client, NoTls};
+
+use crate::{Coordinate, SpatialCoordinate, WorldError};
+
+use super::{Capabilities, HashedCoordinate, MapReader, MapWriter};
+
+/// The `blocks` table as Luanti creates it
+const BLOCKS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS blocks (
+    posX INT NOT NULL,
+    posY INT NOT NULL,
+    posZ INT NOT NULL,
+    data BYTEA,
+    PRIMARY KEY (posX, posY, posZ)
+)";
+
+/// A map reader for PostgreSQL databases
+///
+/// Expected schema: see `create_schema`.
+pub struct PostgresMapReader {
+    // Queries need exclusive access to the connection, while the map traits only take `&self`
+    client: RefCell<Client>,
+}
+
+impl PostgresMapReader {
+    /// Connects to a map database
+    ///
+    /// # Arguments
+    /// - `params` - Connection string, such as `host=localhost user=luanti dbname=world`, as in a
+    ///   world's `pgsql_connection` setting
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the connection failed
+    pub fn connect(params: &str) -> Result<PostgresMapReader, WorldError> {
+        let client = Client::connect(params, NoTls).map_err(|error| {
+            WorldError::DatabaseError(format!("Failed to connect to PostgreSQL: {}", error))
+        })?;
+        Ok(PostgresMapReader {
+            client: RefCell::new(client),
+        })
+    }
+
+    /// Creates the `blocks` table, as Luanti would for a new world, unless it already exists
+    ///
+    /// # Errors
+    /// - `WorldError::DatabaseError` - If the table could not be created
+    pub fn create_schema(&self) -> Result<(), WorldError> {
+        self.client
+            .borrow_mut()
+            .batch_execute(BLOCKS_SCHEMA)
+            .map_err(|_| WorldError::DatabaseError("Failed to create blocks table".to_string()))
+    }
+}
+
+/// The key columns of a block
+fn block_key(coord: HashedCoordinate) -> [i32; 3] {
+    let (x, y, z) = coord.position();
+    [x.into(), y.into(), z.into()]
+}
+
+/// Packs the key columns of a block back into its position
+///
+/// # Errors
+/// - `WorldError::CorruptData` - If the position lies outside the area a map can hold
+fn decode_block_key(x: i32, y: i32, z: i32) -> Result<HashedCoordinate, WorldError> {
+    let invalid =
+        || WorldError::CorruptData(format!("Invalid block position ({}, {}, {})", x, y, z));
+    let component = |value: i32| i16::try_from(value).map_err(|_| invalid());
+    HashedCoordinate::at(component(x)?, component(y)?, component(z)?).map_err(|_| invalid())
+}
+
+impl MapReader for PostgresMapReader {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            // Positions aren't packed, so ranges of packed positions don't map onto the index
+            supports_area_query: false,
+            supports_batch: false,
+            writable: true,
+        }
+    }
+
+    fn get_block(&self, coord: HashedCoordinate) -> Result<Vec<u8>, WorldError> {
+        let [x, y, z] = block_key(coord);
+        let row = self
+            .client
+            .borrow_mut()
+            .query_opt(
+                "SELECT data FROM blocks WHERE posX = $1 AND posY = $2 AND posZ = $3",
+                &[&x, &y, &z],
+            )
+            .map_err(|_| WorldError::DatabaseError("Failed to get block".to_string()))?;
+        match row {
+            Some(row) => row
+                .try_get::<_, Option<Vec<u8>>>(0)
+                .map(Option::unwrap_or_default)
+                .map_err(|_| WorldError::DatabaseError("Failed to get block".to_string())),
+            None => Err(WorldError::PartitionNotFound(
+                <SpatialCoordinate as Coordinate>::from(coord).unwrap(),
+            )),
+        }
+    }
+
+    fn block_exists(&self, coord: HashedCoordinate) -> Result<bool, WorldError> {
+        let [x, y, z] = block_key(coord);
+        let row = self
+            .client
+            .borrow_mut()
+            .query_opt(
+                "SELECT 1 FROM blocks WHERE posX = $1 AND posY = $2 AND posZ = $3",
+                &[&x, &y, &z],
+            )
+            .map_err(|_| WorldError::DatabaseError("Failed to check block".to_string()))?;
+        Ok(row.is_some())
+    }
+
+    fn blocks_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<HashedCoordinate, WorldError>> + '_>, WorldError>
+    {
+        // Rows can't outlive the borrow of the connection, so take a snapshot of the positions
+        let rows = self
+            .client
+            .borrow_mut()
+            .query("SELECT posX, posY, posZ FROM blocks", &[])
+            .map_err(|_| WorldError::DatabaseError("Failed to list blocks".to_string()))?;
+        let coords: Vec<Result<HashedCoordinate, WorldError>> = rows
+            .iter()
+            .map(|row| decode_block_key(row.get(0), row.get(1), row.get(2)))
+            .collect();
+        Ok(Box::new(coords.into_iter()))
+    }
+}
+
+impl MapWriter for PostgresMapReader {
+    fn set_block(&self, coord: HashedCoordinate, data: &Vec<u8>) -> Result<(), WorldError> {
+        let [x, y, z] = block_key(coord);
+        self.client
+            .borrow_mut()
+            .execute(
+                "INSERT INTO blocks (posX, posY, posZ, data) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (posX, posY, posZ) DO UPDATE SET data = EXCLUDED.data",
+                &[&x, &y, &z, data],
+            )
+            .map_err(|_| WorldError::DatabaseError("Failed to set block".to_string()))?;
+        Ok(())
+    }
+
+    fn remove_block(&self, coord: HashedCoordinate) -> Result<(), WorldError> {
+        let [x, y, z] = block_key(coord);
+        self.client
+            .borrow_mut()
+            .execute(
+                "DELETE FROM blocks WHERE posX = $1 AND posY = $2 AND posZ = $3",
+                &[&x, &y, &z],
+            )
+            .map_err(|_| WorldError::DatabaseError("Failed to remove block".to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod luanti_map_postgresql {
+    use super::*;
+
+    #[test]
+    fn block_keys() {
+        let coord = HashedCoordinate::at(-19, 4, 20).unwrap();
+        assert_eq!(block_key(coord), [-19, 4, 20]);
+        assert_eq!(decode_block_key(-19, 4, 20).unwrap(), coord);
+        assert_eq!(
+            decode_block_key(-2048, 2047, -1).unwrap(),
+            HashedCoordinate::at(-2048, 2047, -1).unwrap()
+        );
+        assert!(decode_block_key(0, 2048, 0).is_err());
+        assert!(decode_block_key(0, 0, 100000).is_err());
+    }
+}
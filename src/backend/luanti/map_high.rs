@@ -0,0 +1,160 @@
+// A Luanti map read through its blocks and nodes, rather than as raw block data
+
+use crate::{Coordinate, SpatialCoordinate, WorldError};
+
+use super::{
+    block_serialization::{decode_block_at, deserialize_block_data, MapBlockData},
+    map::{HashedCoordinate, MapReader},
+};
+
+/// A Luanti map whose blocks are decoded as they are read
+pub struct LuantiMap {
+    reader: Box<dyn MapReader>,
+}
+
+impl LuantiMap {
+    /// Wraps a map backend
+    ///
+    /// # Arguments
+    /// - `reader` - The backend holding the map's blocks
+    pub fn new(reader: Box<dyn MapReader>) -> LuantiMap {
+        LuantiMap { reader }
+    }
+
+    /// The backend the blocks are read from
+    pub fn reader(&self) -> &dyn MapReader {
+        self.reader.as_ref()
+    }
+
+    /// Reads and decodes the block at `coord`, keeping it in the version it is stored in
+    ///
+    /// # Arguments
+    /// - `coord` - The position of the block
+    ///
+    /// # Errors
+    /// - `WorldError::PartitionNotFound` - If there is no block at `coord`
+    /// - `WorldError::CorruptData` - If the block could not be decoded
+    pub fn get_mapblock(
+        &self,
+        coord: HashedCoordinate,
+    ) -> Result<Box<dyn MapBlockData>, WorldError> {
+        deserialize_block_data(&self.reader.get_block(coord)?)
+    }
+
+    /// Looks up the node at a world position
+    ///
+    /// # Arguments
+    /// - `world_coord` - Node position in the world
+    ///
+    /// # Returns
+    /// - The node's name, param1 and param2. A node whose content id isn't in its block's name-id
+    ///   mapping is named `unknown`, as Luanti does - some blocks are saved with an empty mapping
+    ///
+    /// # Errors
+    /// - `WorldError::OutOfBounds` - If the position lies outside the area a map can hold
+    /// - `WorldError::PartitionNotFound` - If the block holding the node isn't in the map
+    /// - `WorldError::CorruptPartition` - If that block could not be decoded, including when its name-id mapping is corrupt
+    pub fn node_at(&self, world_coord: SpatialCoordinate) -> Result<(String, u8, u8), WorldError> {
+        let block_position = world_coord.to_block_coord();
+        let coord = <HashedCoordinate as Coordinate>::from(block_position)
+            .map_err(|_| WorldError::OutOfBounds(world_coord))?;
+
+        let block = decode_block_at(coord, &self.reader.get_block(coord)?)?;
+        let (x, y, z) = world_coord.to_node_offset();
+        let node = block.node_at(x, y, z);
+        let name = block.content_name(node.content).unwrap_or("unknown");
+        Ok((name.to_string(), node.param1, node.param2))
+    }
+}
+
+#[cfg(test)]
+mod luanti_map_tests {
+    use super::*;
+    use crate::backend::luanti::map::{MapWriter, MemoryMapReader, SQLite3MapReader};
+
+    fn sample_map() -> LuantiMap {
+        LuantiMap::new(Box::new(
+            SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap(),
+        ))
+    }
+
+    /// The sample block holding a viola at block-local (3, 2, 12)
+    const VIOLA_BLOCK: HashedCoordinate = HashedCoordinate { value: 335560685 };
+
+    /// The world position of the node at block-local (x, y, z) within `block`
+    fn world_position(block: HashedCoordinate, x: i16, y: i16, z: i16) -> SpatialCoordinate {
+        let (block_x, block_y, block_z) = block.position();
+        SpatialCoordinate::new(
+            (block_x * 16 + x).into(),
+            (block_y * 16 + y).into(),
+            (block_z * 16 + z).into(),
+        )
+    }
+
+    #[test]
+    fn get_mapblock() {
+        let map = sample_map();
+        let block = map
+            .get_mapblock(HashedCoordinate { value: 335560685 })
+            .unwrap();
+        assert!(block.was_generated());
+        assert_eq!(block.timestamp(), 0xffffffff);
+
+        assert!(matches!(
+            LuantiMap::new(Box::new(MemoryMapReader::new()))
+                .get_mapblock(HashedCoordinate { value: 335560685 }),
+            Err(WorldError::PartitionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn node_at() {
+        let map = sample_map();
+        let coord = VIOLA_BLOCK;
+        let expected = decode_block_at(coord, &map.reader().get_block(coord).unwrap())
+            .unwrap()
+            .node_at(3, 2, 12);
+
+        let world_coord = world_position(coord, 3, 2, 12);
+        assert_eq!(
+            map.node_at(world_coord).unwrap(),
+            (
+                "flowers:viola".to_string(),
+                expected.param1,
+                expected.param2
+            )
+        );
+
+        assert!(matches!(
+            map.node_at(SpatialCoordinate::new(0, 40000, 0)),
+            Err(WorldError::OutOfBounds(_))
+        ));
+    }
+
+    #[test]
+    fn node_at_unmapped() {
+        let sample = sample_map();
+        let coord = VIOLA_BLOCK;
+        let data = sample.reader().get_block(coord).unwrap();
+
+        // Strip the name-id mapping, which follows the 7 byte header, leaving it empty
+        let mut raw = zstd::stream::decode_all(&data[1..]).unwrap();
+        let mut end = 10;
+        for _ in 0..u16::from_be_bytes([raw[8], raw[9]]) {
+            end += 4 + usize::from(u16::from_be_bytes([raw[end + 2], raw[end + 3]]));
+        }
+        raw.splice(8..end, [0, 0]);
+        let mut stripped = vec![29];
+        stripped.extend(zstd::stream::encode_all(raw.as_slice(), 0).unwrap());
+
+        let writer = MemoryMapReader::new();
+        writer.set_block(coord, &stripped).unwrap();
+        let map = LuantiMap::new(Box::new(writer));
+        let expected = decode_block_at(coord, &data).unwrap().node_at(3, 2, 12);
+        let world_coord = world_position(coord, 3, 2, 12);
+        assert_eq!(
+            map.node_at(world_coord).unwrap(),
+            ("unknown".to_string(), expected.param1, expected.param2)
+        );
+    }
+}
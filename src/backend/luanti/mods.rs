@@ -1 +1,101 @@
+// Mods - Metadata of the mods a game or world loads
 
+use std::{fs, path::Path};
+
+use crate::WorldError;
+
+use super::file_format::KeyValue;
+
+/// Display metadata of a mod, as given by its `mod.conf`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModInfo {
+    pub name: String,
+    /// Human-readable name - the mod's name if `mod.conf` doesn't give one
+    pub title: String,
+    pub description: Option<String>,
+    /// Names of the mods this mod requires
+    pub depends: Vec<String>,
+}
+
+/// Reads the metadata of a mod in a game's `mods` directory
+///
+/// A mod without a `mod.conf` (or with one that leaves out `name`) is named after its directory.
+///
+/// # Arguments
+/// - `game_dir` - The game directory, e.g. `games/minetest_game`
+/// - `mod_name` - The name of the mod's directory within `game_dir/mods`
+///
+/// # Errors
+/// - `WorldError::NameNotFound` - If the game has no such mod
+/// - `WorldError::FileNotFound` - If `mod.conf` exists but could not be read
+pub fn mod_info(game_dir: &Path, mod_name: &str) -> Result<ModInfo, WorldError> {
+    let mod_dir = game_dir.join("mods").join(mod_name);
+    if !mod_dir.is_dir() {
+        return Err(WorldError::NameNotFound(mod_name.to_string()));
+    }
+
+    let mod_conf = mod_dir.join("mod.conf");
+    let conf = if mod_conf.is_file() {
+        let data = fs::read(&mod_conf).map_err(|_| {
+            WorldError::FileNotFound(format!("Failed to read {}", mod_conf.display()))
+        })?;
+        KeyValue::from(&String::from_utf8_lossy(&data))
+    } else {
+        KeyValue::new()
+    };
+
+    let name = conf.get("name").unwrap_or(mod_name.to_string());
+    Ok(ModInfo {
+        title: conf.get("title").unwrap_or(name.clone()),
+        description: conf.get("description"),
+        depends: conf
+            .get("depends")
+            .map(|depends| {
+                depends
+                    .split(',')
+                    .map(|depend| depend.trim().to_string())
+                    .filter(|depend| !depend.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        name,
+    })
+}
+
+#[cfg(test)]
+mod mods_tests {
+    use super::*;
+
+    #[test]
+    fn mod_info() {
+        let game = tempfile::tempdir().unwrap();
+        let mods = game.path().join("mods");
+        fs::create_dir_all(mods.join("doors")).unwrap();
+        fs::write(
+            mods.join("doors/mod.conf"),
+            "name = doors\ntitle = Doors\ndescription = Adds doors and trapdoors\ndepends = default, screwdriver\n",
+        )
+        .unwrap();
+        fs::create_dir_all(mods.join("bare")).unwrap();
+
+        let info = super::mod_info(game.path(), "doors").unwrap();
+        assert_eq!(info.name, "doors");
+        assert_eq!(info.title, "Doors");
+        assert_eq!(
+            info.description,
+            Some("Adds doors and trapdoors".to_string())
+        );
+        assert_eq!(info.depends, ["default", "screwdriver"]);
+
+        let info = super::mod_info(game.path(), "bare").unwrap();
+        assert_eq!(info.name, "bare");
+        assert_eq!(info.title, "bare");
+        assert_eq!(info.description, None);
+        assert!(info.depends.is_empty());
+
+        assert_eq!(
+            super::mod_info(game.path(), "missing"),
+            Err(WorldError::NameNotFound("missing".to_string()))
+        );
+    }
+}
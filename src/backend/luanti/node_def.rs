@@ -0,0 +1,119 @@
+// Node definitions - Properties of nodes that are defined by the game rather than stored in the map
+
+use std::{collections::HashSet, fs, path::Path};
+
+use crate::WorldError;
+
+/// Supplies per-node properties, typically sourced from a game's node definitions
+pub trait NodeDefProvider {
+    /// True if players and entities collide with the node (Luanti's `walkable`)
+    fn is_walkable(&self, name: &str) -> bool;
+}
+
+/// Supplies the color of each node as seen from above, for rendering maps
+pub trait ColorProvider {
+    /// The node's RGB color, or None if it should be seen through
+    fn color_of(&self, name: &str) -> Option<[u8; 3]>;
+}
+
+/// Collects the names of the nodes registered by a game's mods
+///
+/// This is a best-effort scan rather than a Lua interpreter: it looks for calls such as
+/// `minetest.register_node("mod:name", ...)` (or `core.register_node`) with a string literal name.
+/// Nodes registered through variables or helper functions are missed.
+///
+/// # Arguments
+/// - `game_dir` - The game directory, e.g. `games/minetest_game`. Every `.lua` file beneath it is scanned.
+///
+/// # Returns
+/// - The registered names, with the leading `:` of overriding registrations removed
+pub fn scan_node_names(game_dir: &Path) -> Result<HashSet<String>, WorldError> {
+    let mut names = HashSet::new();
+    scan_lua_files(game_dir, &mut names)?;
+    Ok(names)
+}
+
+fn scan_lua_files(dir: &Path, names: &mut HashSet<String>) -> Result<(), WorldError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", dir.display())))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", dir.display())))?
+            .path();
+        if path.is_dir() {
+            scan_lua_files(&path, names)?;
+        } else if path.extension().is_some_and(|extension| extension == "lua") {
+            let source = fs::read(&path).map_err(|_| {
+                WorldError::FileNotFound(format!("Failed to read {}", path.display()))
+            })?;
+            names.extend(registered_node_names(&String::from_utf8_lossy(&source)));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the string literal passed to each `register_node(` call in Lua source
+fn registered_node_names(source: &str) -> Vec<String> {
+    const CALL: &str = "register_node(";
+
+    let mut names = Vec::new();
+    for (start, _) in source.match_indices(CALL) {
+        let arguments = source[start + CALL.len()..].trim_start();
+        let Some(quote) = arguments.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        let literal = &arguments[1..];
+        if let Some(end) = literal.find(quote) {
+            let name = literal[..end].trim_start_matches(':');
+            if !name.is_empty() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod node_def_tests {
+    use super::*;
+
+    #[test]
+    fn scan_node_names() {
+        let game = tempfile::tempdir().unwrap();
+        let mod_dir = game.path().join("mods").join("testmod");
+        fs::create_dir_all(mod_dir.join("textures")).unwrap();
+        fs::write(
+            mod_dir.join("init.lua"),
+            r#"
+minetest.register_node("testmod:stone", {
+    description = "Stone",
+    tiles = {"testmod_stone.png"},
+})
+
+core.register_node( 'testmod:glass' , {drawtype = "glasslike"})
+
+-- Overrides another mod's node
+minetest.register_node(":default:dirt", {})
+
+local name = "testmod:hidden"
+minetest.register_node(name, {})
+minetest.register_craftitem("testmod:lump", {})
+"#,
+        )
+        .unwrap();
+        fs::write(mod_dir.join("mod.conf"), "name = testmod\n").unwrap();
+        fs::write(mod_dir.join("textures").join("testmod_stone.png"), []).unwrap();
+
+        let names = super::scan_node_names(game.path()).unwrap();
+        assert_eq!(
+            names,
+            HashSet::from([
+                "testmod:stone".to_string(),
+                "testmod:glass".to_string(),
+                "default:dirt".to_string(),
+            ])
+        );
+
+        assert!(super::scan_node_names(&game.path().join("missing")).is_err());
+    }
+}
@@ -1,42 +1,107 @@
-use super::file_format::KeyValue;
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use rusqlite::Connection;
+
+use crate::WorldError;
+
+use super::{
+    auth::auth_user_names,
+    file_format::KeyValue,
+    mods::{mod_info, ModInfo},
+};
 
 // Based off of the format specified at
 // https://github.com/minetest/minetest/blob/master/doc/world_format.md
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum BackendType {
     SQLite3,
     LevelDB,
     Redis,
     PostgreSQL,
     Files, // Files - Deprecated
+    Dummy, // Dummy - Stores nothing
+}
+
+impl BackendType {
+    /// Parses a backend name as written in `world.mt`
+    ///
+    /// Returns `None` for names Luanti does not recognize.
+    pub fn from_name(name: &str) -> Option<BackendType> {
+        match name {
+            "sqlite3" => Some(BackendType::SQLite3),
+            "leveldb" => Some(BackendType::LevelDB),
+            "redis" => Some(BackendType::Redis),
+            "postgresql" => Some(BackendType::PostgreSQL),
+            "files" => Some(BackendType::Files),
+            "dummy" => Some(BackendType::Dummy),
+            _ => None,
+        }
+    }
+
+    /// The backend's name as written in `world.mt`, the inverse of `from_name`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendType::SQLite3 => "sqlite3",
+            BackendType::LevelDB => "leveldb",
+            BackendType::Redis => "redis",
+            BackendType::PostgreSQL => "postgresql",
+            BackendType::Files => "files",
+            BackendType::Dummy => "dummy",
+        }
+    }
 }
 
+impl fmt::Display for BackendType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Luanti's `mapgen_limit` when none is configured
+const DEFAULT_MAPGEN_LIMIT: i32 = 31000;
+
 pub struct World {
     // Metadata
+    world_name: Option<String>,
     game_id: String,
     enable_damage: bool,
     enable_creative: bool,
-    backend: BackendType,
-    player_backend: BackendType,
-    auth_backend: BackendType,
-    mod_storage_backend: BackendType,
+    // Backends - None if not configured (or not recognized)
+    backend: Option<BackendType>,
+    player_backend: Option<BackendType>,
+    auth_backend: Option<BackendType>,
+    mod_storage_backend: Option<BackendType>,
     mods: Vec<String>,
+    /// `mapgen_limit` from `map_meta.txt` or `world.mt`, if set
+    mapgen_limit: Option<i32>,
+    /// Names of the mods switched on by a `load_mod_<name>` key
+    enabled_mods: Vec<String>,
     server_announce: bool,
+    /// world.mt as read, keeping unknown keys and the order of all keys
+    metadata: KeyValue,
 }
 
 impl World {
     pub fn open(world_directory: &std::path::Path) -> Result<World, ()> {
         let mut world = World {
+            world_name: None,
             game_id: String::new(),
             enable_damage: false,
             enable_creative: false,
-            backend: BackendType::Files,
-            player_backend: BackendType::Files,
-            auth_backend: BackendType::Files,
-            mod_storage_backend: BackendType::Files,
+            backend: None,
+            player_backend: None,
+            auth_backend: None,
+            mod_storage_backend: None,
             mods: Vec::new(),
+            mapgen_limit: None,
+            enabled_mods: Vec::new(),
             server_announce: false,
+            metadata: KeyValue::new(),
         };
 
         /* -------------------------------------------------------------------------- */
@@ -55,6 +120,7 @@ impl World {
         );
 
         // Fill in the world metadata
+        world.world_name = world_metadata.get("world_name");
         world.game_id = world_metadata
             .get("gameid")
             .unwrap_or("minetest_game".to_string());
@@ -82,56 +148,66 @@ impl World {
             })
             .collect();
         world.mods = load_mods_mt;
+        // The value is "false" for disabled mods, otherwise "true" or the path the mod is loaded from
+        world.enabled_mods = world_metadata
+            .clone()
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix("load_mod_")?;
+                (value != "false").then(|| name.to_string())
+            })
+            .collect();
 
-        world.backend = match world_metadata
+        // A missing key is left as None - Luanti falls back to its own defaults in that case
+        world.backend = world_metadata
             .get("backend")
-            .unwrap_or("files".to_string())
-            .as_str()
-        {
-            "sqlite3" => BackendType::SQLite3,
-            "leveldb" => BackendType::LevelDB,
-            "redis" => BackendType::Redis,
-            "postgresql" => BackendType::PostgreSQL,
-            _ => BackendType::Files,
-        };
-        world.player_backend = match world_metadata
+            .and_then(|name| BackendType::from_name(&name));
+        world.player_backend = world_metadata
             .get("player_backend")
-            .unwrap_or("files".to_string())
-            .as_str()
-        {
-            "sqlite3" => BackendType::SQLite3,
-            "leveldb" => BackendType::LevelDB,
-            "redis" => BackendType::Redis,
-            "postgresql" => BackendType::PostgreSQL,
-            _ => BackendType::Files,
-        };
-        world.auth_backend = match world_metadata
+            .and_then(|name| BackendType::from_name(&name));
+        world.auth_backend = world_metadata
             .get("auth_backend")
-            .unwrap_or("files".to_string())
-            .as_str()
-        {
-            "sqlite3" => BackendType::SQLite3,
-            "leveldb" => BackendType::LevelDB,
-            "redis" => BackendType::Redis,
-            "postgresql" => BackendType::PostgreSQL,
-            _ => BackendType::Files,
-        };
-        world.mod_storage_backend = match world_metadata
+            .and_then(|name| BackendType::from_name(&name));
+        world.mod_storage_backend = world_metadata
             .get("mod_storage_backend")
-            .unwrap_or("files".to_string())
-            .as_str()
-        {
-            "sqlite3" => BackendType::SQLite3,
-            "leveldb" => BackendType::LevelDB,
-            "redis" => BackendType::Redis,
-            "postgresql" => BackendType::PostgreSQL,
-            _ => BackendType::Files,
+            .and_then(|name| BackendType::from_name(&name));
+
+        // The map's own settings, saved by the mapgen - These take precedence over world.mt
+        let file_map_meta = world_directory.join("map_meta.txt");
+        let map_metadata = match std::fs::read(file_map_meta) {
+            Ok(data) => KeyValue::from(&String::from_utf8_lossy(&data)),
+            Err(_) => KeyValue::new(),
         };
+        world.mapgen_limit = map_metadata
+            .get("mapgen_limit")
+            .or_else(|| world_metadata.get("mapgen_limit"))
+            .and_then(|limit| limit.parse().ok());
 
+        world.metadata = world_metadata;
         Ok(world)
     }
 
+    /// Writes `world.mt` back to a world directory
+    ///
+    /// Keys are written in the order they were read, so saving an unchanged world leaves `world.mt`
+    /// the same apart from whitespace around the `=`.
+    ///
+    /// # Arguments
+    /// - `dir` - The world directory to write to
+    ///
+    /// # Errors
+    /// - `WorldError::UnknownError` - If the file could not be written
+    pub fn save(&self, dir: &Path) -> Result<(), WorldError> {
+        let path = dir.join("world.mt");
+        fs::write(&path, self.metadata.as_str())
+            .map_err(|_| WorldError::UnknownError(format!("Failed to write {}", path.display())))
+    }
+
     /* ----------------------- Property Getters - Metadata ---------------------- */
+    /// The name given in `world.mt`, which older worlds may lack
+    pub fn world_name(&self) -> Option<&str> {
+        self.world_name.as_deref()
+    }
+
     pub fn game_id(&self) -> &str {
         &self.game_id
     }
@@ -148,27 +224,602 @@ impl World {
         self.server_announce
     }
 
-    #[allow(dead_code)]
-    pub fn backend(&self) -> BackendType {
+    /// The map backend, or None if the world does not configure one
+    pub fn backend(&self) -> Option<BackendType> {
         self.backend
     }
 
-    #[allow(dead_code)]
-    fn player_backend(&self) -> BackendType {
+    /// The player backend, or None if the world does not configure one
+    pub fn player_backend(&self) -> Option<BackendType> {
         self.player_backend
     }
 
-    #[allow(dead_code)]
-    fn auth_backend(&self) -> BackendType {
+    /// The auth backend, or None if the world does not configure one
+    pub fn auth_backend(&self) -> Option<BackendType> {
         self.auth_backend
     }
 
-    #[allow(dead_code)]
-    fn mod_storage_backend(&self) -> BackendType {
+    /// The mod storage backend, or None if the world does not configure one
+    pub fn mod_storage_backend(&self) -> Option<BackendType> {
         self.mod_storage_backend
     }
 
+    /// How far from the origin terrain may be generated, in nodes along each axis
+    ///
+    /// Luanti's default of 31000 if the world does not set one.
+    pub fn mapgen_limit(&self) -> i32 {
+        self.mapgen_limit.unwrap_or(DEFAULT_MAPGEN_LIMIT)
+    }
+
     pub fn mods(&self) -> &Vec<String> {
         &self.mods
     }
+
+    /// The world's thumbnail, `screenshot.png`, if it has one
+    ///
+    /// # Arguments
+    /// - `dir` - The world directory the world was opened from
+    pub fn thumbnail_path(&self, dir: &Path) -> Option<PathBuf> {
+        let path = dir.join("screenshot.png");
+        path.is_file().then_some(path)
+    }
+
+    /// Gathers what a launcher shows for the world
+    ///
+    /// # Arguments
+    /// - `dir` - The world directory the world was opened from
+    pub fn listing(&self, dir: &Path) -> WorldListing {
+        WorldListing {
+            name: self.world_name.clone().unwrap_or_else(|| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            game_id: self.game_id.clone(),
+            creative: self.enable_creative,
+            damage_enabled: self.enable_damage,
+            announcing: self.server_announce,
+            thumbnail: self.thumbnail_path(dir),
+        }
+    }
+
+    /// Reads any key-value file in the world directory, e.g. a mod's settings
+    ///
+    /// # Arguments
+    /// - `dir` - The world directory the world was opened from
+    /// - `filename` - Name of the file within `dir`
+    ///
+    /// # Errors
+    /// - `WorldError::FileNotFound` - If the file does not exist or could not be read
+    pub fn read_kv(&self, dir: &Path, filename: &str) -> Result<KeyValue, WorldError> {
+        let path = dir.join(filename);
+        let data = fs::read(&path)
+            .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", path.display())))?;
+        Ok(KeyValue::from(&String::from_utf8_lossy(&data)))
+    }
+
+    /// Names of the mods the world enables
+    pub fn enabled_mods(&self) -> &Vec<String> {
+        &self.enabled_mods
+    }
+
+    /// Reads the `mod.conf` of each mod the world enables
+    ///
+    /// # Arguments
+    /// - `game_dir` - The directory of the world's game, where the mods are looked up
+    ///
+    /// # Errors
+    /// - `WorldError::NameNotFound` - If an enabled mod is not part of the game
+    pub fn mods_with_conf(&self, game_dir: &Path) -> Result<Vec<ModInfo>, WorldError> {
+        self.enabled_mods
+            .iter()
+            .map(|name| mod_info(game_dir, name))
+            .collect()
+    }
+}
+
+/// A summary of a world, as shown by content launchers
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldListing {
+    /// The world's name, or its directory name if `world.mt` doesn't give one
+    pub name: String,
+    pub game_id: String,
+    pub creative: bool,
+    pub damage_enabled: bool,
+    pub announcing: bool,
+    /// Path to `screenshot.png`, if the world has one
+    pub thumbnail: Option<PathBuf>,
+}
+
+/// Lists the worlds in a directory of worlds, such as Luanti's `worlds` directory
+///
+/// Subdirectories without a `world.mt`, and worlds that fail to open, are skipped.
+///
+/// # Returns
+/// - Each world's directory name paired with the world, sorted by name
+pub fn scan_worlds(worlds_dir: &Path) -> Result<Vec<(String, World)>, WorldError> {
+    let mut worlds = Vec::new();
+    for entry in fs::read_dir(worlds_dir)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", worlds_dir.display())))?
+    {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let path = entry.path();
+        if !path.join("world.mt").is_file() {
+            continue;
+        }
+        if let Ok(world) = World::open(&path) {
+            worlds.push((entry.file_name().to_string_lossy().into_owned(), world));
+        }
+    }
+    worlds.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(worlds)
+}
+
+/// Names of every player with data in the world's player backend
+///
+/// Worlds that don't configure a backend are read with the `files` backend, as Luanti does.
+///
+/// # Errors
+/// - `WorldError::UnknownError` - If the backend is not supported
+/// - `WorldError::DatabaseError` - If `players.sqlite` could not be read
+fn player_names(world: &World, dir: &Path) -> Result<Vec<String>, WorldError> {
+    match world.player_backend().unwrap_or(BackendType::Files) {
+        BackendType::Files => {
+            let Ok(entries) = fs::read_dir(dir.join("players")) else {
+                return Ok(Vec::new());
+            };
+            let mut names = Vec::new();
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_file() {
+                    continue;
+                }
+                // The name is given in the player's args, which end before the inventory
+                let data = fs::read(&path).map_err(|_| {
+                    WorldError::FileNotFound(format!("Failed to read {}", path.display()))
+                })?;
+                let data = String::from_utf8_lossy(&data);
+                let args = data.split("PlayerArgsEnd").next().unwrap_or_default();
+                names.push(
+                    KeyValue::from(args)
+                        .get("name")
+                        .unwrap_or_else(|| entry.file_name().to_string_lossy().into_owned()),
+                );
+            }
+            Ok(names)
+        }
+        BackendType::SQLite3 => {
+            let path = dir.join("players.sqlite");
+            if !path.is_file() {
+                return Ok(Vec::new());
+            }
+            let error = || WorldError::DatabaseError(format!("Failed to read {}", path.display()));
+            let conn = Connection::open(&path).map_err(|_| error())?;
+            let mut stmt = conn
+                .prepare("SELECT name FROM player")
+                .map_err(|_| error())?;
+            let names = stmt
+                .query_map([], |row| row.get(0))
+                .map_err(|_| error())?
+                .collect::<Result<Vec<String>, _>>()
+                .map_err(|_| error())?;
+            Ok(names)
+        }
+        BackendType::Dummy => Ok(Vec::new()),
+        backend => Err(WorldError::UnknownError(format!(
+            "Unsupported player backend {:?}",
+            backend
+        ))),
+    }
+}
+
+/// Cross-references the players with data against the users in the auth database
+///
+/// Luanti creates both for every player who joins, so a name in only one of them points to a
+/// partially restored backup or a database edited by hand.
+///
+/// # Arguments
+/// - `world` - The world to check
+/// - `dir` - The world directory the world was opened from
+///
+/// # Returns
+/// - A description of each mismatch, sorted by player name. Empty if the two agree.
+///
+/// # Errors
+/// - `WorldError::UnknownError` - If the player or auth backend is not supported
+/// - `WorldError::DatabaseError` - If `auth.sqlite` is corrupt or has no `auth` table
+pub fn check_player_auth_consistency(world: &World, dir: &Path) -> Result<Vec<String>, WorldError> {
+    let players: BTreeSet<String> = player_names(world, dir)?.into_iter().collect();
+    let users: BTreeSet<String> =
+        auth_user_names(world.auth_backend().unwrap_or(BackendType::Files), dir)?
+            .into_iter()
+            .collect();
+
+    let mut mismatches: Vec<(&String, String)> = players
+        .difference(&users)
+        .map(|name| (name, format!("Player {} has no auth entry", name)))
+        .chain(
+            users
+                .difference(&players)
+                .map(|name| (name, format!("Auth entry {} has no player data", name))),
+        )
+        .collect();
+    mismatches.sort();
+    Ok(mismatches
+        .into_iter()
+        .map(|(_, mismatch)| mismatch)
+        .collect())
+}
+
+/// Copies a whole world directory, checkpointing its SQLite databases first
+///
+/// Any write-ahead log of a `.sqlite` database is checkpointed into the main file so the copy is
+/// self-contained. Files are copied into a staging directory next to `dst_dir`, which only takes
+/// `dst_dir`'s place once the copied `world.mt` has been parsed successfully.
+///
+/// # Arguments
+/// - `src_dir` - The world directory to back up
+/// - `dst_dir` - Where to put the copy. Must not exist, or be empty.
+///
+/// # Errors
+/// - `WorldError::FileNotFound` - If `dst_dir` is not empty, or a file could not be read or written
+/// - `WorldError::DatabaseError` - If a database could not be checkpointed
+/// - `WorldError::CorruptData` - If the copied world could not be opened
+pub fn backup_world(src_dir: &Path, dst_dir: &Path) -> Result<(), WorldError> {
+    if dst_dir.exists() {
+        let mut entries = fs::read_dir(dst_dir).map_err(|_| {
+            WorldError::FileNotFound(format!("Failed to read {}", dst_dir.display()))
+        })?;
+        if entries.next().is_some() {
+            return Err(WorldError::FileNotFound(format!(
+                "Refusing to back up into non-empty {}",
+                dst_dir.display()
+            )));
+        }
+    }
+
+    for entry in fs::read_dir(src_dir)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", src_dir.display())))?
+    {
+        let path = entry
+            .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", src_dir.display())))?
+            .path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "sqlite")
+        {
+            checkpoint(&path)?;
+        }
+    }
+
+    let mut staging = dst_dir.as_os_str().to_owned();
+    staging.push(".partial");
+    let staging = Path::new(&staging);
+    let result = copy_dir(src_dir, staging).and_then(|_| {
+        World::open(staging).map_err(|_| {
+            WorldError::CorruptData(format!("Failed to open the copy of {}", src_dir.display()))
+        })
+    });
+    if let Err(error) = result {
+        let _ = fs::remove_dir_all(staging);
+        return Err(error);
+    }
+
+    if dst_dir.exists() {
+        fs::remove_dir(dst_dir).map_err(|_| {
+            WorldError::FileNotFound(format!("Failed to replace {}", dst_dir.display()))
+        })?;
+    }
+    fs::rename(staging, dst_dir)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to create {}", dst_dir.display())))
+}
+
+/// Moves the contents of a database's write-ahead log (if any) into the database file
+fn checkpoint(database: &Path) -> Result<(), WorldError> {
+    let conn = Connection::open(database)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to open {}", database.display())))?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .map_err(|_| {
+            WorldError::DatabaseError(format!("Failed to checkpoint {}", database.display()))
+        })
+}
+
+fn copy_dir(src_dir: &Path, dst_dir: &Path) -> Result<(), WorldError> {
+    fs::create_dir_all(dst_dir)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to create {}", dst_dir.display())))?;
+    for entry in fs::read_dir(src_dir)
+        .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", src_dir.display())))?
+    {
+        let path = entry
+            .map_err(|_| WorldError::FileNotFound(format!("Failed to read {}", src_dir.display())))?
+            .path();
+        let destination = dst_dir.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &destination)?;
+        } else {
+            fs::copy(&path, &destination).map_err(|_| {
+                WorldError::FileNotFound(format!("Failed to copy {}", path.display()))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod world_tests {
+    use super::*;
+
+    /// Creates a world directory containing only the given world.mt
+    fn world_with_metadata(world_mt: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("world.mt"), world_mt).unwrap();
+        dir
+    }
+
+    #[test]
+    fn backend_type_names() {
+        assert_eq!(format!("{}", BackendType::SQLite3), "sqlite3");
+        assert_eq!(format!("{:?}", BackendType::SQLite3), "SQLite3");
+        for backend in [
+            BackendType::SQLite3,
+            BackendType::LevelDB,
+            BackendType::Redis,
+            BackendType::PostgreSQL,
+            BackendType::Files,
+            BackendType::Dummy,
+        ] {
+            assert_eq!(BackendType::from_name(&backend.to_string()), Some(backend));
+        }
+    }
+
+    #[test]
+    fn save() {
+        let original = fs::read_to_string("assets/world_luanti_5.10/world.mt").unwrap();
+        let dir = world_with_metadata(&original);
+        World::open(dir.path()).unwrap().save(dir.path()).unwrap();
+        let saved = fs::read_to_string(dir.path().join("world.mt")).unwrap();
+
+        // Same lines in the same order, only without spaces around the `=`
+        let normalize = |text: &str| -> Vec<String> {
+            text.lines()
+                .map(|line| line.replacen(" = ", "=", 1))
+                .collect()
+        };
+        assert_eq!(normalize(&saved), normalize(&original));
+        assert!(saved.starts_with("enable_damage=true\ncreative_mode=true\n"));
+
+        // Saving again changes nothing
+        World::open(dir.path()).unwrap().save(dir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("world.mt")).unwrap(),
+            saved
+        );
+    }
+
+    #[test]
+    fn dummy_backend() {
+        let dir = world_with_metadata("gameid = minetest\nplayer_backend = dummy\n");
+        let world = World::open(dir.path()).unwrap();
+        assert_eq!(world.player_backend(), Some(BackendType::Dummy));
+    }
+
+    #[test]
+    fn missing_backend() {
+        let dir = world_with_metadata("gameid = minetest\nbackend = sqlite3\n");
+        let world = World::open(dir.path()).unwrap();
+        assert_eq!(world.backend(), Some(BackendType::SQLite3));
+        assert_eq!(world.player_backend(), None);
+        assert_eq!(world.auth_backend(), None);
+        assert_eq!(world.mod_storage_backend(), None);
+    }
+
+    #[test]
+    fn unknown_backend() {
+        let dir = world_with_metadata("auth_backend = carrier_pigeon\n");
+        let world = World::open(dir.path()).unwrap();
+        assert_eq!(world.auth_backend(), None);
+    }
+
+    #[test]
+    fn backup_world() {
+        let source = Path::new("assets/world_luanti_5.10");
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("backup");
+        super::backup_world(source, &destination).unwrap();
+
+        let original = World::open(source).unwrap();
+        let copy = World::open(&destination).unwrap();
+        assert_eq!(copy.game_id(), original.game_id());
+        assert_eq!(copy.backend(), original.backend());
+        assert!(!dir.path().join("backup.partial").exists());
+
+        let count = |world: &Path| -> i64 {
+            Connection::open(world.join("map.sqlite"))
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM blocks", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count(&destination), count(source));
+
+        // The destination now holds the backup
+        assert!(super::backup_world(source, &destination).is_err());
+        assert!(World::open(&destination).is_ok());
+    }
+
+    #[test]
+    fn backup_world_into_empty_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        super::backup_world(Path::new("assets/world_luanti_5.10"), dir.path()).unwrap();
+        assert!(World::open(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn scan_worlds() {
+        let dir = tempfile::tempdir().unwrap();
+        super::backup_world(
+            Path::new("assets/world_luanti_5.10"),
+            &dir.path().join("sample"),
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("not_a_world")).unwrap();
+        fs::write(dir.path().join("not_a_world").join("notes.txt"), "hello").unwrap();
+        fs::write(dir.path().join("world.mt"), "gameid = stray\n").unwrap();
+        fs::create_dir(dir.path().join("another")).unwrap();
+        fs::write(
+            dir.path().join("another").join("world.mt"),
+            "gameid = mineclonia\n",
+        )
+        .unwrap();
+
+        let worlds = super::scan_worlds(dir.path()).unwrap();
+        let names: Vec<&str> = worlds.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["another", "sample"]);
+        assert_eq!(worlds[0].1.game_id(), "mineclonia");
+        assert_eq!(
+            worlds[1].1.game_id(),
+            World::open(Path::new("assets/world_luanti_5.10"))
+                .unwrap()
+                .game_id()
+        );
+
+        assert!(super::scan_worlds(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn mods_with_conf() {
+        let dir = world_with_metadata(
+            "gameid = minetest\nload_mod_doors = true\nload_mod_farming = false\n",
+        );
+        let world = World::open(dir.path()).unwrap();
+        assert_eq!(world.enabled_mods(), &["doors"]);
+
+        let game = tempfile::tempdir().unwrap();
+        fs::create_dir_all(game.path().join("mods/doors")).unwrap();
+        fs::write(
+            game.path().join("mods/doors/mod.conf"),
+            "name = doors\ntitle = Doors\n",
+        )
+        .unwrap();
+
+        let mods = world.mods_with_conf(game.path()).unwrap();
+        assert_eq!(mods.len(), 1);
+        assert_eq!(mods[0].title, "Doors");
+    }
+
+    #[test]
+    fn thumbnail_path() {
+        let dir = world_with_metadata("gameid = minetest\nworld_name = Sunny Isle\n");
+        let world = World::open(dir.path()).unwrap();
+        assert_eq!(world.thumbnail_path(dir.path()), None);
+        assert_eq!(world.listing(dir.path()).thumbnail, None);
+
+        fs::write(dir.path().join("screenshot.png"), b"\x89PNG").unwrap();
+        assert_eq!(
+            world.thumbnail_path(dir.path()),
+            Some(dir.path().join("screenshot.png"))
+        );
+
+        let listing = world.listing(dir.path());
+        assert_eq!(listing.name, "Sunny Isle");
+        assert_eq!(listing.game_id, "minetest");
+        assert_eq!(listing.thumbnail, Some(dir.path().join("screenshot.png")));
+    }
+
+    #[test]
+    fn mapgen_limit() {
+        let dir = world_with_metadata("gameid = minetest\n");
+        std::fs::write(
+            dir.path().join("map_meta.txt"),
+            "mg_name = v7\nmapgen_limit = 2000\nmg_biome_np_heat = {\n\tseed = 5349\n\t}\n[end_of_params]\n",
+        )
+        .unwrap();
+        assert_eq!(World::open(dir.path()).unwrap().mapgen_limit(), 2000);
+
+        let dir = world_with_metadata("gameid = minetest\n");
+        std::fs::write(
+            dir.path().join("map_meta.txt"),
+            "mg_name = v7\n[end_of_params]\n",
+        )
+        .unwrap();
+        assert_eq!(World::open(dir.path()).unwrap().mapgen_limit(), 31000);
+
+        let sample = World::open(Path::new("assets/world_luanti_5.10")).unwrap();
+        assert_eq!(sample.mapgen_limit(), 31007);
+    }
+
+    #[test]
+    fn check_player_auth_consistency() {
+        let dir = world_with_metadata("gameid = minetest\n");
+        fs::write(
+            dir.path().join("auth.txt"),
+            "alice::interact\nbob::interact,shout\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("players")).unwrap();
+        for name in ["alice", "carol"] {
+            fs::write(
+                dir.path().join("players").join(name),
+                format!("hp = 20\nname = {}\nPlayerArgsEnd\nList main 32\nEndInventoryList\nEndInventory\n", name),
+            )
+            .unwrap();
+        }
+
+        let world = World::open(dir.path()).unwrap();
+        assert_eq!(
+            super::check_player_auth_consistency(&world, dir.path()).unwrap(),
+            [
+                "Auth entry bob has no player data",
+                "Player carol has no auth entry"
+            ]
+        );
+
+        let sample = Path::new("assets/world_luanti_5.10");
+        let world = World::open(sample).unwrap();
+        assert!(super::check_player_auth_consistency(&world, sample)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn check_player_auth_consistency_corrupt() {
+        let dir = world_with_metadata("gameid = minetest\nauth_backend = sqlite3\n");
+        let world = World::open(dir.path()).unwrap();
+
+        fs::write(
+            dir.path().join("auth.sqlite"),
+            b"not a database, just garbage",
+        )
+        .unwrap();
+        assert!(matches!(
+            super::check_player_auth_consistency(&world, dir.path()),
+            Err(WorldError::DatabaseError(_))
+        ));
+
+        // A database, but not an auth database
+        fs::remove_file(dir.path().join("auth.sqlite")).unwrap();
+        Connection::open(dir.path().join("auth.sqlite"))
+            .unwrap()
+            .execute_batch("CREATE TABLE other (id INTEGER)")
+            .unwrap();
+        assert!(matches!(
+            super::check_player_auth_consistency(&world, dir.path()),
+            Err(WorldError::DatabaseError(_))
+        ));
+    }
+
+    #[test]
+    fn read_kv() {
+        let dir = Path::new("assets/world_luanti_5.10");
+        let world = World::open(dir).unwrap();
+        let world_mt = world.read_kv(dir, "world.mt").unwrap();
+        assert_eq!(world_mt.get("world_name"), Some("test".to_string()));
+        assert_eq!(world_mt.get("backend"), Some("sqlite3".to_string()));
+
+        assert!(matches!(
+            world.read_kv(dir, "minetest.conf"),
+            Err(WorldError::FileNotFound(_))
+        ));
+    }
 }
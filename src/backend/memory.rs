@@ -1,11 +1,13 @@
-use std::{iter::Map, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
-    Area, Block, Coordinate, CoordinateScalar, WorldError, 
-    WorldRegistry, WorldPartition, WorldReader, WorldWriter
+    Area, Block, Coordinate, CoordinateFrame, SpatialCoordinate, WorldError, WorldPartition,
+    WorldReader, WorldRegistry,
 };
 
-struct MemoryBlock {
+type Scalar = <SpatialCoordinate as Coordinate>::Scalar;
+
+pub struct MemoryBlock {
     id: Arc<String>,
 }
 
@@ -23,110 +25,200 @@ impl PartialEq for MemoryBlock {
 
 impl Eq for MemoryBlock {}
 
+#[derive(Default)]
 pub struct MemoryWorldRegistry {
-    registry: Map<i32, (String, String)>
+    // id -> (name, description)
+    registry: HashMap<i32, (Arc<String>, String)>,
 }
 
 impl MemoryWorldRegistry {
     pub fn new() -> MemoryWorldRegistry {
         MemoryWorldRegistry {
-            registry: Map 
+            registry: HashMap::new(),
         }
     }
 
-    fn clear(&mut self) {
+    pub fn clear(&mut self) {
         self.registry.clear();
     }
 
-    fn insert(&mut self, id: i32, name: String, description: String) {
-        self.registry.insert(id, (name, description));
+    pub fn insert(&mut self, id: i32, name: String, description: String) {
+        self.registry.insert(id, (Arc::new(name), description));
     }
 
-    fn remove(&mut self, id: i32) {
-        self.registry.remove(id);
+    pub fn remove(&mut self, id: i32) {
+        self.registry.remove(&id);
     }
 
-    fn block_name(&self, id: &str) -> Option<String> {
-        self.registry.get(id).map(|(name, _)| name.clone())
+    pub fn block_name(&self, id: i32) -> Option<String> {
+        self.registry.get(&id).map(|(name, _)| name.to_string())
     }
 
-    fn block_description(&self, id: &str) -> Option<String> {
-        self.registry.get(id).map(|(_, description)| description.clone())
+    pub fn block_description(&self, id: i32) -> Option<String> {
+        self.registry
+            .get(&id)
+            .map(|(_, description)| description.clone())
     }
 }
 
 impl WorldRegistry<MemoryBlock> for MemoryWorldRegistry {
     fn create_block(&self, id: i32) -> Result<MemoryBlock, WorldError> {
-        match self.registry.get(id) {
-            Some((name, description)) => Ok(MemoryBlock { id, registry: self }),
-            None => Err(WorldError::BlockNotFound)
+        match self.registry.get(&id) {
+            // Blocks share the registry's name rather than each holding a copy
+            Some((name, _)) => Ok(MemoryBlock { id: name.clone() }),
+            None => Err(WorldError::IdNotFound(id)),
         }
     }
 
     fn all_blocks(&self) -> Vec<MemoryBlock> {
-        todo!()
+        self.registry
+            .values()
+            .map(|(name, _)| MemoryBlock { id: name.clone() })
+            .collect()
     }
 }
 
-struct MemoryWorldPartition {
+pub struct MemoryWorldPartition {
+    /// World position of the partition's lowest corner
+    origin: SpatialCoordinate,
+    size: SpatialCoordinate,
+}
 
+impl MemoryWorldPartition {
+    /// Creates a partition spanning `size` nodes (positive on every axis) from `origin`, its lowest corner
+    pub fn new(origin: SpatialCoordinate, size: SpatialCoordinate) -> MemoryWorldPartition {
+        MemoryWorldPartition { origin, size }
+    }
 }
 
-impl WorldPartition for MemoryWorldPartition {
-    fn area(&self, frame: crate::CoordinateFrame) -> Area {
-        todo!()
+impl WorldPartition<MemoryWorldPartition, MemoryBlock> for MemoryWorldPartition {
+    fn area(&self, frame: CoordinateFrame) -> Area {
+        let last = self.size - SpatialCoordinate { x: 1, y: 1, z: 1 };
+        match frame {
+            CoordinateFrame::World => Area {
+                from: self.origin,
+                to: self.origin + last,
+            },
+            CoordinateFrame::Relative => Area {
+                from: SpatialCoordinate::zero(),
+                to: last,
+            },
+            CoordinateFrame::Index => {
+                let index = SpatialCoordinate {
+                    x: self.origin.x.div_euclid(self.size.x),
+                    y: self.origin.y.div_euclid(self.size.y),
+                    z: self.origin.z.div_euclid(self.size.z),
+                };
+                Area {
+                    from: index,
+                    to: index,
+                }
+            }
+        }
     }
 
-    fn world_dimensions(&self) -> Coordinate {
-        todo!()
+    fn world_dimensions(&self) -> SpatialCoordinate {
+        self.size
     }
 
-    fn local_dimensions(&self) -> Coordinate {
-        todo!()
+    fn local_dimensions(&self) -> SpatialCoordinate {
+        self.size
     }
 
-    fn block_at_pos(&self, coord: Coordinate, reference: crate::CoordinateFrame) -> Result<&B, ()> {
-        todo!()
+    // Partitions don't store blocks or child partitions yet, so every lookup comes up empty
+
+    fn block_at_pos(
+        &self,
+        _coord: SpatialCoordinate,
+        _reference: CoordinateFrame,
+    ) -> Result<&MemoryBlock, ()> {
+        Err(())
     }
 
     fn block_at_pos_mut(
         &mut self,
-        coord: Coordinate,
-        reference: crate::CoordinateFrame,
-    ) -> Result<&mut B, ()> {
-        todo!()
+        _coord: SpatialCoordinate,
+        _reference: CoordinateFrame,
+    ) -> Result<&mut MemoryBlock, ()> {
+        Err(())
     }
 
-    fn child_at_pos(&self, coord: Coordinate) -> Result<&T, ()> {
-        todo!()
+    fn child_at_pos(&self, _coord: SpatialCoordinate) -> Result<&MemoryWorldPartition, ()> {
+        Err(())
     }
 
-    fn child_at_pos_mut(&mut self, coord: Coordinate) -> Result<&mut T, ()> {
-        todo!()
+    fn child_at_pos_mut(
+        &mut self,
+        _coord: SpatialCoordinate,
+    ) -> Result<&mut MemoryWorldPartition, ()> {
+        Err(())
     }
 
-    fn blocks(&self) -> dyn Iterator<Item = &B> {
-        todo!()
+    fn blocks(&self) -> Box<dyn Iterator<Item = &MemoryBlock> + '_> {
+        Box::new(std::iter::empty())
     }
 
-    fn blocks_mut(&mut self) -> dyn Iterator<Item = &mut B> {
-        todo!()
+    fn blocks_mut(&mut self) -> Box<dyn Iterator<Item = &mut MemoryBlock> + '_> {
+        Box::new(std::iter::empty())
     }
 
-    fn children(&self) -> dyn Iterator<Item = &T> {
-        todo!()
+    fn children(&self) -> Box<dyn Iterator<Item = &MemoryWorldPartition> + '_> {
+        Box::new(std::iter::empty())
     }
 
-    fn children_mut(&mut self) -> dyn Iterator<Item = &mut T> {
-        todo!()
+    fn children_mut(&mut self) -> Box<dyn Iterator<Item = &mut MemoryWorldPartition> + '_> {
+        Box::new(std::iter::empty())
     }
 }
 
-struct MemoryWorld {
+pub struct MemoryWorld {
+    registry: MemoryWorldRegistry,
+    max_area: Area,
+    partitions: Vec<MemoryWorldPartition>,
+}
+
+impl MemoryWorld {
+    /// Creates a world spanning every representable coordinate
+    pub fn new(registry: MemoryWorldRegistry) -> MemoryWorld {
+        MemoryWorld {
+            registry,
+            max_area: Area {
+                from: SpatialCoordinate {
+                    x: Scalar::MIN,
+                    y: Scalar::MIN,
+                    z: Scalar::MIN,
+                },
+                to: SpatialCoordinate {
+                    x: Scalar::MAX,
+                    y: Scalar::MAX,
+                    z: Scalar::MAX,
+                },
+            },
+            partitions: Vec::new(),
+        }
+    }
+
+    /// Limits the world to the given area
+    pub fn set_max_area(&mut self, area: Area) {
+        self.max_area = area;
+    }
+
+    /// The kinds of blocks this world can create
+    pub fn registry(&self) -> &MemoryWorldRegistry {
+        &self.registry
+    }
+
+    pub fn registry_mut(&mut self) -> &mut MemoryWorldRegistry {
+        &mut self.registry
+    }
 
+    /// Adds a partition to the world. Partitions are expected not to overlap.
+    pub fn insert_partition(&mut self, partition: MemoryWorldPartition) {
+        self.partitions.push(partition);
+    }
 }
 
-impl WorldReader<MemoryBlock, MemoryWorldPartition> for MemoryWorld {
+impl WorldReader<SpatialCoordinate, MemoryBlock, MemoryWorldPartition> for MemoryWorld {
     fn name(&self) -> String {
         "Generic In-Memory World Data".to_string()
     }
@@ -135,42 +227,181 @@ impl WorldReader<MemoryBlock, MemoryWorldPartition> for MemoryWorld {
         Some("A world stored in memory. Can be reconfigured as needed to suit various world structures.".to_string())
     }
 
-    fn max_area(&self) -> crate::Area {
+    fn max_area(&self) -> Area {
         Area {
-            from: Coordinate {
-                x: CoordinateScalar::MIN,
-                y: CoordinateScalar::MIN,
-                z: CoordinateScalar::MIN,
-            },
-            to: Coordinate {
-                x: CoordinateScalar::MAX,
-                y: CoordinateScalar::MAX,
-                z: CoordinateScalar::MAX,
-            },
+            from: self.max_area.from,
+            to: self.max_area.to,
         }
     }
 
-    fn bottom(&self) -> CoordinateScalar {
-        CoordinateScalar::MIN
+    fn bottom(&self) -> Scalar {
+        self.max_area.from.z
     }
 
-    fn top(&self) -> CoordinateScalar {
-        CoordinateScalar::MAX
+    fn top(&self) -> Scalar {
+        self.max_area.to.z
     }
 
-    fn node_at_pos(&self, coord: crate::Coordinate) -> Result<&B, ()> {
-        todo!()
+    fn node_at_pos(&self, coord: SpatialCoordinate) -> Result<&MemoryBlock, ()> {
+        self.partition_at_pos(coord)
+            .map_err(|_| ())?
+            .block_at_pos(coord, CoordinateFrame::World)
     }
 
-    fn partition_at_pos(&self, coord: crate::Coordinate) -> Result<&P, ()> {
-        todo!()
+    fn partition_at_pos(
+        &self,
+        coord: SpatialCoordinate,
+    ) -> Result<&MemoryWorldPartition, WorldError> {
+        self.partitions
+            .iter()
+            .find(|partition| partition.area(CoordinateFrame::World).contains(coord))
+            .ok_or(WorldError::PartitionNotFound(coord))
     }
 
+    /// The number of nodes covered by the world's partitions, saturating at `i64::MAX`
     fn volume(&self) -> i64 {
-        todo!()
+        self.partitions.iter().fold(0i64, |volume, partition| {
+            let size = partition.size;
+            let nodes = i64::from(size.x)
+                .saturating_mul(i64::from(size.y))
+                .saturating_mul(i64::from(size.z));
+            volume.saturating_add(nodes)
+        })
+    }
+
+    fn partitions(&self) -> Box<dyn Iterator<Item = &MemoryWorldPartition> + '_> {
+        Box::new(self.partitions.iter())
+    }
+
+    fn new_block(&self, id: i32) -> Result<MemoryBlock, WorldError> {
+        self.registry.create_block(id)
+    }
+}
+
+#[cfg(test)]
+mod memory_world_tests {
+    use super::*;
+
+    #[test]
+    fn new_block() {
+        let mut registry = MemoryWorldRegistry::new();
+        registry.insert(1, "default:stone".to_string(), "Stone".to_string());
+        let world = MemoryWorld::new(registry);
+
+        let block = world.new_block(1).unwrap();
+        assert_eq!(block.id(), "default:stone");
+        assert!(block == world.new_block(1).unwrap());
+        assert_eq!(
+            world.registry().block_description(1),
+            Some("Stone".to_string())
+        );
+
+        assert!(matches!(world.new_block(2), Err(WorldError::IdNotFound(2))));
     }
 
-    fn partitions(&self) -> dyn Iterator<Item = &P> {
-        todo!()
+    #[test]
+    fn contains_position() {
+        let mut world = MemoryWorld::new(MemoryWorldRegistry::new());
+        let far = SpatialCoordinate {
+            x: 1_000_000,
+            y: -1_000_000,
+            z: 0,
+        };
+        assert!(world.contains_position(far));
+
+        world.set_max_area(Area {
+            from: SpatialCoordinate {
+                x: -100,
+                y: -100,
+                z: -100,
+            },
+            to: SpatialCoordinate {
+                x: 100,
+                y: 100,
+                z: 100,
+            },
+        });
+        assert!(world.contains_position(SpatialCoordinate {
+            x: 100,
+            y: 0,
+            z: -100
+        }));
+        assert!(!world.contains_position(SpatialCoordinate { x: 101, y: 0, z: 0 }));
+        assert!(!world.contains_position(far));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn partition_at_pos() {
+        let mut world = MemoryWorld::new(MemoryWorldRegistry::new());
+        let size = SpatialCoordinate {
+            x: 16,
+            y: 16,
+            z: 16,
+        };
+        world.insert_partition(MemoryWorldPartition::new(SpatialCoordinate::zero(), size));
+        world.insert_partition(MemoryWorldPartition::new(
+            SpatialCoordinate {
+                x: -16,
+                y: 32,
+                z: 0,
+            },
+            size,
+        ));
+
+        let origin_of = |coord| {
+            world
+                .partition_at_pos(coord)
+                .unwrap()
+                .area(CoordinateFrame::World)
+                .from
+        };
+        let first = origin_of(SpatialCoordinate { x: 15, y: 0, z: 7 });
+        assert_eq!((first.x, first.y, first.z), (0, 0, 0));
+        let second = origin_of(SpatialCoordinate {
+            x: -1,
+            y: 47,
+            z: 15,
+        });
+        assert_eq!((second.x, second.y, second.z), (-16, 32, 0));
+
+        let outside = SpatialCoordinate { x: 16, y: 0, z: 0 };
+        assert!(matches!(
+            world.partition_at_pos(outside),
+            Err(WorldError::PartitionNotFound(_))
+        ));
+        assert_eq!(world.partitions().count(), 2);
+    }
+
+    #[test]
+    fn empty_partitions() {
+        let mut world = MemoryWorld::new(MemoryWorldRegistry::new());
+        assert_eq!(world.volume(), 0);
+        let size = SpatialCoordinate { x: 16, y: 8, z: 4 };
+        world.insert_partition(MemoryWorldPartition::new(SpatialCoordinate::zero(), size));
+        world.insert_partition(MemoryWorldPartition::new(
+            SpatialCoordinate { x: 16, y: 0, z: 0 },
+            size,
+        ));
+        assert_eq!(world.volume(), 2 * 16 * 8 * 4);
+
+        // Nothing is stored within the partitions
+        let inside = SpatialCoordinate { x: 3, y: 2, z: 1 };
+        assert!(world.node_at_pos(inside).is_err());
+        assert!(world
+            .node_at_pos(SpatialCoordinate { x: 0, y: 100, z: 0 })
+            .is_err());
+        let mut partition = MemoryWorldPartition::new(SpatialCoordinate::zero(), size);
+        assert!(partition
+            .block_at_pos(inside, CoordinateFrame::Relative)
+            .is_err());
+        assert!(partition
+            .block_at_pos_mut(inside, CoordinateFrame::World)
+            .is_err());
+        assert!(partition.child_at_pos(inside).is_err());
+        assert!(partition.child_at_pos_mut(inside).is_err());
+        assert_eq!(partition.blocks().count(), 0);
+        assert_eq!(partition.blocks_mut().count(), 0);
+        assert_eq!(partition.children().count(), 0);
+        assert_eq!(partition.children_mut().count(), 0);
+    }
+}
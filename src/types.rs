@@ -1,10 +1,11 @@
 use std::fmt::{self, Debug, Display};
-use std::ops::{Add, Sub};
+use std::ops::{Add, Div, Mul, Sub};
 
 /// The frame of reference for a coordinate to be interpreted with.
 ///
 /// This is used to determine how a coordinate should be used.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CoordinateFrame {
     /// The coordinate is relative to the world, and is an absolute position.
     ///
@@ -28,9 +29,12 @@ pub enum CoordinateFrame {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CoordinateError {
     OutOfBounds,
     InvalidFrame,
+    /// The coordinate could not be parsed from text
+    InvalidFormat,
 }
 
 /// A generic 3D coordinate trait.
@@ -49,9 +53,11 @@ pub trait Coordinate: Sized + Add + Sub + Debug + Display + PartialEq + Eq {
     fn right() -> Self;
     fn forward() -> Self;
     fn back() -> Self;
-    fn from<T: Coordinate>(coord: T) -> Result<Self, CoordinateError>
-    where
-        Self::Internal: From<T::Scalar>;
+    /// Converts from any other kind of coordinate.
+    ///
+    /// Components are cast numerically, so narrowing conversions are allowed, and
+    /// return `CoordinateError::OutOfBounds` when a component does not fit.
+    fn from<T: Coordinate>(coord: T) -> Result<Self, CoordinateError>;
 }
 
 #[cfg(feature = "big_coordinates")]
@@ -61,6 +67,7 @@ type SpatialCoordinateScalar = i32;
 
 /// A generic 3D coordinate in the world.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpatialCoordinate {
     pub x: SpatialCoordinateScalar,
     pub y: SpatialCoordinateScalar,
@@ -119,18 +126,127 @@ impl Coordinate for SpatialCoordinate {
         Self { x: 0, y: 0, z: -1 }
     }
     #[inline]
-    fn from<T: Coordinate>(coord: T) -> Result<Self, CoordinateError>
-    where
-        Self::Internal: From<T::Scalar>,
-    {
+    fn from<T: Coordinate>(coord: T) -> Result<Self, CoordinateError> {
+        let cast = |component: T::Scalar| {
+            <Self::Scalar as num::NumCast>::from(component).ok_or(CoordinateError::OutOfBounds)
+        };
         Ok(Self {
-            x: Self::Scalar::from(coord.x()),
-            y: Self::Scalar::from(coord.y()),
-            z: Self::Scalar::from(coord.z()),
+            x: cast(coord.x())?,
+            y: cast(coord.y())?,
+            z: cast(coord.z())?,
         })
     }
 }
 
+impl SpatialCoordinate {
+    /// Creates a coordinate from its components. A `(x, y, z)` tuple or `[x, y, z]` array converts
+    /// with `into()` as well, and back again.
+    pub fn new(
+        x: SpatialCoordinateScalar,
+        y: SpatialCoordinateScalar,
+        z: SpatialCoordinateScalar,
+    ) -> SpatialCoordinate {
+        SpatialCoordinate { x, y, z }
+    }
+
+    /// Formats the coordinate as a node position, the way Luanti does (as in `minetest.pos_to_string`).
+    ///
+    /// # Example
+    /// `(-1,17,32)`
+    pub fn format_node(&self) -> String {
+        format!("({},{},{})", self.x, self.y, self.z)
+    }
+
+    /// Formats the position of the MapBlock (16x16x16 nodes) containing this node, the way Luanti does.
+    ///
+    /// # Example
+    /// The node `(-1,17,32)` is within block `(-1,1,2)`
+    pub fn format_block(&self) -> String {
+        self.to_block_coord().format_node()
+    }
+
+    /// True if both nodes are within the same MapBlock (16x16x16 nodes)
+    ///
+    /// # Example
+    /// The nodes `(-1,0,0)` and `(-16,0,0)` share block `(-1,0,0)`, but `(0,0,0)` is in block `(0,0,0)`
+    pub fn same_block(&self, other: &SpatialCoordinate) -> bool {
+        self.to_block_coord() == other.to_block_coord()
+    }
+
+    /// Returns the position of the MapBlock (16x16x16 nodes) containing this node.
+    ///
+    /// Rounds down, so negative nodes belong to negative blocks.
+    ///
+    /// # Example
+    /// The node `(-1,17,32)` is within block `(-1,1,2)`
+    pub fn to_block_coord(&self) -> SpatialCoordinate {
+        *self / 16
+    }
+
+    /// Returns the position of this node within its MapBlock, each component in `0..16`.
+    ///
+    /// # Example
+    /// The node `(-1,17,32)` is at `(15,1,0)` within its block
+    pub fn to_node_offset(&self) -> (u8, u8, u8) {
+        let offset = |value: SpatialCoordinateScalar| value.rem_euclid(16) as u8;
+        (offset(self.x), offset(self.y), offset(self.z))
+    }
+
+    /// Returns the smaller of each component of the two coordinates.
+    ///
+    /// # Example
+    /// `(1, -5, 3).min((2, 0, -4))` is `(1, -5, -4)`
+    pub fn min(self, other: SpatialCoordinate) -> SpatialCoordinate {
+        SpatialCoordinate {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    /// Returns the larger of each component of the two coordinates.
+    ///
+    /// # Example
+    /// `(1, -5, 3).max((2, 0, -4))` is `(2, 0, 3)`
+    pub fn max(self, other: SpatialCoordinate) -> SpatialCoordinate {
+        SpatialCoordinate {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
+}
+
+type SpatialCoordinateTuple = (
+    SpatialCoordinateScalar,
+    SpatialCoordinateScalar,
+    SpatialCoordinateScalar,
+);
+
+impl From<SpatialCoordinateTuple> for SpatialCoordinate {
+    fn from((x, y, z): SpatialCoordinateTuple) -> Self {
+        SpatialCoordinate { x, y, z }
+    }
+}
+
+impl From<[SpatialCoordinateScalar; 3]> for SpatialCoordinate {
+    fn from([x, y, z]: [SpatialCoordinateScalar; 3]) -> Self {
+        SpatialCoordinate { x, y, z }
+    }
+}
+
+impl From<SpatialCoordinate> for SpatialCoordinateTuple {
+    fn from(coord: SpatialCoordinate) -> Self {
+        (coord.x, coord.y, coord.z)
+    }
+}
+
+impl From<SpatialCoordinate> for [SpatialCoordinateScalar; 3] {
+    fn from(coord: SpatialCoordinate) -> Self {
+        [coord.x, coord.y, coord.z]
+    }
+}
+
 impl Add for SpatialCoordinate {
     type Output = SpatialCoordinate;
 
@@ -155,6 +271,44 @@ impl Sub for SpatialCoordinate {
     }
 }
 
+impl Mul<SpatialCoordinateScalar> for SpatialCoordinate {
+    type Output = SpatialCoordinate;
+
+    fn mul(self, scalar: SpatialCoordinateScalar) -> SpatialCoordinate {
+        SpatialCoordinate {
+            x: self.x * scalar,
+            y: self.y * scalar,
+            z: self.z * scalar,
+        }
+    }
+}
+
+/// Divides each component, rounding down rather than towards zero, as positions are divided into
+/// MapBlocks: `(-3, 0, 0) / 2` is `(-2, 0, 0)`, not `(-1, 0, 0)`.
+///
+/// # Panics
+/// If `scalar` is zero.
+impl Div<SpatialCoordinateScalar> for SpatialCoordinate {
+    type Output = SpatialCoordinate;
+
+    fn div(self, scalar: SpatialCoordinateScalar) -> SpatialCoordinate {
+        // div_euclid only rounds down for positive divisors
+        let floor = |value: SpatialCoordinateScalar| {
+            let quotient = value / scalar;
+            if value % scalar != 0 && (value < 0) != (scalar < 0) {
+                quotient - 1
+            } else {
+                quotient
+            }
+        };
+        SpatialCoordinate {
+            x: floor(self.x),
+            y: floor(self.y),
+            z: floor(self.z),
+        }
+    }
+}
+
 impl Debug for SpatialCoordinate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "({}, {}, {})", self.x, self.y, self.z)
@@ -175,16 +329,105 @@ impl PartialEq for SpatialCoordinate {
 
 impl Eq for SpatialCoordinate {}
 
+/// Returns every coordinate along the line between two points, inclusive of both ends.
+///
+/// Uses 3D Bresenham, so each coordinate is adjacent (including diagonally) to the one before it.
+///
+/// # Example
+/// ```rust
+/// use minecraft_world::types::{line, SpatialCoordinate};
+///
+/// let from = SpatialCoordinate { x: 0, y: 0, z: 0 };
+/// let to = SpatialCoordinate { x: 4, y: 2, z: 0 };
+///
+/// let points = line(from, to);
+/// assert_eq!(points.len(), 5);
+/// assert_eq!(points[0], from);
+/// assert_eq!(points[4], to);
+/// ```
+pub fn line(from: SpatialCoordinate, to: SpatialCoordinate) -> Vec<SpatialCoordinate> {
+    let mut position = [from.x, from.y, from.z];
+    let delta = [to.x - from.x, to.y - from.y, to.z - from.z];
+    let length = delta.map(|d| d.abs());
+    let step = delta.map(|d| d.signum());
+
+    // The axis with the greatest change advances every step, the others when their error overflows
+    let driving = (0..3).max_by_key(|axis| length[*axis]).unwrap();
+    let steps = length[driving];
+    let mut error = length.map(|l| 2 * l - steps);
+
+    let mut points = Vec::with_capacity(steps as usize + 1);
+    points.push(from);
+    for _ in 0..steps {
+        for axis in 0..3 {
+            if axis == driving {
+                position[axis] += step[axis];
+                continue;
+            }
+            if error[axis] > 0 {
+                position[axis] += step[axis];
+                error[axis] -= 2 * steps;
+            }
+            error[axis] += 2 * length[axis];
+        }
+        points.push(SpatialCoordinate {
+            x: position[0],
+            y: position[1],
+            z: position[2],
+        });
+    }
+    points
+}
+
+/// Returns every coordinate within `radius` of `center`, or only those on the outer shell if `hollow`.
+///
+/// A coordinate is inside when its squared distance to the center is at most `radius²`,
+/// and on the shell when it is also greater than `(radius - 1)²`.
+///
+/// # Example
+/// ```rust
+/// use minecraft_world::types::{sphere, SpatialCoordinate};
+///
+/// let center = SpatialCoordinate { x: 0, y: 0, z: 0 };
+///
+/// // The center and its six neighbours
+/// assert_eq!(sphere(center, 1, false).len(), 7);
+/// ```
+pub fn sphere(center: SpatialCoordinate, radius: u32, hollow: bool) -> Vec<SpatialCoordinate> {
+    let radius = radius as SpatialCoordinateScalar;
+    let outer = radius * radius;
+    let inner = (radius - 1) * (radius - 1);
+
+    let mut points = Vec::new();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let distance = x * x + y * y + z * z;
+                if distance > outer || (hollow && radius > 0 && distance <= inner) {
+                    continue;
+                }
+                points.push(SpatialCoordinate {
+                    x: center.x + x,
+                    y: center.y + y,
+                    z: center.z + z,
+                });
+            }
+        }
+    }
+    points
+}
+
 /// An area in the world.
 ///
 /// This is used to represent a volume of space in the world.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Area {
     pub from: SpatialCoordinate,
     pub to: SpatialCoordinate,
 }
 
 impl Area {
-    /// Creates a new area with zero volume.
+    /// Creates an area holding only the origin.
     #[allow(dead_code)]
     pub fn zero() -> Area {
         Area {
@@ -193,22 +436,20 @@ impl Area {
         }
     }
 
-    /// Checks if the area contains the given coordinate.
+    /// Checks if the area contains the given coordinate. Both corners are part of the area.
     ///
     /// # Example
     /// ```rust
-    /// use minecraft_world::types::{Area, Coordinate};
+    /// use minecraft_world::types::{Area, Coordinate, SpatialCoordinate};
     ///
     /// let area = Area {
-    ///    from: Coordinate::zero(),
-    ///   to: Coordinate { x: 10, y: 10, z: 10 }
+    ///     from: SpatialCoordinate::zero(),
+    ///     to: SpatialCoordinate::new(10, 10, 10),
     /// };
     ///
-    /// let a = Coordinate { x: 5, y: 5, z: 5 };
-    /// let b = Coordinate { x: 15, y: 15, z: 15 };
-    ///
-    /// assert!(area.contains(a));
-    /// assert!(!area.contains(b));
+    /// assert!(area.contains(SpatialCoordinate::new(5, 5, 5)));
+    /// assert!(area.contains((10, 10, 10).into()));
+    /// assert!(!area.contains(SpatialCoordinate::new(15, 15, 15)));
     /// ```
     #[allow(dead_code)]
     pub fn contains(&self, coord: SpatialCoordinate) -> bool {
@@ -227,22 +468,236 @@ impl Area {
         self.to = self.to + coord;
     }
 
-    /// Returns the contained volume of the area.
+    /// Re-expresses the area in another frame of reference.
+    ///
+    /// # Arguments
+    /// - `from` - The frame the area is currently in
+    /// - `to` - The frame to convert it to
+    /// - `origin` - World position of the partition that `Relative` coordinates are relative to
+    /// - `size` - Dimensions of a partition, used to convert from `Index` coordinates
+    ///
+    /// An area of indices converts to the full extent of the partitions it spans.
+    ///
+    /// # Errors
+    /// - `CoordinateError::InvalidFrame` - If `to` is `Index` (an arbitrary area does not map onto whole
+    ///   partitions), or `from` is `Index` and `size` is not positive on every axis
+    #[allow(dead_code)]
+    pub fn in_frame(
+        &self,
+        from: CoordinateFrame,
+        to: CoordinateFrame,
+        origin: SpatialCoordinate,
+        size: SpatialCoordinate,
+    ) -> Result<Area, CoordinateError> {
+        if from == to {
+            return Ok(Area {
+                from: self.from,
+                to: self.to,
+            });
+        }
+
+        // Convert both corners to the world frame first
+        let world = match from {
+            CoordinateFrame::World => Area {
+                from: self.from,
+                to: self.to,
+            },
+            CoordinateFrame::Relative => Area {
+                from: self.from + origin,
+                to: self.to + origin,
+            },
+            CoordinateFrame::Index => {
+                if size.x <= 0 || size.y <= 0 || size.z <= 0 {
+                    return Err(CoordinateError::InvalidFrame);
+                }
+                let scale = |coord: SpatialCoordinate| SpatialCoordinate {
+                    x: coord.x * size.x,
+                    y: coord.y * size.y,
+                    z: coord.z * size.z,
+                };
+                Area {
+                    from: scale(self.from),
+                    to: scale(self.to) + size - SpatialCoordinate { x: 1, y: 1, z: 1 },
+                }
+            }
+        };
+
+        match to {
+            CoordinateFrame::World => Ok(world),
+            CoordinateFrame::Relative => Ok(Area {
+                from: world.from - origin,
+                to: world.to - origin,
+            }),
+            CoordinateFrame::Index => Err(CoordinateError::InvalidFrame),
+        }
+    }
+
+    /// Returns the same area with `from` as its lowest corner and `to` as its highest.
+    ///
+    /// # Example
+    /// ```rust
+    /// use minecraft_world::types::{Area, SpatialCoordinate};
+    ///
+    /// let area = Area {
+    ///     from: SpatialCoordinate::new(5, 0, -3),
+    ///     to: SpatialCoordinate::new(1, 2, -7),
+    /// };
+    ///
+    /// let normalized = area.normalized();
+    /// assert_eq!(normalized.from, SpatialCoordinate::new(1, 0, -7));
+    /// assert_eq!(normalized.to, SpatialCoordinate::new(5, 2, -3));
+    /// ```
+    #[allow(dead_code)]
+    pub fn normalized(&self) -> Area {
+        Area {
+            from: self.from.min(self.to),
+            to: self.from.max(self.to),
+        }
+    }
+
+    /// Returns the number of nodes within the area, counting both corners (as `contains` does).
+    ///
+    /// Corners given in either order span the same area.
+    ///
+    /// # Returns
+    /// - The number of nodes, or None if it doesn't fit in a `u64` (as for an area spanning most of
+    ///   the coordinate range)
+    ///
+    /// # Example
+    /// ```rust
+    /// use minecraft_world::types::{Area, Coordinate, SpatialCoordinate};
+    ///
+    /// let area = Area {
+    ///     from: SpatialCoordinate::zero(),
+    ///     to: SpatialCoordinate::new(10, 10, 10),
+    /// };
+    ///
+    /// assert_eq!(area.volume(), Some(1331));
+    /// assert_eq!(Area::zero().volume(), Some(1));
+    /// ```
+    #[allow(dead_code)]
+    pub fn volume(&self) -> Option<u64> {
+        let area = self.normalized();
+        let length = |from: SpatialCoordinateScalar, to: SpatialCoordinateScalar| {
+            i64::from(to).abs_diff(i64::from(from)).checked_add(1)
+        };
+        length(area.from.x, area.to.x)?
+            .checked_mul(length(area.from.y, area.to.y)?)?
+            .checked_mul(length(area.from.z, area.to.z)?)
+    }
+
+    /// Returns how many MapBlocks (16x16x16 nodes) the area touches along each axis, as `(x, y, z)`.
+    ///
+    /// Partially covered blocks count, so an area of a single node spans one block on each axis.
     ///
     /// # Example
     /// ```rust
-    /// use minecraft_world::types::{Area, Coordinate};
+    /// use minecraft_world::types::{Area, SpatialCoordinate};
     ///
     /// let area = Area {
-    ///    from: Coordinate::zero(),
-    ///   to: Coordinate { x: 10, y: 10, z: 10 }
+    ///     from: SpatialCoordinate::new(-16, 0, 15),
+    ///     to: SpatialCoordinate::new(15, 3, 16),
     /// };
     ///
-    /// assert_eq!(area.volume(), 1000);
+    /// assert_eq!(area.block_dimensions(), (2, 1, 2));
     /// ```
     #[allow(dead_code)]
-    pub fn volume(&self) -> i32 {
-        (self.to.x - self.from.x) * (self.to.y - self.from.y) * (self.to.z - self.from.z)
+    pub fn block_dimensions(&self) -> (i64, i64, i64) {
+        let blocks = |from: SpatialCoordinateScalar, to: SpatialCoordinateScalar| {
+            i64::from(to).div_euclid(16) - i64::from(from).div_euclid(16) + 1
+        };
+        (
+            blocks(self.from.x, self.to.x),
+            blocks(self.from.y, self.to.y),
+            blocks(self.from.z, self.to.z),
+        )
+    }
+
+    /// Returns the area both areas cover, or None if they don't overlap.
+    ///
+    /// Areas that merely touch share the nodes along the touching face, so they overlap.
+    ///
+    /// # Example
+    /// ```rust
+    /// use minecraft_world::types::{Area, SpatialCoordinate};
+    ///
+    /// let a = Area {
+    ///     from: SpatialCoordinate::new(0, 0, 0),
+    ///     to: SpatialCoordinate::new(9, 9, 9),
+    /// };
+    /// let b = Area {
+    ///     from: SpatialCoordinate::new(15, 15, 15),
+    ///     to: SpatialCoordinate::new(5, 5, 5),
+    /// };
+    ///
+    /// let overlap = a.intersection(&b).unwrap();
+    /// assert_eq!(overlap.from, SpatialCoordinate::new(5, 5, 5));
+    /// assert_eq!(overlap.to, SpatialCoordinate::new(9, 9, 9));
+    /// ```
+    #[allow(dead_code)]
+    pub fn intersection(&self, other: &Area) -> Option<Area> {
+        let (a, b) = (self.normalized(), other.normalized());
+        let area = Area {
+            from: a.from.max(b.from),
+            to: a.to.min(b.to),
+        };
+        (area.from.x <= area.to.x && area.from.y <= area.to.y && area.from.z <= area.to.z)
+            .then_some(area)
+    }
+
+    /// Returns the smallest area covering both areas.
+    ///
+    /// The result can cover nodes neither area does, when they don't line up.
+    ///
+    /// # Example
+    /// ```rust
+    /// use minecraft_world::types::{Area, SpatialCoordinate};
+    ///
+    /// let a = Area {
+    ///     from: SpatialCoordinate::new(0, 0, 0),
+    ///     to: SpatialCoordinate::new(1, 1, 1),
+    /// };
+    /// let b = Area {
+    ///     from: SpatialCoordinate::new(10, -4, 1),
+    ///     to: SpatialCoordinate::new(8, -2, 0),
+    /// };
+    ///
+    /// let union = a.bounding_union(&b);
+    /// assert_eq!(union.from, SpatialCoordinate::new(0, -4, 0));
+    /// assert_eq!(union.to, SpatialCoordinate::new(10, 1, 1));
+    /// ```
+    #[allow(dead_code)]
+    pub fn bounding_union(&self, other: &Area) -> Area {
+        let (a, b) = (self.normalized(), other.normalized());
+        Area {
+            from: a.from.min(b.from),
+            to: a.to.max(b.to),
+        }
+    }
+
+    /// Iterates over every coordinate within the area, counting both corners (as `contains` does).
+    ///
+    /// Coordinates are yielded with x changing fastest, then y, then z. Corners given in either
+    /// order span the same area.
+    ///
+    /// # Example
+    /// ```rust
+    /// use minecraft_world::types::{Area, SpatialCoordinate};
+    ///
+    /// let area = Area {
+    ///     from: SpatialCoordinate::new(1, 0, 0),
+    ///     to: SpatialCoordinate::new(0, 0, 1),
+    /// };
+    ///
+    /// assert_eq!(area.iter().count(), 4);
+    /// ```
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = SpatialCoordinate> {
+        let Area { from, to } = self.normalized();
+        (from.z..=to.z).flat_map(move |z| {
+            (from.y..=to.y)
+                .flat_map(move |y| (from.x..=to.x).map(move |x| SpatialCoordinate::new(x, y, z)))
+        })
     }
 }
 
@@ -257,3 +712,509 @@ impl Display for Area {
         write!(f, "({}) -> ({})", self.from, self.to)
     }
 }
+
+#[cfg(test)]
+mod area_tests {
+    use super::*;
+
+    const ORIGIN: SpatialCoordinate = SpatialCoordinate {
+        x: 100,
+        y: -50,
+        z: 16,
+    };
+    const SIZE: SpatialCoordinate = SpatialCoordinate {
+        x: 16,
+        y: 16,
+        z: 16,
+    };
+
+    /// A corner of an area, as `(x, y, z)`
+    type Corner = (
+        SpatialCoordinateScalar,
+        SpatialCoordinateScalar,
+        SpatialCoordinateScalar,
+    );
+
+    /// The area between two corners
+    fn area(from: Corner, to: Corner) -> Area {
+        Area {
+            from: SpatialCoordinate::new(from.0, from.1, from.2),
+            to: SpatialCoordinate::new(to.0, to.1, to.2),
+        }
+    }
+
+    #[test]
+    fn in_frame_relative_to_world() {
+        let area = Area {
+            from: SpatialCoordinate::zero(),
+            to: SpatialCoordinate { x: 15, y: 3, z: 1 },
+        };
+        let world = area
+            .in_frame(
+                CoordinateFrame::Relative,
+                CoordinateFrame::World,
+                ORIGIN,
+                SIZE,
+            )
+            .unwrap();
+        assert_eq!(world.from, ORIGIN);
+        assert_eq!(
+            world.to,
+            SpatialCoordinate {
+                x: 115,
+                y: -47,
+                z: 17
+            }
+        );
+
+        // And back again
+        let relative = world
+            .in_frame(
+                CoordinateFrame::World,
+                CoordinateFrame::Relative,
+                ORIGIN,
+                SIZE,
+            )
+            .unwrap();
+        assert_eq!(relative.from, area.from);
+        assert_eq!(relative.to, area.to);
+    }
+
+    #[test]
+    fn in_frame_index() {
+        let area = Area {
+            from: SpatialCoordinate { x: -1, y: 0, z: 2 },
+            to: SpatialCoordinate { x: 0, y: 0, z: 2 },
+        };
+        let world = area
+            .in_frame(CoordinateFrame::Index, CoordinateFrame::World, ORIGIN, SIZE)
+            .unwrap();
+        assert_eq!(
+            world.from,
+            SpatialCoordinate {
+                x: -16,
+                y: 0,
+                z: 32
+            }
+        );
+        assert_eq!(
+            world.to,
+            SpatialCoordinate {
+                x: 15,
+                y: 15,
+                z: 47
+            }
+        );
+
+        assert_eq!(
+            world
+                .in_frame(CoordinateFrame::World, CoordinateFrame::Index, ORIGIN, SIZE)
+                .unwrap_err(),
+            CoordinateError::InvalidFrame
+        );
+        assert_eq!(
+            area.in_frame(
+                CoordinateFrame::Index,
+                CoordinateFrame::World,
+                ORIGIN,
+                SpatialCoordinate::zero()
+            )
+            .unwrap_err(),
+            CoordinateError::InvalidFrame
+        );
+    }
+
+    #[test]
+    fn block_dimensions() {
+        // Exactly two blocks along x, part of one along y, and one node of each of two blocks along z
+        let area = Area {
+            from: SpatialCoordinate { x: 0, y: 2, z: 15 },
+            to: SpatialCoordinate { x: 31, y: 9, z: 16 },
+        };
+        assert_eq!(area.block_dimensions(), (2, 1, 2));
+        assert_eq!(Area::zero().block_dimensions(), (1, 1, 1));
+
+        // Negative corners round down to their block
+        let area = Area {
+            from: SpatialCoordinate {
+                x: -17,
+                y: -16,
+                z: -1,
+            },
+            to: SpatialCoordinate { x: -1, y: 0, z: 0 },
+        };
+        assert_eq!(area.block_dimensions(), (2, 2, 2));
+    }
+
+    #[test]
+    fn volume() {
+        // A single node, with both corners on it
+        let point = SpatialCoordinate::new(-7, 300, 12);
+        let area = Area {
+            from: point,
+            to: point,
+        };
+        assert_eq!(area.volume(), Some(1));
+
+        let area = Area {
+            from: SpatialCoordinate::new(0, -1, 5),
+            to: SpatialCoordinate::new(3, 1, 5),
+        };
+        assert_eq!(area.volume(), Some(4 * 3));
+        assert_eq!(area.volume(), Some(area.iter().count() as u64));
+
+        // Swapped corners span the same area, rather than a negative one
+        let swapped = Area {
+            from: SpatialCoordinate::new(3, 1, 5),
+            to: SpatialCoordinate::new(0, -1, 5),
+        };
+        assert_eq!(swapped.volume(), Some(4 * 3));
+
+        // The whole coordinate range holds more nodes than a u64 can count
+        let full = Area {
+            from: SpatialCoordinate::new(
+                SpatialCoordinateScalar::MIN,
+                SpatialCoordinateScalar::MIN,
+                SpatialCoordinateScalar::MIN,
+            ),
+            to: SpatialCoordinate::new(
+                SpatialCoordinateScalar::MAX,
+                SpatialCoordinateScalar::MAX,
+                SpatialCoordinateScalar::MAX,
+            ),
+        };
+        assert_eq!(full.volume(), None);
+        // Full range on a single axis
+        let line = Area {
+            from: SpatialCoordinate::new(SpatialCoordinateScalar::MIN, 0, 0),
+            to: SpatialCoordinate::new(SpatialCoordinateScalar::MAX, 0, 0),
+        };
+        assert_eq!(
+            line.volume(),
+            u64::try_from(i128::from(SpatialCoordinateScalar::MAX) * 2 + 2).ok()
+        );
+    }
+
+    #[test]
+    fn intersection() {
+        let a = area((0, 0, 0), (9, 9, 9));
+
+        // Overlapping, with the other's corners given high to low
+        let overlap = a.intersection(&area((12, 4, 9), (5, -3, 2))).unwrap();
+        assert_eq!(overlap.from, SpatialCoordinate::new(5, 0, 2));
+        assert_eq!(overlap.to, SpatialCoordinate::new(9, 4, 9));
+        let contained = a.intersection(&area((2, 2, 2), (3, 3, 3))).unwrap();
+        assert_eq!(contained.from, SpatialCoordinate::new(2, 2, 2));
+        assert_eq!(contained.to, SpatialCoordinate::new(3, 3, 3));
+
+        // Touching along the x = 9 face
+        let touching = a.intersection(&area((9, 0, 0), (20, 9, 9))).unwrap();
+        assert_eq!(touching.from, SpatialCoordinate::new(9, 0, 0));
+        assert_eq!(touching.to, SpatialCoordinate::new(9, 9, 9));
+        assert_eq!(touching.volume(), Some(100));
+
+        // Adjacent without sharing a node, and apart on a single axis
+        assert!(a.intersection(&area((10, 0, 0), (20, 9, 9))).is_none());
+        assert!(a.intersection(&area((0, 0, -1), (9, 9, -5))).is_none());
+    }
+
+    #[test]
+    fn bounding_union() {
+        let a = area((0, 0, 0), (9, 9, 9));
+
+        // Overlapping
+        let union = a.bounding_union(&area((12, 4, 9), (5, -3, 2)));
+        assert_eq!(union.from, SpatialCoordinate::new(0, -3, 0));
+        assert_eq!(union.to, SpatialCoordinate::new(12, 9, 9));
+
+        // Touching
+        let union = a.bounding_union(&area((9, 0, 0), (20, 9, 9)));
+        assert_eq!(union.from, SpatialCoordinate::new(0, 0, 0));
+        assert_eq!(union.to, SpatialCoordinate::new(20, 9, 9));
+        assert_eq!(
+            union.volume().unwrap(),
+            a.volume().unwrap() + area((10, 0, 0), (20, 9, 9)).volume().unwrap()
+        );
+
+        // Disjoint, covering the gap between them too
+        let b = area((-5, 20, -5), (-1, 30, -1));
+        let union = a.bounding_union(&b);
+        assert_eq!(union.from, SpatialCoordinate::new(-5, 0, -5));
+        assert_eq!(union.to, SpatialCoordinate::new(9, 30, 9));
+        assert!(union.contains(SpatialCoordinate::new(0, 15, 0)));
+        assert_eq!(b.bounding_union(&a).from, union.from);
+        assert_eq!(b.bounding_union(&a).to, union.to);
+    }
+
+    #[test]
+    fn iter() {
+        let area = Area {
+            from: SpatialCoordinate::new(4, -1, 7),
+            to: SpatialCoordinate::new(5, 0, 8),
+        };
+        let expected: Vec<SpatialCoordinate> = [
+            (4, -1, 7),
+            (5, -1, 7),
+            (4, 0, 7),
+            (5, 0, 7),
+            (4, -1, 8),
+            (5, -1, 8),
+            (4, 0, 8),
+            (5, 0, 8),
+        ]
+        .into_iter()
+        .map(|(x, y, z)| SpatialCoordinate::new(x, y, z))
+        .collect();
+        assert_eq!(area.iter().collect::<Vec<_>>(), expected);
+        assert!(area.iter().all(|coord| area.contains(coord)));
+
+        // Swapped corners span the same area, in the same order
+        let swapped = Area {
+            from: SpatialCoordinate::new(5, -1, 8),
+            to: SpatialCoordinate::new(4, 0, 7),
+        };
+        assert_eq!(swapped.iter().collect::<Vec<_>>(), expected);
+
+        assert_eq!(
+            Area::zero().iter().collect::<Vec<_>>(),
+            vec![SpatialCoordinate::zero()]
+        );
+    }
+}
+
+#[cfg(test)]
+mod spatial_coordinate_tests {
+    use super::*;
+
+    #[test]
+    fn format_node() {
+        let coord = SpatialCoordinate {
+            x: -1,
+            y: 17,
+            z: 32,
+        };
+        assert_eq!(coord.format_node(), "(-1,17,32)");
+        assert_eq!(coord.to_string(), "(-1, 17, 32)");
+    }
+
+    #[test]
+    fn format_block() {
+        let coord = SpatialCoordinate {
+            x: -1,
+            y: 17,
+            z: 32,
+        };
+        assert_eq!(coord.format_block(), "(-1,1,2)");
+        assert_eq!(SpatialCoordinate::zero().format_block(), "(0,0,0)");
+    }
+
+    #[test]
+    fn same_block() {
+        let node = |x, y, z| SpatialCoordinate { x, y, z };
+        assert!(node(0, 0, 0).same_block(&node(15, 15, 15)));
+        assert!(node(17, -3, 40).same_block(&node(31, -16, 47)));
+
+        // Adjacent blocks
+        assert!(!node(15, 0, 0).same_block(&node(16, 0, 0)));
+        assert!(!node(0, 15, 0).same_block(&node(0, 16, 0)));
+        assert!(!node(0, 0, 31).same_block(&node(0, 0, 32)));
+
+        // Either side of zero, and within the first negative block
+        assert!(!node(-1, 0, 0).same_block(&node(0, 0, 0)));
+        assert!(node(-1, 0, 0).same_block(&node(-16, 0, 0)));
+        assert!(!node(-16, 0, 0).same_block(&node(-17, 0, 0)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let coord = SpatialCoordinate::new(-1, 17, 32);
+        let json = serde_json::to_string(&coord).unwrap();
+        assert_eq!(json, r#"{"x":-1,"y":17,"z":32}"#);
+        assert_eq!(
+            serde_json::from_str::<SpatialCoordinate>(&json).unwrap(),
+            coord
+        );
+
+        let area = Area {
+            from: coord,
+            to: SpatialCoordinate::new(5, 20, 40),
+        };
+        let json = serde_json::to_string(&area).unwrap();
+        assert_eq!(
+            json,
+            r#"{"from":{"x":-1,"y":17,"z":32},"to":{"x":5,"y":20,"z":40}}"#
+        );
+        let parsed: Area = serde_json::from_str(&json).unwrap();
+        assert_eq!((parsed.from, parsed.to), (area.from, area.to));
+
+        for frame in [
+            CoordinateFrame::World,
+            CoordinateFrame::Relative,
+            CoordinateFrame::Index,
+        ] {
+            let json = serde_json::to_string(&frame).unwrap();
+            assert_eq!(
+                serde_json::from_str::<CoordinateFrame>(&json).unwrap(),
+                frame
+            );
+        }
+        for error in [
+            CoordinateError::OutOfBounds,
+            CoordinateError::InvalidFrame,
+            CoordinateError::InvalidFormat,
+        ] {
+            let json = serde_json::to_string(&error).unwrap();
+            assert_eq!(
+                serde_json::from_str::<CoordinateError>(&json).unwrap(),
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let coord = SpatialCoordinate::new(-1, 17, 32);
+        assert_eq!(<SpatialCoordinate as From<_>>::from((-1, 17, 32)), coord);
+        assert_eq!(<SpatialCoordinate as From<_>>::from([-1, 17, 32]), coord);
+        assert_eq!(<(_, _, _)>::from(coord), (-1, 17, 32));
+        assert_eq!(<[_; 3]>::from(coord), [-1, 17, 32]);
+
+        // Components past the range of i32 survive with big coordinates
+        #[cfg(feature = "big_coordinates")]
+        {
+            let far: SpatialCoordinate = [1 << 40, -(1 << 40), i64::MAX].into();
+            assert_eq!(far.x, 1 << 40);
+            let (x, y, z) = far.into();
+            assert_eq!((x, y, z), (1 << 40, -(1 << 40), i64::MAX));
+        }
+    }
+
+    #[test]
+    fn block_coord() {
+        let node = |x, y, z| SpatialCoordinate { x, y, z };
+        assert_eq!(node(0, 0, 0).to_block_coord(), node(0, 0, 0));
+        assert_eq!(node(0, 0, 0).to_node_offset(), (0, 0, 0));
+        assert_eq!(node(15, 15, 15).to_block_coord(), node(0, 0, 0));
+        assert_eq!(node(15, 15, 15).to_node_offset(), (15, 15, 15));
+        assert_eq!(node(16, 16, 16).to_block_coord(), node(1, 1, 1));
+        assert_eq!(node(16, 16, 16).to_node_offset(), (0, 0, 0));
+
+        // Below zero, nodes count up from the low side of their block
+        assert_eq!(node(-1, -1, -1).to_block_coord(), node(-1, -1, -1));
+        assert_eq!(node(-1, -1, -1).to_node_offset(), (15, 15, 15));
+        assert_eq!(node(-16, -16, -16).to_block_coord(), node(-1, -1, -1));
+        assert_eq!(node(-16, -16, -16).to_node_offset(), (0, 0, 0));
+        assert_eq!(node(-17, 17, -33).to_block_coord(), node(-2, 1, -3));
+        assert_eq!(node(-17, 17, -33).to_node_offset(), (15, 1, 15));
+
+        // The block and offset add back up to the node
+        let coord = node(-300, 47, 12345);
+        let (x, y, z) = coord.to_node_offset();
+        assert_eq!(
+            coord.to_block_coord() * 16 + node(x.into(), y.into(), z.into()),
+            coord
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let coord = SpatialCoordinate::new(-3, 0, 7);
+        assert_eq!(coord * 16, SpatialCoordinate::new(-48, 0, 112));
+        assert_eq!(coord * -1, SpatialCoordinate::new(3, 0, -7));
+
+        // Division rounds down, even for negative components or divisors
+        assert_eq!(
+            SpatialCoordinate::new(-3, 0, 0) / 2,
+            SpatialCoordinate::new(-2, 0, 0)
+        );
+        assert_eq!(coord / 2, SpatialCoordinate::new(-2, 0, 3));
+        assert_eq!(coord / -2, SpatialCoordinate::new(1, 0, -4));
+        assert_eq!(
+            SpatialCoordinate::new(-16, -17, 15) / 16,
+            SpatialCoordinate::new(-1, -2, 0)
+        );
+        assert_eq!((coord * 5) / 5, coord);
+    }
+
+    #[test]
+    fn min_max() {
+        let a = SpatialCoordinate::new(1, -5, 3);
+        let b = SpatialCoordinate::new(2, 0, -4);
+        assert_eq!(a.min(b), SpatialCoordinate::new(1, -5, -4));
+        assert_eq!(a.max(b), SpatialCoordinate::new(2, 0, 3));
+        assert_eq!(b.min(a), a.min(b));
+        assert_eq!(a.min(a), a);
+    }
+}
+
+#[cfg(test)]
+mod shape_tests {
+    use super::*;
+
+    /// True if every coordinate is a neighbour (including diagonally) of the previous one
+    fn contiguous(points: &[SpatialCoordinate]) -> bool {
+        points.windows(2).all(|pair| {
+            let step = pair[1] - pair[0];
+            step != SpatialCoordinate::zero()
+                && step.x.abs() <= 1
+                && step.y.abs() <= 1
+                && step.z.abs() <= 1
+        })
+    }
+
+    #[test]
+    fn line_axis_aligned() {
+        let from = SpatialCoordinate { x: 0, y: 5, z: 0 };
+        let to = SpatialCoordinate { x: 0, y: -5, z: 0 };
+        let points = line(from, to);
+        assert_eq!(points.len(), 11);
+        assert_eq!(points[0], from);
+        assert_eq!(points[10], to);
+        assert!(points.iter().all(|p| p.x == 0 && p.z == 0));
+        assert!(contiguous(&points));
+    }
+
+    #[test]
+    fn line_diagonal() {
+        let from = SpatialCoordinate { x: -3, y: 1, z: 7 };
+        let to = SpatialCoordinate { x: 9, y: -4, z: 2 };
+        let points = line(from, to);
+        assert_eq!(points.len(), 13);
+        assert_eq!(points[0], from);
+        assert_eq!(*points.last().unwrap(), to);
+        assert!(contiguous(&points));
+    }
+
+    #[test]
+    fn sphere_solid() {
+        let center = SpatialCoordinate { x: 10, y: -4, z: 3 };
+        let points = sphere(center, 1, false);
+        assert_eq!(points.len(), 7);
+        assert!(points.contains(&center));
+        assert!(points.contains(&(center + SpatialCoordinate::up())));
+        assert!(points.contains(&(center + SpatialCoordinate::back())));
+        assert_eq!(sphere(center, 0, false), vec![center]);
+    }
+
+    #[test]
+    fn sphere_hollow() {
+        let center = SpatialCoordinate { x: 0, y: 0, z: 0 };
+        let shell = sphere(center, 2, true);
+        // 12 at distance² 2, 8 at distance² 3, 6 at distance² 4
+        assert_eq!(shell.len(), 26);
+        for point in shell.iter() {
+            let distance = point.x * point.x + point.y * point.y + point.z * point.z;
+            assert!(distance > 1 && distance <= 4);
+        }
+        assert!(!shell.contains(&center));
+        assert!(!shell.contains(&SpatialCoordinate::up()));
+        assert_eq!(sphere(center, 2, false).len(), shell.len() + 7);
+    }
+
+    #[test]
+    fn line_single_point() {
+        let point = SpatialCoordinate { x: 1, y: 2, z: 3 };
+        assert_eq!(line(point, point), vec![point]);
+    }
+}
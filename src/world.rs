@@ -11,6 +11,8 @@ pub enum WorldError {
     OutOfBounds(SpatialCoordinate),
     PartitionNotFound(SpatialCoordinate),
     CorruptData(String),
+    /// A partition's stored data could not be decoded
+    CorruptPartition(SpatialCoordinate, String),
     DatabaseError(String),
     UnknownError(String),
 }
@@ -21,6 +23,13 @@ pub trait Block: PartialEq + Eq {
     // TODO: Abstracted properties for blocks (This could be sourced from NBT or similar)
 }
 
+/// A World Registry maps numeric ids to the kinds of blocks a world can contain.
+pub trait WorldRegistry<B: Block> {
+    /// Creates a block of the kind registered under `id`, or `WorldError::IdNotFound` if there is none.
+    fn create_block(&self, id: i32) -> Result<B, WorldError>;
+    fn all_blocks(&self) -> Vec<B>;
+}
+
 /// A World Partition is a section of the world that can be loaded and unloaded as needed.
 /// These may contain blocks, or may contain further partitions.
 pub trait WorldPartition<T, B: Block> {
@@ -36,10 +45,10 @@ pub trait WorldPartition<T, B: Block> {
     fn child_at_pos(&self, coord: SpatialCoordinate) -> Result<&T, ()>;
     fn child_at_pos_mut(&mut self, coord: SpatialCoordinate) -> Result<&mut T, ()>;
 
-    fn blocks(&self) -> dyn Iterator<Item = &B>;
-    fn blocks_mut(&mut self) -> dyn Iterator<Item = &mut B>;
-    fn children(&self) -> dyn Iterator<Item = &T>;
-    fn children_mut(&mut self) -> dyn Iterator<Item = &mut T>;
+    fn blocks(&self) -> Box<dyn Iterator<Item = &B> + '_>;
+    fn blocks_mut(&mut self) -> Box<dyn Iterator<Item = &mut B> + '_>;
+    fn children(&self) -> Box<dyn Iterator<Item = &T> + '_>;
+    fn children_mut(&mut self) -> Box<dyn Iterator<Item = &mut T> + '_>;
 }
 
 /// A World is a collection of blocks - either directly, or through partitions.
@@ -49,6 +58,11 @@ pub trait WorldReader<C: Coordinate, B: Block, P> {
 
     fn max_area(&self) -> Area;
 
+    /// Returns true if the coordinate is within the world's representable area.
+    fn contains_position(&self, coord: SpatialCoordinate) -> bool {
+        self.max_area().contains(coord)
+    }
+
     /// Returns the lowest possible z-coordinate of the world.
     fn bottom(&self) -> C::Scalar;
 
@@ -56,11 +70,12 @@ pub trait WorldReader<C: Coordinate, B: Block, P> {
     fn top(&self) -> C::Scalar;
 
     fn node_at_pos(&self, coord: SpatialCoordinate) -> Result<&B, ()>;
-    fn partition_at_pos(&self, coord: SpatialCoordinate) -> Result<&P, ()>;
+    /// Returns the partition containing the coordinate, or `WorldError::PartitionNotFound` if there is none.
+    fn partition_at_pos(&self, coord: SpatialCoordinate) -> Result<&P, WorldError>;
 
     fn volume(&self) -> i64;
 
-    fn partitions(&self) -> dyn Iterator<Item = &P>;
+    fn partitions(&self) -> Box<dyn Iterator<Item = &P> + '_>;
     fn new_block(&self, id: i32) -> Result<B, WorldError>;
 }
 
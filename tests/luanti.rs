@@ -10,3 +10,78 @@ fn basic_open() {
     // Check that mod "worldedit_gui" is false
     assert_eq!(world.mods().contains(&"worldedit_gui".to_string()), false);
 }
+
+#[test]
+fn read_map_block() {
+    use minecraft_world::backend::luanti::map::{HashedCoordinate, MapReader, SQLite3MapReader};
+
+    let map = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+    let coord = HashedCoordinate { value: 335560685 };
+    assert!(map.block_exists(coord).unwrap());
+    let data = map.get_block(coord).unwrap();
+    // Serialization version
+    assert_eq!(data[0], 29);
+    assert!(map.blocks().unwrap().contains(&coord));
+}
+
+#[test]
+fn read_map_node() {
+    use minecraft_world::backend::luanti::map::{HashedCoordinate, SQLite3MapReader};
+    use minecraft_world::backend::luanti::map_high::LuantiMap;
+    use minecraft_world::SpatialCoordinate;
+
+    let reader = SQLite3MapReader::open_file("assets/world_luanti_5.10/map.sqlite").unwrap();
+    let map = LuantiMap::new(Box::new(reader));
+    let block = map
+        .get_mapblock(HashedCoordinate { value: 335560685 })
+        .unwrap();
+    assert!(block.was_generated());
+    // Block (-19, 4, 20), node (3, 2, 12) within it
+    let (name, _, _) = map
+        .node_at(SpatialCoordinate::new(
+            -19 * 16 + 3,
+            4 * 16 + 2,
+            20 * 16 + 12,
+        ))
+        .unwrap();
+    assert_eq!(name, "flowers:viola");
+}
+
+/// Runs against the database in the `MINETEST_TEST_PG` connection string, if it is set
+#[cfg(feature = "postgres")]
+#[test]
+fn postgres_map() {
+    use minecraft_world::backend::luanti::map::{
+        HashedCoordinate, MapReader, MapWriter, PostgresMapReader,
+    };
+
+    let Ok(params) = std::env::var("MINETEST_TEST_PG") else {
+        return;
+    };
+    let map = PostgresMapReader::connect(&params).unwrap();
+    map.create_schema().unwrap();
+
+    // Out of the way of any real blocks
+    let coord = HashedCoordinate::at(-2048, -2048, -2048).unwrap();
+    map.set_block(coord, &vec![29, 1, 2, 3]).unwrap();
+    assert!(map.block_exists(coord).unwrap());
+    assert_eq!(map.get_block(coord).unwrap(), vec![29, 1, 2, 3]);
+    assert!(map.blocks().unwrap().contains(&coord));
+
+    map.set_block(coord, &vec![29, 4]).unwrap();
+    assert_eq!(map.get_block(coord).unwrap(), vec![29, 4]);
+
+    map.remove_block(coord).unwrap();
+    assert!(!map.block_exists(coord).unwrap());
+    assert!(map.get_block(coord).is_err());
+}
+
+#[test]
+fn read_auth_txt() {
+    use minecraft_world::auth::{AuthBackend, User};
+    use minecraft_world::backend::luanti::auth::AuthTxtBackend;
+
+    let auth = AuthTxtBackend::from_reader("singleplayer::interact,shout\n".as_bytes()).unwrap();
+    let names: Vec<String> = auth.users().iter().map(User::name).collect();
+    assert_eq!(names, ["singleplayer"]);
+}